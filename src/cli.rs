@@ -3,15 +3,28 @@
 //! 此模块基于 `clap` 包实现了命令行的参数设置，标准输出以及文件读写。
 //!
 
-use chai::config::Config;
+use chai::config::{Config, ConstraintsConfig};
+use chai::error::Error;
 use chai::interface::Interface;
+use chai::metaheuristics::simulated_annealing::Schedule;
 use chai::objectives::metric::Metric;
-use chai::representation::{Assets, EncodeExport, Entry, RawSequenceMap, WordList};
+use chai::representation::{
+    Assets, CodeEntry, EncodeExport, Entry, Identifiers, MoveRecord, Pronunciation, RawSequenceMap,
+    ShortCodeAssignment, SimulationExport, TrajectoryEntry, Variants, WordList,
+};
 use chrono::Local;
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use csv::{Reader, ReaderBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::iter::zip;
+use std::sync::Mutex;
+use std::time::Duration;
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -52,42 +65,323 @@ pub struct Cli {
     /// 双键速度当量表，默认为 assets 目录下的 pair_equivalence.txt
     #[arg(short, long, value_name = "FILE")]
     pub pair_equivalence: Option<PathBuf>,
+
+    /// 三键当量表，默认为 assets 目录下的 triple_equivalence.txt，这个文件不存在时不使用三键当量
+    #[arg(long, value_name = "FILE")]
+    pub triple_equivalence: Option<PathBuf>,
+
+    /// 标点符号频率表，默认为 assets 目录下的 punctuation_frequency.txt，这个文件不存在时不使用标点符号
+    #[arg(long, value_name = "FILE")]
+    pub punctuation_frequency: Option<PathBuf>,
+
+    /// 键盘几何文件，默认为 assets 目录下的 keyboard_geometry.txt，这个文件不存在时不使用键盘几何
+    #[arg(long, value_name = "FILE")]
+    pub keyboard_geometry: Option<PathBuf>,
+
+    /// 繁体字频表，默认为 assets 目录下的 traditional_frequency.txt，这个文件不存在时不评测繁体
+    #[arg(long, value_name = "FILE")]
+    pub traditional_frequency: Option<PathBuf>,
+
+    /// 简繁对照表，默认为 assets 目录下的 variants.txt，这个文件不存在时不使用
+    #[arg(long, value_name = "FILE")]
+    pub variants: Option<PathBuf>,
+
+    /// 末笔字型表，默认为 assets 目录下的 identifiers.txt，这个文件不存在时不使用
+    #[arg(long, value_name = "FILE")]
+    pub identifiers: Option<PathBuf>,
+
+    /// 只编码和评测这个字集中的字，字集文件为 assets/character_sets 目录下的「字集名称.txt」
+    #[arg(long, value_name = "NAME")]
+    pub character_set: Option<String>,
+
+    /// 音形方案的读音表，默认为 assets 目录下的 pronunciation.txt，这个文件不存在时不使用读音
+    #[arg(long, value_name = "FILE")]
+    pub pronunciation: Option<PathBuf>,
+
+    /// 用于模拟动态调频的语料文本，默认为 assets 目录下的 corpus.txt，这个文件不存在时不使用语料
+    #[arg(long, value_name = "FILE")]
+    pub corpus: Option<PathBuf>,
+
+    /// 标准输出的格式，为 json 时评测指标和优化进度都以每行一个 JSON 对象的形式输出，便于脚本读取
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+
+    /// 输出更多的信息，-v 输出每一步接受的方案，-vv 还输出每一步拒绝的方案
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// 只输出命令的结果，不输出优化过程中的进度等信息
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// 正在写入的优化轨迹文件，在第一次记录时创建
+    #[arg(skip)]
+    trajectory: Mutex<Option<BufWriter<File>>>,
+
+    /// 退火过程中的进度条，在第一次汇报进度时创建，优化结束时清除
+    #[arg(skip)]
+    progress: Mutex<Option<ProgressBar>>,
+}
+
+/// 命令结果的日志目标。安静模式下只输出这一目标的消息，以及警告和错误
+const RESULT: &str = "result";
+
+/// 命令行的日志：消息直接写到标准输出，不加级别、时间等前缀
+struct Logger {
+    quiet: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let chatter = metadata.level() >= Level::Info && metadata.target() != RESULT;
+        metadata.level() <= log::max_level() && !(self.quiet && chatter)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+/// 标准输出的格式
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// 供人阅读的中文文本
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象，用 event 字段区分输出的内容
+    Json,
 }
 
 /// 命令行中所有可用的子命令
 #[derive(Subcommand, Clone)]
 pub enum Command {
     /// 使用方案文件和拆分表计算出字词编码并统计各类评测指标
-    Encode,
+    Encode {
+        /// 额外输出以编码为主键的反查表 codes.txt，每个编码后按候选顺序列出所有的字词
+        #[arg(long)]
+        reverse: bool,
+    },
     /// 评测当前方案的各项指标
     Evaluate,
     /// 基于拆分表和方案文件中的配置优化元素布局
-    Optimize,
+    Optimize(OptimizeArgs),
+    /// 从保存的检查点继续优化，需要使用与中断前相同的方案文件和拆分表
+    Resume {
+        /// 检查点文件，默认为优化时自动保存的 output/checkpoint.bin
+        #[arg(default_value = "output/checkpoint.bin")]
+        checkpoint: PathBuf,
+    },
+    /// 在方案文件的布局上重新执行一条优化轨迹，需要使用与记录时相同的方案文件和拆分表
+    Replay {
+        /// 优化时用 --record 记录的轨迹文件
+        trajectory: PathBuf,
+    },
+    /// 按照当前方案输入一个文本文件，输出击键序列并统计字均键数和选重次数
+    Simulate {
+        /// 要输入的文本文件
+        text: PathBuf,
+    },
+    /// 检查方案文件中的约束是否与键盘映射一致，列出所有发现的问题及其在方案文件中的位置
+    Validate,
+    /// 比较两个方案文件：哪些元素换了按键、哪些字词的编码改变了，以及两者的评测指标。两个方案需要使用同一个拆分表
+    Diff {
+        /// 原来的方案文件，它代替全局的方案文件参数，用来读取拆分表以外的所有配置
+        old: PathBuf,
+        /// 新的方案文件，只使用其中的键盘映射
+        new: PathBuf,
+        /// 最多列出多少个编码改变的字词，按频率从高到低排列
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        top: usize,
+    },
+    /// 用方案文件的配置评测一个目录中所有的方案文件（例如多次优化的结果），按目标函数从小到大排名。这些方案的元素集合必须和方案文件完全相同
+    Rank {
+        /// 包含若干个方案文件的目录
+        directory: PathBuf,
+        /// 把排名表另外保存为 CSV 文件
+        #[arg(long, value_name = "FILE")]
+        csv: Option<PathBuf>,
+    },
+    /// 按照当前方案生成输入法可以直接使用的码表
+    Export {
+        /// 码表的格式
+        #[arg(long, value_enum, default_value_t = Target::Rime)]
+        target: Target,
+        /// 码表的名称，默认为方案文件的文件名（不含扩展名）
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+/// 编码改变的字词：字词、原来的编码、新的编码和频率
+type CodeChange = (String, String, String, u64);
+
+/// 导出码表的格式
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Rime 的词典文件「名称.dict.yaml」，可以直接用于小狼毫、鼠须管等
+    Rime,
+    /// 多多输入法的码表「名称.duoduo.txt」，每行为「编码=字词」
+    Duoduo,
+    /// 极点五笔的码表源文件「名称.jidian.txt」，每行为一个编码和它的所有字词，可以用极点的码表工具生成 .mb 文件
+    Jidian,
+    /// 小小输入法的码表「名称.xiaoxiao.yaml」，每个编码对应一个字词的列表
+    Xiaoxiao,
+}
+
+/// 优化命令的参数，它们会覆盖方案文件中的对应配置
+#[derive(Args, Clone)]
+pub struct OptimizeArgs {
+    /// 独立重启优化的次数，只汇报和保存所有重启中最好的方案
+    #[arg(long, value_name = "N")]
+    pub restarts: Option<usize>,
+
+    /// 随机数种子，使用相同的种子和输入文件的两次优化会得到完全相同的结果
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// 优化的时间上限，例如 90s、30min、2h，系统会根据实际运行速度调整步数以在这一时间内完成
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub max_time: Option<Duration>,
+
+    /// 并行运行的退火链数量，各条链定期把全局最好的方案迁移到最差的链上
+    #[arg(long, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// 每条退火链内部编码和累计指标所用的线程数，适合词表很大、单步评测较慢的方案
+    #[arg(long, value_name = "N")]
+    pub encode_threads: Option<usize>,
+
+    /// 包含若干个方案文件的目录（例如以前的优化结果），评测其中所有的方案，从最好的一个开始优化
+    #[arg(long, value_name = "DIR")]
+    pub warm_start: Option<PathBuf>,
+
+    /// 把退火过程中接受的每一步记录到 output 目录下的轨迹文件中，之后可以用 replay 命令重新执行
+    #[arg(long)]
+    pub record: bool,
 }
 
 impl Cli {
+    /// 以 JSON 格式输出时，把这一条输出写成一行 JSON 并返回 true；否则什么也不做，返回 false，由调用者输出文本
+    fn json(&self, value: impl FnOnce() -> Value) -> bool {
+        if self.format != Format::Json {
+            return false;
+        }
+        info!(target: RESULT, "{}", value());
+        true
+    }
+
+    /// 按照 -v 和 -q 设置日志的级别，需要在输出任何内容之前调用
+    pub fn init_logger(&self) {
+        let level = match self.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        };
+        log::set_boxed_logger(Box::new(Logger { quiet: self.quiet })).unwrap();
+        log::set_max_level(level);
+    }
+
+    /// 有进度条时先把它暂时隐藏再输出文本，以免文本和进度条交错在一起
+    fn suspend(&self, print: impl FnOnce()) {
+        match self.progress.lock().unwrap().as_ref() {
+            Some(bar) => bar.suspend(print),
+            None => print(),
+        }
+    }
+
     fn get_reader(path: PathBuf) -> Reader<File> {
-        return ReaderBuilder::new()
+        ReaderBuilder::new()
             .delimiter(b'\t')
             .has_headers(false)
             .from_path(path)
-            .unwrap();
+            .unwrap()
     }
 
-    pub fn prepare_file(&self) -> (Config, RawSequenceMap, WordList, Assets) {
-        let config_path = self.config.clone().unwrap_or(PathBuf::from("config.yaml"));
+    /// 约束预设的名称对应 assets/constraints 目录下的「预设名称.yaml」，以 .yaml 或 .yml 结尾的则是相对于方案文件所在目录的路径
+    fn apply_preset(config: &mut Config, config_path: &Path) -> Result<(), Error> {
+        let constraints = &mut config.optimization.constraints;
+        let Some(name) = constraints.as_ref().and_then(|x| x.preset.clone()) else {
+            return Ok(());
+        };
+        let path = if name.ends_with(".yaml") || name.ends_with(".yml") {
+            let directory = config_path.parent().unwrap_or(Path::new(""));
+            directory.join(&name)
+        } else {
+            Path::new("assets")
+                .join("constraints")
+                .join(format!("{name}.yaml"))
+        };
+        let path_name = path.display();
+        let content = fs::read_to_string(&path)
+            .map_err(|_| format!("约束预设文件 {path_name} 不存在"))?;
+        let preset: ConstraintsConfig = serde_yaml::from_str(&content)
+            .map_err(|e| format!("约束预设文件 {path_name} 格式错误：{e}"))?;
+        if preset.preset.is_some() {
+            return Err(format!("约束预设文件 {path_name} 不能再引用其他预设").into());
+        }
+        *constraints = constraints.take().map(|x| x.merge(preset));
+        Ok(())
+    }
+
+    pub fn prepare_file(&self) -> Result<(Config, RawSequenceMap, WordList, Assets), Error> {
+        let config_path = match &self.command {
+            Command::Diff { old, .. } => old.clone(),
+            _ => self.config.clone().unwrap_or(PathBuf::from("config.yaml")),
+        };
         let config_content = fs::read_to_string(&config_path)
-            .expect(&format!("文件 {} 不存在", config_path.display()));
-        let config: Config = serde_yaml::from_str(&config_content).unwrap();
+            .unwrap_or_else(|_| panic!("文件 {} 不存在", config_path.display()));
+        let mut config: Config = serde_yaml::from_str(&config_content).unwrap();
+        Self::apply_preset(&mut config, &config_path)?;
+        if let Command::Optimize(args) = &self.command {
+            let metaheuristic = &mut config.optimization.metaheuristic;
+            if let Some(restarts) = args.restarts {
+                metaheuristic.restarts = Some(restarts);
+            }
+            if let Some(seed) = args.seed {
+                metaheuristic.seed = Some(seed);
+            }
+            if let Some(max_time) = args.max_time {
+                metaheuristic.max_time = Some(max_time.as_secs());
+            }
+            if let Some(threads) = args.threads {
+                metaheuristic.threads = Some(threads);
+            }
+            if let Some(encode_threads) = args.encode_threads {
+                metaheuristic.encode_threads = Some(encode_threads);
+            }
+            if args.record {
+                metaheuristic.record = Some(true);
+            }
+        }
+
+        if let Some(name) = &self.character_set {
+            config.encoder.character_set = Some(name.clone());
+        }
 
         let elemets_path = self
             .elements
             .clone()
             .unwrap_or(PathBuf::from("elements.txt"));
-        let elements: HashMap<char, String> = Self::get_reader(elemets_path)
-            .deserialize()
+        // 同一个字出现在多行时视为一字多拆，各个拆分之间用 | 连接
+        let mut elements: HashMap<char, String> = HashMap::new();
+        for (char, sequence) in Self::get_reader(elemets_path)
+            .deserialize::<(char, String)>()
             .map(|x| x.unwrap())
-            .collect();
+        {
+            elements
+                .entry(char)
+                .and_modify(|x| {
+                    x.push('|');
+                    x.push_str(&sequence);
+                })
+                .or_insert(sequence);
+        }
 
         // prepare assets
         let assets_dir = Path::new("assets");
@@ -123,7 +417,119 @@ impl Cli {
             .deserialize()
             .map(|x| x.unwrap())
             .collect();
-        let words = if let Some(_) = self.words {
+        let teq_path = self
+            .triple_equivalence
+            .clone()
+            .unwrap_or(assets_dir.join("triple_equivalence.txt"));
+        let triple_equivalence: HashMap<String, f64> =
+            if self.triple_equivalence.is_some() || teq_path.exists() {
+                Self::get_reader(teq_path)
+                    .deserialize()
+                    .map(|x| x.unwrap())
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        let pf_path = self
+            .punctuation_frequency
+            .clone()
+            .unwrap_or(assets_dir.join("punctuation_frequency.txt"));
+        let punctuation_frequency: HashMap<char, u64> =
+            if self.punctuation_frequency.is_some() || pf_path.exists() {
+                Self::get_reader(pf_path)
+                    .deserialize()
+                    .map(|x| x.unwrap())
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        let geometry_path = self
+            .keyboard_geometry
+            .clone()
+            .unwrap_or(assets_dir.join("keyboard_geometry.txt"));
+        let keyboard_geometry: HashMap<char, (f64, f64)> =
+            if self.keyboard_geometry.is_some() || geometry_path.exists() {
+                Self::get_reader(geometry_path)
+                    .deserialize()
+                    .map(|x| x.unwrap())
+                    .map(|(key, x, y): (char, f64, f64)| (key, (x, y)))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        let corpus_path = self
+            .corpus
+            .clone()
+            .unwrap_or(assets_dir.join("corpus.txt"));
+        let corpus = if self.corpus.is_some() || corpus_path.exists() {
+            fs::read_to_string(corpus_path).expect("语料文件无法读取")
+        } else {
+            String::new()
+        };
+        let pronunciation_path = self
+            .pronunciation
+            .clone()
+            .unwrap_or(assets_dir.join("pronunciation.txt"));
+        let mut pronunciation: Pronunciation = HashMap::new();
+        if self.pronunciation.is_some() || pronunciation_path.exists() {
+            // 单字的多个读音可以写在一行中以空格分隔，也可以分成多行；词语的读音是词中每个字的读音
+            let lines = Self::get_reader(pronunciation_path).into_deserialize();
+            for (item, reading) in lines.map(|x: Result<(String, String), _>| x.unwrap()) {
+                let syllables = reading.split_whitespace().map(|x| x.to_string());
+                let entry = pronunciation.entry(item.clone()).or_default();
+                if item.chars().count() == 1 || entry.is_empty() {
+                    entry.extend(syllables);
+                }
+            }
+        }
+        let tf_path = self
+            .traditional_frequency
+            .clone()
+            .unwrap_or(assets_dir.join("traditional_frequency.txt"));
+        let traditional_frequency: HashMap<char, u64> =
+            if self.traditional_frequency.is_some() || tf_path.exists() {
+                Self::get_reader(tf_path)
+                    .deserialize()
+                    .map(|x| x.unwrap())
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        let variants_path = self
+            .variants
+            .clone()
+            .unwrap_or(assets_dir.join("variants.txt"));
+        let mut variants: Variants = HashMap::new();
+        if self.variants.is_some() || variants_path.exists() {
+            // 一个简体字对应多个繁体字时，可以写在一行中以空格分隔，也可以分成多行
+            let lines = Self::get_reader(variants_path).into_deserialize();
+            for (character, traditional) in lines.map(|x: Result<(char, String), _>| x.unwrap()) {
+                let entry = variants.entry(character).or_default();
+                entry.extend(traditional.chars().filter(|x| !x.is_whitespace()));
+            }
+        }
+        let identifiers_path = self
+            .identifiers
+            .clone()
+            .unwrap_or(assets_dir.join("identifiers.txt"));
+        let identifiers: Identifiers =
+            if self.identifiers.is_some() || identifiers_path.exists() {
+                Self::get_reader(identifiers_path)
+                    .deserialize()
+                    .map(|x: Result<(char, u8, u8), _>| x.unwrap())
+                    .map(|(char, stroke, shape)| (char, (stroke, shape)))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+        let mut character_sets = HashMap::new();
+        if let Some(name) = &config.encoder.character_set {
+            let path = assets_dir.join("character_sets").join(format!("{name}.txt"));
+            let content = fs::read_to_string(&path)
+                .unwrap_or_else(|_| panic!("字集文件 {} 不存在", path.display()));
+            character_sets.insert(name.clone(), content);
+        }
+        let words = if self.words.is_some() {
             vec![]
         } else {
             word_frequency.clone().into_keys().collect()
@@ -133,8 +539,17 @@ impl Cli {
             word_frequency,
             key_distribution,
             pair_equivalence,
+            triple_equivalence,
+            corpus,
+            keyboard_geometry,
+            punctuation_frequency,
+            pronunciation,
+            character_sets,
+            traditional_frequency,
+            variants,
+            identifiers,
         };
-        return (config, elements, words, assets);
+        Ok((config, elements, words, assets))
     }
 
     pub fn export_code(path: &PathBuf, original: Vec<Entry>) {
@@ -155,37 +570,504 @@ impl Cli {
         writer.flush().unwrap();
     }
 
-    pub fn write_encode_results(results: EncodeExport) {
+    pub fn write_encode_results(&self, results: EncodeExport, reverse: bool) {
         let c_path = PathBuf::from("characters.txt");
         let w_path = PathBuf::from("words.txt");
+        let characters = results.characters.len();
+        let words = results.words.as_ref().map_or(0, |x| x.len());
         Self::export_code(&c_path, results.characters);
         if let Some(words) = results.words {
             Self::export_code(&w_path, words);
         }
-        println!(
+        let mut p_path = None;
+        if let Some(punctuation) = results.punctuation {
+            let path = PathBuf::from("punctuation.txt");
+            Self::export_code(&path, punctuation);
+            p_path = Some(path);
+        }
+        let mut s_path = None;
+        if let Some(short_codes) = results.short_codes {
+            let path = PathBuf::from("short_codes.txt");
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .has_headers(false)
+                .from_path(&path)
+                .unwrap();
+            for ShortCodeAssignment { code, item, contenders } in short_codes {
+                writer.serialize((&code, &item, contenders.join(" "))).unwrap();
+            }
+            writer.flush().unwrap();
+            s_path = Some(path);
+        }
+        let mut r_path = None;
+        if reverse {
+            let path = PathBuf::from("codes.txt");
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .has_headers(false)
+                .from_path(&path)
+                .unwrap();
+            for CodeEntry { code, items } in results.codes {
+                writer.serialize((&code, items.join(" "))).unwrap();
+            }
+            writer.flush().unwrap();
+            r_path = Some(path);
+        }
+        if self.json(|| {
+            json!({
+                "event": "encode",
+                "characters": { "path": c_path, "count": characters },
+                "words": { "path": w_path, "count": words },
+                "punctuation": p_path,
+                "short_codes": s_path,
+                "codes": r_path,
+            })
+        }) {
+            return;
+        }
+        if let Some(path) = &p_path {
+            info!(target: RESULT, "标点符号的编码保存在 {} 中", path.display());
+        }
+        if let Some(path) = &s_path {
+            info!(target: RESULT, "单字简码的分配结果保存在 {} 中", path.display());
+        }
+        if let Some(path) = &r_path {
+            info!(target: RESULT, "反查表保存在 {} 中", path.display());
+        }
+        info!(
+            target: RESULT,
             "已完成编码，结果保存在 {} 和 {} 中",
             c_path.display(),
             w_path.display()
         );
     }
 
-    pub fn report_metric(metric: Metric) {
-        println!("当前方案评测：");
-        print!("{}", metric);
+    /// 码表中每个字词的权重：单字取字频，词语取词频，没有频率的字词（如标点）为 0
+    fn weight(item: &str, assets: &Assets) -> u64 {
+        let mut chars = item.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => assets.character_frequency.get(&c).copied(),
+            _ => assets.word_frequency.get(item).copied(),
+        }
+        .unwrap_or(0)
+    }
+
+    /// 按照反查表写出码表：同一个编码上的字词按候选顺序排列，所以导出的码表保留编码器决定的候选顺序
+    pub fn write_export(
+        &self,
+        results: EncodeExport,
+        config: &Config,
+        assets: &Assets,
+        target: Target,
+        name: Option<String>,
+    ) {
+        let config_path = self.config.clone().unwrap_or(PathBuf::from("config.yaml"));
+        let name = name.unwrap_or_else(|| {
+            let stem = config_path.file_stem().unwrap_or_default();
+            stem.to_string_lossy().to_string()
+        });
+        // 不同格式的文件名后缀各不相同，以免覆盖方案文件或者其他格式的码表
+        let path = match target {
+            Target::Rime => PathBuf::from(format!("{name}.dict.yaml")),
+            Target::Duoduo => PathBuf::from(format!("{name}.duoduo.txt")),
+            Target::Jidian => PathBuf::from(format!("{name}.jidian.txt")),
+            Target::Xiaoxiao => PathBuf::from(format!("{name}.xiaoxiao.yaml")),
+        };
+        let mut writer = BufWriter::new(File::create(&path).unwrap());
+        match target {
+            Target::Rime => {
+                let version = config.info.as_ref().map_or("1.0", |x| x.version.as_str());
+                writeln!(writer, "# Rime dictionary").unwrap();
+                writeln!(writer, "# encoding: utf-8").unwrap();
+                writeln!(writer, "#").unwrap();
+                writeln!(writer, "# 由 chai 根据 {} 生成", config_path.display()).unwrap();
+                writeln!(writer).unwrap();
+                writeln!(writer, "---").unwrap();
+                writeln!(writer, "name: {name}").unwrap();
+                writeln!(writer, "version: {}", serde_json::to_string(version).unwrap()).unwrap();
+                // 按文件中的顺序排列候选，而不是按权重重新排序
+                writeln!(writer, "sort: original").unwrap();
+                writeln!(writer, "columns:\n  - text\n  - code\n  - weight").unwrap();
+                writeln!(writer, "...").unwrap();
+                writeln!(writer).unwrap();
+                for CodeEntry { code, items } in &results.codes {
+                    for item in items {
+                        let weight = Self::weight(item, assets);
+                        writeln!(writer, "{item}\t{code}\t{weight}").unwrap();
+                    }
+                }
+            }
+            Target::Duoduo => {
+                for CodeEntry { code, items } in &results.codes {
+                    for item in items {
+                        writeln!(writer, "{code}={item}").unwrap();
+                    }
+                }
+            }
+            Target::Jidian => {
+                for CodeEntry { code, items } in &results.codes {
+                    writeln!(writer, "{code} {}", items.join(" ")).unwrap();
+                }
+            }
+            Target::Xiaoxiao => {
+                // JSON 的字符串也是合法的 YAML 字符串，这样标点等特殊字符不需要另外转义
+                for CodeEntry { code, items } in &results.codes {
+                    let items: Vec<String> = items
+                        .iter()
+                        .map(|x| serde_json::to_string(x).unwrap())
+                        .collect();
+                    let code = serde_json::to_string(code).unwrap();
+                    writeln!(writer, "{code}: [{}]", items.join(", ")).unwrap();
+                }
+            }
+        }
+        writer.flush().unwrap();
+        let entries: usize = results.codes.iter().map(|x| x.items.len()).sum();
+        if self.json(|| json!({ "event": "export", "path": path, "entries": entries })) {
+            return;
+        }
+        info!(target: RESULT, "已导出 {} 条码表项，保存在 {} 中", entries, path.display());
+    }
+
+    /// 把评测指标展开成指标路径和数值的列表，例如「characters_full.duplication」，顺序与指标中的字段顺序一致
+    fn flatten_metric(metric: &Metric) -> Vec<(String, f64)> {
+        fn visit(prefix: String, value: &Value, result: &mut Vec<(String, f64)>) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let path = if prefix.is_empty() {
+                            key.clone()
+                        } else {
+                            format!("{prefix}.{key}")
+                        };
+                        visit(path, value, result);
+                    }
+                }
+                Value::Array(values) => {
+                    for (index, value) in values.iter().enumerate() {
+                        visit(format!("{prefix}[{index}]"), value, result);
+                    }
+                }
+                Value::Number(number) => result.push((prefix, number.as_f64().unwrap_or(0.0))),
+                _ => {}
+            }
+        }
+        let mut result = Vec::new();
+        visit(String::new(), &serde_json::to_value(metric).unwrap(), &mut result);
+        result
+    }
+
+    /// 比较两个方案的编码结果，列出编码改变的字词，以及它们的频率占全部字词频率的比例
+    fn code_changes(old: &[Entry], new: &[Entry], assets: &Assets) -> (Vec<CodeChange>, f64) {
+        let describe = |entry: &Entry| match &entry.short {
+            Some(short) if short != &entry.full => format!("{}（简码 {}）", entry.full, short),
+            _ => entry.full.clone(),
+        };
+        let mut changes = Vec::new();
+        let mut total = 0;
+        let mut changed = 0;
+        for (old, new) in old.iter().zip(new) {
+            let weight = Self::weight(&old.item, assets);
+            total += weight;
+            if old.full != new.full || old.short != new.short {
+                changed += weight;
+                changes.push((old.item.clone(), describe(old), describe(new), weight));
+            }
+        }
+        changes.sort_by_key(|x| Reverse(x.3));
+        (changes, changed as f64 / total.max(1) as f64)
+    }
+
+    pub fn report_diff(
+        &self,
+        moves: &[MoveRecord],
+        old: (EncodeExport, Metric, f64),
+        new: (EncodeExport, Metric, f64),
+        assets: &Assets,
+        top: usize,
+    ) {
+        let (old_codes, old_metric, old_loss) = old;
+        let (new_codes, new_metric, new_loss) = new;
+        let mut parts = vec![(
+            "单字",
+            Self::code_changes(&old_codes.characters, &new_codes.characters, assets),
+        )];
+        if let (Some(old), Some(new)) = (&old_codes.words, &new_codes.words) {
+            parts.push(("词语", Self::code_changes(old, new, assets)));
+        }
+        let new_values: HashMap<String, f64> =
+            Self::flatten_metric(&new_metric).into_iter().collect();
+        let mut metrics = vec![("目标函数".to_string(), old_loss, new_loss)];
+        for (path, value) in Self::flatten_metric(&old_metric) {
+            if let Some(new_value) = new_values.get(&path) {
+                metrics.push((path, value, *new_value));
+            }
+        }
+        if self.json(|| {
+            let codes: Vec<Value> = parts
+                .iter()
+                .map(|(kind, (changes, impact))| {
+                    let count = changes.len();
+                    let changes: Vec<Value> = changes
+                        .iter()
+                        .take(top)
+                        .map(|(item, old, new, frequency)| {
+                            json!({ "item": item, "old": old, "new": new, "frequency": frequency })
+                        })
+                        .collect();
+                    json!({
+                        "kind": kind,
+                        "count": count,
+                        "impact": impact,
+                        "changes": changes,
+                    })
+                })
+                .collect();
+            let metrics: Vec<Value> = metrics
+                .iter()
+                .map(|(name, old, new)| json!({ "name": name, "old": old, "new": new }))
+                .collect();
+            json!({ "event": "diff", "moves": moves, "codes": codes, "metrics": metrics })
+        }) {
+            return;
+        }
+        info!(target: RESULT, "共有 {} 个元素换了按键：", moves.len());
+        for MoveRecord { element, from, to } in moves {
+            info!(target: RESULT, "{}\t{} → {}", element, from, to);
+        }
+        for (kind, (changes, impact)) in &parts {
+            info!(target: RESULT, "");
+            info!(
+                target: RESULT,
+                "{}中有 {} 个编码改变，按频率加权占 {:.4}%，其中最常用的 {} 个：",
+                kind,
+                changes.len(),
+                impact * 100.0,
+                top.min(changes.len())
+            );
+            for (item, old, new, frequency) in changes.iter().take(top) {
+                info!(target: RESULT, "{}\t{} → {}\t{}", item, old, new, frequency);
+            }
+        }
+        info!(target: RESULT, "");
+        info!(target: RESULT, "指标\t原方案\t新方案\t变化");
+        for (name, old, new) in metrics {
+            info!(target: RESULT, "{}\t{:.6}\t{:.6}\t{:+.6}", name, old, new, new - old);
+        }
+    }
+
+    /// 输出排名表，列依次为名次、文件、目标函数和展开之后的各项评测指标。某个方案没有的指标留空
+    pub fn report_rank(&self, results: &[(String, Metric, f64)], csv: Option<&Path>) {
+        let flattened: Vec<HashMap<String, f64>> = results
+            .iter()
+            .map(|(_, metric, _)| Self::flatten_metric(metric).into_iter().collect())
+            .collect();
+        let mut columns: Vec<String> = Vec::new();
+        for (_, metric, _) in results {
+            for (name, _) in Self::flatten_metric(metric) {
+                if !columns.contains(&name) {
+                    columns.push(name);
+                }
+            }
+        }
+        let mut header = vec!["名次".to_string(), "文件".to_string(), "目标函数".to_string()];
+        header.extend(columns.iter().cloned());
+        let rows: Vec<Vec<String>> = zip(results, &flattened)
+            .enumerate()
+            .map(|(index, ((name, _, loss), values))| {
+                let mut row = vec![(index + 1).to_string(), name.clone(), format!("{loss:.6}")];
+                for column in &columns {
+                    row.push(values.get(column).map_or(String::new(), |x| format!("{x:.6}")));
+                }
+                row
+            })
+            .collect();
+        if let Some(path) = csv {
+            let mut writer = csv::Writer::from_path(path).unwrap();
+            writer.write_record(&header).unwrap();
+            for row in &rows {
+                writer.write_record(row).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        if self.json(|| {
+            let entries: Vec<Value> = zip(results, flattened)
+                .map(|((name, _, loss), metrics)| {
+                    json!({ "path": name, "loss": loss, "metrics": metrics })
+                })
+                .collect();
+            json!({ "event": "rank", "entries": entries, "csv": csv })
+        }) {
+            return;
+        }
+        info!(target: RESULT, "已评测 {} 个方案，按目标函数从小到大排名如下：", results.len());
+        info!(target: RESULT, "{}", header.join("\t"));
+        for row in rows {
+            info!(target: RESULT, "{}", row.join("\t"));
+        }
+        if let Some(path) = csv {
+            info!(target: RESULT, "排名表保存在 {} 中", path.display());
+        }
+    }
+
+    pub fn read_text(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_else(|_| panic!("文本文件 {} 不存在", path.display()))
+    }
+
+    pub fn write_simulation(&self, result: SimulationExport) {
+        let path = PathBuf::from("simulation.txt");
+        fs::write(&path, &result.keystrokes).unwrap();
+        let per_character = result.keys as f64 / result.characters.max(1) as f64;
+        if self.json(|| {
+            json!({
+                "event": "simulate",
+                "characters": result.characters,
+                "keys": result.keys,
+                "keys_per_character": per_character,
+                "selections": result.selections,
+                "path": path,
+            })
+        }) {
+            return;
+        }
+        info!(
+            target: RESULT,
+            "共输入 {} 个字，击键 {} 次，字均键数 {:.4}，选重 {} 次",
+            result.characters, result.keys, per_character, result.selections
+        );
+        info!(target: RESULT, "击键序列保存在 {} 中", path.display());
+    }
+
+    pub fn read_checkpoint(path: &Path) -> Vec<u8> {
+        fs::read(path).unwrap_or_else(|_| panic!("检查点文件 {} 不存在", path.display()))
+    }
+
+    pub fn read_config(path: &Path) -> Config {
+        let content = fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("方案文件 {} 不存在", path.display()));
+        serde_yaml::from_str(&content)
+            .unwrap_or_else(|e| panic!("方案文件 {} 无法解析：{}", path.display(), e))
+    }
+
+    /// 读取目录中所有的方案文件，按照文件名排序
+    pub fn read_warm_start(directory: &Path) -> Vec<(String, Config)> {
+        let entries = fs::read_dir(directory)
+            .unwrap_or_else(|_| panic!("目录 {} 不存在", directory.display()));
+        let mut paths: Vec<PathBuf> = entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|x| x == "yaml" || x == "yml"))
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let content = fs::read_to_string(&path).unwrap();
+                let config: Config = serde_yaml::from_str(&content)
+                    .unwrap_or_else(|e| panic!("方案文件 {} 无法解析：{}", path.display(), e));
+                (path.display().to_string(), config)
+            })
+            .collect()
+    }
+
+    /// 读取优化轨迹文件，每一行是一步
+    pub fn read_trajectory(path: &Path) -> Vec<TrajectoryEntry> {
+        let content = fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("轨迹文件 {} 不存在", path.display()));
+        content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_str(line).expect("轨迹文件格式错误"))
+            .collect()
+    }
+
+    pub fn report_replay(&self, step: usize, moves: usize, metric: &Metric) {
+        if self.json(|| {
+            json!({
+                "event": "replay",
+                "step": step,
+                "moves": moves,
+                "metric": metric,
+            })
+        }) {
+            return;
+        }
+        info!(target: RESULT, "已回放到第 {} 步（共 {} 次移动），评测指标如下：", step, moves);
+        info!(target: RESULT, "{}", metric.to_string().trim_end());
+    }
+
+    pub fn report_replay_result(&self, loss: f64, expected_loss: f64) {
+        if self.json(|| {
+            json!({
+                "event": "replay_result",
+                "loss": loss,
+                "expected_loss": expected_loss,
+            })
+        }) {
+            return;
+        }
+        info!(
+            target: RESULT,
+            "回放得到的目标函数值为 {:.6}，记录中累计的目标函数值为 {:.6}",
+            loss, expected_loss
+        );
+    }
+
+    pub fn report_validation(&self, problems: &[String]) {
+        if self.json(|| json!({ "event": "validate", "problems": problems })) {
+            return;
+        }
+        if problems.is_empty() {
+            info!(target: RESULT, "没有发现问题");
+            return;
+        }
+        info!(target: RESULT, "发现 {} 个问题：", problems.len());
+        for problem in problems {
+            info!(target: RESULT, "- {problem}");
+        }
+    }
+
+    pub fn report_metric(&self, metric: Metric) {
+        if self.json(|| json!({ "event": "evaluate", "metric": metric })) {
+            return;
+        }
+        info!(target: RESULT, "当前方案评测：");
+        info!(target: RESULT, "{}", metric.to_string().trim_end());
     }
 }
 
 impl Interface for Cli {
     fn prepare_output(&self) {
-        let _ = fs::create_dir_all("output").expect("should be able to create an output directory");
+        fs::create_dir_all("output").expect("should be able to create an output directory");
     }
 
     fn init_autosolve(&self) {
-        println!("开始寻找参数……");
+        if self.json(|| json!({ "event": "autosolve" })) {
+            return;
+        }
+        info!("开始寻找参数……");
+    }
+
+    fn report_search_space(&self, movable: usize, size: f64) {
+        if self.json(|| json!({ "event": "search_space", "movable": movable, "size": size })) {
+            return;
+        }
+        info!(
+            "共有 {} 个可移动的元素，满足约束的布局约有 10^{:.1} 种",
+            movable, size
+        );
     }
 
     fn report_trial_t_max(&self, temperature: f64, accept_rate: f64) {
-        println!(
+        if self.json(|| {
+            json!({
+                "event": "trial_t_max",
+                "temperature": temperature,
+                "accept_rate": accept_rate,
+            })
+        }) {
+            return;
+        }
+        info!(
             "若温度为 {:.2e}，接受率为 {:.2}%",
             temperature,
             accept_rate * 100.0
@@ -193,14 +1075,26 @@ impl Interface for Cli {
     }
 
     fn report_t_max(&self, temperature: f64) {
-        println!(
+        if self.json(|| json!({ "event": "t_max", "temperature": temperature })) {
+            return;
+        }
+        info!(
             "接受率已符合标准，体系最高温度估计为：t_max = {:.2e}",
             temperature
         );
     }
 
     fn report_trial_t_min(&self, temperature: f64, improve_rate: f64) {
-        println!(
+        if self.json(|| {
+            json!({
+                "event": "trial_t_min",
+                "temperature": temperature,
+                "improve_rate": improve_rate,
+            })
+        }) {
+            return;
+        }
+        info!(
             "若温度为 {:.2e}，改进率为 {:.2}%",
             temperature,
             improve_rate * 100.0
@@ -208,29 +1102,262 @@ impl Interface for Cli {
     }
 
     fn report_t_min(&self, temperature: f64) {
-        println!(
+        if self.json(|| json!({ "event": "t_min", "temperature": temperature })) {
+            return;
+        }
+        info!(
             "改进率已符合标准，体系最低温度估计为：t_min = {:.2e}",
             temperature
         );
     }
 
     fn report_parameters(&self, t_max: f64, t_min: f64, steps: usize) {
-        println!(
-            "参数寻找完成，将在 {} 步内从最高温 {} 降到最低温 {}……",
-            steps, t_max, t_min
-        );
+        if self.json(|| {
+            json!({
+                "event": "parameters",
+                "t_max": t_max,
+                "t_min": t_min,
+                "steps": steps,
+            })
+        }) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "退火参数已确定，将在 {} 步内从最高温 {} 降到最低温 {}……",
+                steps, t_max, t_min
+            );
+        });
     }
 
     fn report_elapsed(&self, time: u128) {
-        println!("计算一次评测用时：{} μs", time);
+        if self.json(|| json!({ "event": "elapsed", "microseconds": time as u64 })) {
+            return;
+        }
+        self.suspend(|| {
+            info!("计算一次评测用时：{} μs", time);
+        });
     }
 
-    fn report_schedule(&self, step: usize, temperature: f64, metric: String) {
-        println!(
-            "优化已执行 {} 步，当前温度为 {:.2e}，当前评测指标如下：",
-            step, temperature
+    fn report_step_estimate(&self, per_step: f64, budget: u64, steps: usize) {
+        if self.json(|| {
+            json!({
+                "event": "step_estimate",
+                "per_step": per_step,
+                "budget": budget,
+                "steps": steps,
+            })
+        }) {
+            return;
+        }
+        info!(
+            "实测每步用时约 {:.1} μs，剩余运行时间 {} 秒，据此确定优化步数为 {}",
+            per_step, budget, steps
         );
-        println!("{}", metric);
+    }
+
+    fn report_schedule(&self, step: usize, temperature: f64, schedule: Schedule, metric: String) {
+        if self.json(|| {
+            json!({
+                "event": "schedule",
+                "step": step,
+                "temperature": temperature,
+                "schedule": schedule,
+                "metric": metric,
+            })
+        }) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "优化已执行 {} 步，当前温度为 {:.2e}（{}），当前评测指标如下：",
+                step, temperature, schedule
+            );
+            info!("{}", metric);
+        });
+    }
+
+    fn report_progress(&self, step: usize, metric: String) {
+        if self.json(|| json!({ "event": "progress", "step": step, "metric": metric })) {
+            return;
+        }
+        self.suspend(|| {
+            info!("优化已执行 {} 步，当前评测指标如下：", step);
+            info!("{}", metric);
+        });
+    }
+
+    fn report_status(&self, step: usize, steps: usize, temperature: f64, best: f64) {
+        // JSON 格式的输出已经有每一千步一次的进度，不需要进度条；安静模式不显示进度，详细模式逐步输出的信息会打乱进度条
+        if self.format == Format::Json || self.quiet || self.verbose > 0 {
+            return;
+        }
+        let mut progress = self.progress.lock().unwrap();
+        let bar = progress.get_or_insert_with(|| {
+            let bar = ProgressBar::new(steps as u64);
+            let template = "{elapsed_precise} [{bar:40}] {pos}/{len} 步 {msg} 预计剩余 {eta}";
+            bar.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "));
+            // 从检查点继续优化时第一次汇报的步数不为零，不应该计入速度
+            bar.set_position(step as u64);
+            bar.reset_eta();
+            bar
+        });
+        bar.set_length(steps as u64);
+        bar.set_position(step as u64);
+        bar.set_message(format!("温度 {:.2e}，最好 {:.6}", temperature, best));
+        if step >= steps {
+            bar.finish_and_clear();
+            *progress = None;
+        }
+    }
+
+    fn report_early_stop(&self, step: usize, stagnation: usize) {
+        if self.json(|| json!({ "event": "early_stop", "step": step, "stagnation": stagnation })) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "最好方案已经连续 {} 步没有改进，优化在第 {} 步提前停止",
+                stagnation, step
+            );
+        });
+    }
+
+    fn report_reheat(&self, step: usize, temperature: f64) {
+        if self.json(|| json!({ "event": "reheat", "step": step, "temperature": temperature })) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "搜索在第 {} 步陷入停滞，温度回升至 {:.2e}",
+                step, temperature
+            );
+        });
+    }
+
+    fn report_restarts(&self, losses: &[f64]) {
+        let count = losses.len() as f64;
+        let mean = losses.iter().sum::<f64>() / count;
+        let variance = losses.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / count;
+        let min = losses.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = losses.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if self.json(|| {
+            json!({
+                "event": "restarts",
+                "losses": losses,
+                "min": min,
+                "max": max,
+                "mean": mean,
+                "std": variance.sqrt(),
+            })
+        }) {
+            return;
+        }
+        info!(target: RESULT, "{} 次重启已全部完成，各次的目标函数值如下：", losses.len());
+        info!(target: RESULT, "序号\t目标函数值");
+        for (index, loss) in losses.iter().enumerate() {
+            info!(target: RESULT, "{}\t{:.6}", index + 1, loss);
+        }
+        info!(
+            target: RESULT,
+            "最好：{:.6}；最差：{:.6}；平均：{:.6}；标准差：{:.6}",
+            min,
+            max,
+            mean,
+            variance.sqrt()
+        );
+    }
+
+    fn report_stage(&self, stage: usize, total: usize, fixed: usize) {
+        if self.json(|| {
+            json!({
+                "event": "stage",
+                "stage": stage,
+                "total": total,
+                "fixed": fixed,
+            })
+        }) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "开始第 {} / {} 阶段的优化，已固定 {} 个元素",
+                stage, total, fixed
+            );
+        });
+    }
+
+    fn report_warm_start(&self, losses: &[(String, f64)], best: usize) {
+        if self.json(|| {
+            let losses: Vec<Value> = losses
+                .iter()
+                .map(|(name, loss)| json!({ "path": name, "loss": loss }))
+                .collect();
+            json!({ "event": "warm_start", "candidates": losses, "best": best })
+        }) {
+            return;
+        }
+        info!("已评测 {} 个候选的初始方案，各自的目标函数值如下：", losses.len());
+        info!("文件\t目标函数值");
+        for (name, loss) in losses {
+            info!("{}\t{:.6}", name, loss);
+        }
+        info!("将从 {} 开始优化", losses[best].0);
+    }
+
+    fn report_move(&self, entry: TrajectoryEntry) {
+        let mut trajectory = self.trajectory.lock().unwrap();
+        let writer = trajectory.get_or_insert_with(|| {
+            let time = Local::now();
+            let path = format!("output/trajectory-{}.jsonl", time.format("%m-%d+%H_%M_%S"));
+            if !self.json(|| json!({ "event": "trajectory", "path": path })) {
+                info!("优化轨迹将记录在 {} 中", path);
+            }
+            BufWriter::new(File::create(path).unwrap())
+        });
+        serde_json::to_writer(&mut *writer, &entry).unwrap();
+        writer.write_all(b"\n").unwrap();
+    }
+
+    fn report_checkpoint(&self, _: usize, checkpoint: Vec<u8>) {
+        // 先写入临时文件再重命名，避免写入过程中断导致检查点损坏
+        let temporary = Path::new("output").join("checkpoint.bin.tmp");
+        fs::write(&temporary, checkpoint).unwrap();
+        fs::rename(&temporary, Path::new("output").join("checkpoint.bin")).unwrap();
+    }
+
+    fn report_pareto_front(&self, components: &[String], front: Vec<(Config, Vec<f64>, String)>) {
+        let time = Local::now();
+        let directory = format!("output/pareto-{}", time.format("%m-%d+%H_%M_%S"));
+        fs::create_dir_all(&directory).unwrap();
+        let json = self.format == Format::Json;
+        let mut solutions = Vec::new();
+        if !json {
+            info!(target: RESULT, "多目标优化完成，帕累托前沿中共有 {} 个方案：", front.len());
+            info!(target: RESULT, "序号\t{}", components.join("\t"));
+        }
+        for (index, (config, values, metric)) in front.into_iter().enumerate() {
+            if json {
+                solutions.push(values.clone());
+            } else {
+                let values: Vec<String> = values.iter().map(|x| format!("{:.6}", x)).collect();
+                info!(target: RESULT, "{}\t{}", index + 1, values.join("\t"));
+            }
+            fs::write(format!("{}/{}.txt", directory, index + 1), metric).unwrap();
+            let config_path = format!("{}/{}.yaml", directory, index + 1);
+            fs::write(config_path, serde_yaml::to_string(&config).unwrap()).unwrap();
+        }
+        if self.json(|| {
+            json!({
+                "event": "pareto_front",
+                "components": components,
+                "front": solutions,
+                "directory": directory,
+            })
+        }) {
+            return;
+        }
+        info!(target: RESULT, "方案文件和评测指标保存于 {} 中", directory);
     }
 
     fn report_solution(&self, config: Config, metric: String, save: bool) {
@@ -238,18 +1365,33 @@ impl Interface for Cli {
         let prefix = format!("{}", time.format("%m-%d+%H_%M_%S_%3f"));
         let config_path = format!("output/{}.yaml", prefix);
         let metric_path = format!("output/{}.txt", prefix);
-        println!(
-            "{} 系统搜索到了一个更好的方案，评测指标如下：",
-            time.format("%H:%M:%S")
-        );
-        print!("{}", metric);
         if save {
-            fs::write(metric_path, metric).unwrap();
-            fs::write(config_path, serde_yaml::to_string(&config).unwrap()).unwrap();
-            println!(
-                "方案文件保存于 {}.yaml 中，评测指标保存于 {}.txt 中",
-                prefix, prefix
-            );
+            fs::write(&metric_path, &metric).unwrap();
+            fs::write(&config_path, serde_yaml::to_string(&config).unwrap()).unwrap();
         }
+        if self.json(|| {
+            json!({
+                "event": "solution",
+                "time": time.format("%H:%M:%S").to_string(),
+                "metric": metric,
+                "config_path": save.then_some(&config_path),
+                "metric_path": save.then_some(&metric_path),
+            })
+        }) {
+            return;
+        }
+        self.suspend(|| {
+            info!(
+                "{} 系统搜索到了一个更好的方案，评测指标如下：",
+                time.format("%H:%M:%S")
+            );
+            info!("{}", metric.to_string().trim_end());
+            if save {
+                info!(
+                    "方案文件保存于 {}.yaml 中，评测指标保存于 {}.txt 中",
+                    prefix, prefix
+                );
+            }
+        });
     }
 }
@@ -5,7 +5,11 @@
 
 use crate::{
     data::Character,
-    metaheuristics::simulated_annealing,
+    metaheuristics::{
+        ant_colony, bandit, beam_search, exhaustive, genetic_algorithm, hill_climbing, memetic, nsga2, parallel_tempering,
+        simulated_annealing, tabu_search,
+    },
+    objectives::normalization::Normalization,
 };
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
@@ -110,42 +114,220 @@ pub struct ShortCodeConfig {
     pub prefix: usize,
     pub count: Option<usize>,
     pub select_keys: Option<Vec<char>>,
+    // 最多有多少个字取得这一级简码，按字频从高到低分配，不填则不限
+    pub limit: Option<usize>,
+    // 只有这些字可以取得这一级简码，不填则所有字都可以
+    pub characters: Option<String>,
+}
+
+/// 元素数量超过最大码长的字如何处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LongSequencePolicy {
+    // 报错
+    #[default]
+    Error,
+    // 只取前面的元素，直到最大码长
+    Truncate,
+    // 取前面的元素，直到最大码长减一，再取最后一个元素
+    FirstAndLast,
+}
+
+/// 一个字在拆分表中有多种拆分时如何编码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlternativeSplits {
+    // 只用第一种拆分
+    #[default]
+    First,
+    // 每次编码时按字频顺序，取第一种不与更常用的字重码的拆分
+    Best,
+    // 用第一种拆分编码，其余拆分作为容错码，排在所有字词之后
+    Tolerance,
+}
+
+/// 音形方案中从读音取得的部分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyllablePart {
+    // 声母，零声母记为「声:0」
+    Initial,
+    // 韵母
+    Final,
+    // 声调，轻声记为 5
+    Tone,
+}
+
+/// 读音元素放在拆分元素之前还是之后
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PronunciationPosition {
+    #[default]
+    Before,
+    After,
+}
+
+/// 音形方案：除了拆分表中的字形元素，每个字还有从读音表中取得的声母、韵母等元素
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PronunciationConfig {
+    // 依次取读音的哪些部分，默认为声母和韵母
+    pub parts: Option<Vec<SyllablePart>>,
+    pub position: Option<PronunciationPosition>,
 }
 
+/// 自动分配简码：按字频从高到低，把空闲的一码、二码等短编码分给全码以之为前缀的字
 #[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoShortCodeConfig {
+    // 分配的最长简码，默认为 2
+    pub max_length: Option<usize>,
+    // 只有字频最高的这么多个字可以取得简码，不填则不限
+    pub top: Option<usize>,
+    // 只有这些字可以取得简码，不填则所有字都可以
+    pub characters: Option<String>,
+}
+
+/// 取得简码的字如何处理它的全码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FullCodePolicy {
+    // 保留全码，全码按字频与其他字排序
+    #[default]
+    Keep,
+    // 出简不出全：从码表中删除全码
+    Remove,
+    // 保留全码，但排在同一编码上所有没有简码的字之后
+    Demote,
+}
+
+#[skip_serializing_none]
+/// 元素数量不足时如何补码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaddingRule {
+    // 重复最后一个元素，直到补足长度
+    #[default]
+    RepeatLast,
+    // 加上由末笔和字型决定的识别码
+    Identifier,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaddingConfig {
+    pub rule: Option<PaddingRule>,
+    // 元素数量少于这个长度的字才需要补码，不填则为最大码长
+    pub length: Option<usize>,
+}
+
+/// 同一个编码上的多个字词如何排列候选
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateOrder {
+    // 单字在前、词语在后，各自按频率排列
+    #[default]
+    CharacterFirst,
+    // 字词统一按频率排列
+    Frequency,
+    // 字数少的在前，字数相同时按频率排列
+    Length,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncoderConfig {
     // 全局
     pub max_length: usize,
+    // 元素数量超过最大码长的字如何处理
+    pub long_sequence_policy: Option<LongSequencePolicy>,
+    // 一个字有多种拆分时如何处理
+    pub alternative_splits: Option<AlternativeSplits>,
+    // 元素数量不足的字如何补码
+    pub padding: Option<PaddingConfig>,
     pub select_keys: Option<Vec<char>>,
     pub auto_select_length: Option<usize>,
     pub auto_select_pattern: Option<String>,
+    // 顶功键：以顶功键开头、其余按键都不是顶功键的编码，由下一个编码的首键顶上屏
+    pub top_keys: Option<String>,
+    // 只编码和评测这个字集中的字，以及完全由这些字组成的词
+    pub character_set: Option<String>,
+    // 音形方案的读音元素
+    pub pronunciation: Option<PronunciationConfig>,
     // 单字全码
     pub sources: Option<BTreeMap<String, NodeConfig>>,
     pub conditions: Option<BTreeMap<String, EdgeConfig>>,
     // 单字简码
     pub short_code_schemes: Option<Vec<ShortCodeConfig>>,
+    // 自动分配简码，与 short_code_schemes 二选一
+    pub auto_short_codes: Option<AutoShortCodeConfig>,
+    // 取得简码的字如何处理它的全码
+    pub full_code_policy: Option<FullCodePolicy>,
+    // 重码的候选顺序，以及总是排在首选的字词
+    pub duplicate_order: Option<DuplicateOrder>,
+    pub pinned: Option<Vec<String>>,
     // 词语全码
     pub rules: Option<Vec<WordRule>>,
+    // 标点符号的编码，是固定的按键序列，不参与优化
+    pub punctuation: Option<BTreeMap<char, String>>,
 }
 
+#[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LevelWeights {
     pub length: usize,
     pub frequency: f64,
+    // 这一码长的字词的组合当量，只用于动态指标
+    pub pair_equivalence: Option<f64>,
 }
 
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TierWeights {
+    // 统计范围是按频率排序的第 from 个（不含）到第 top 个（含）字词，from 默认为 0
+    pub from: Option<usize>,
     pub top: Option<usize>,
     pub duplication: Option<f64>,
+    // 覆盖率：无重码且码长不超过理想码长的字词的比例
+    pub coverage: Option<f64>,
+    // 当前层级中按频率加权的平均码长
+    pub length: Option<f64>,
+    // 当前层级中按频率加权的组合当量
+    pub pair_equivalence: Option<f64>,
     pub levels: Option<Vec<LevelWeights>>,
 }
 
+/// 按选重位置计算的重码代价：costs 依次为第二、第三……候选的代价，超出列表的候选都按最后一项计算
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedDuplicationWeights {
+    pub costs: Vec<f64>,
+    pub weight: f64,
+}
+
+/// 弱指惩罚的一项：使用率不超过阈值（百分比）时惩罚与使用率成正比，超过之后按幂次增长
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeakFingerTerm {
+    pub weight: f64,
+    pub threshold: Option<f64>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeakFingerWeights {
+    pub pinky: Option<WeakFingerTerm>,
+    pub ring: Option<WeakFingerTerm>,
+    pub bottom_pinky: Option<WeakFingerTerm>,
+    // 超过阈值之后的幂次，默认为 2
+    pub exponent: Option<f64>,
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FingeringWeights {
+    pub same_finger: Option<f64>,
+    pub hand_alternation: Option<f64>,
+    pub row_jump: Option<f64>,
     pub same_hand: Option<f64>,
     pub same_finger_large_jump: Option<f64>,
     pub same_finger_small_jump: Option<f64>,
@@ -156,17 +338,39 @@ pub struct FingeringWeights {
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartialWeights {
+    // 是否评测这一部分，默认为是。设为否时可以保留配置而暂时不优化这一部分
+    pub enabled: Option<bool>,
+    // 这一部分的整体权重，与各项指标的权重相乘，默认为 1
+    pub weight: Option<f64>,
     pub tiers: Option<Vec<TierWeights>>,
     pub duplication: Option<f64>,
+    pub ranked_duplication: Option<RankedDuplicationWeights>,
+    // 按照重码实际所用的选择键计算当量和指法，而不是都按首选键计算
+    pub select_keys: Option<bool>,
     pub key_distribution: Option<f64>,
+    pub key_distribution_loss: Option<DistributionLoss>,
     //杏码的「用指当量」。
     pub new_key_equivalence: Option<f64>,
     //杏码的「用指当量」（改），假定连续输入时预测上一键从而计算组合当量（慢）。
     pub new_key_equivalence_modified: Option<f64>,
     pub pair_equivalence: Option<f64>,
+    // 用户指定的差键位组合的惩罚，与速度当量表无关
+    pub bad_pairs: Option<f64>,
+    // 三键当量，需要提供 triple_equivalence.txt
+    pub triple_equivalence: Option<f64>,
     //杏码的「速度（组合）当量」。
     pub new_pair_equivalence: Option<f64>,
     pub fingering: Option<FingeringWeights>,
+    pub finger_load: Option<f64>,
+    // 手指移动距离，需要提供 keyboard_geometry.txt
+    pub travel_distance: Option<f64>,
+    pub weak_fingers: Option<WeakFingerWeights>,
+    // 码位利用率，权重作用于各码长未被利用的码位比例的平均值
+    pub utilization: Option<f64>,
+    // 出简不出全冲突，只对单字简码有效：简码与另一个字的全码相同的字的频率
+    pub short_full_conflict: Option<f64>,
+    // 动态选重率，只对单字有效：按语料逐字输入，重码的候选按最近使用的顺序动态调整
+    pub dynamic_duplication: Option<f64>,
     pub levels: Option<Vec<LevelWeights>>,
 }
 
@@ -177,6 +381,138 @@ pub struct ObjectiveConfig {
     pub words_full: Option<PartialWeights>,
     pub characters_short: Option<PartialWeights>,
     pub words_short: Option<PartialWeights>,
+    pub punctuation: Option<PartialWeights>,
+    // 繁体单字全码，字频取自繁体字频表
+    pub traditional: Option<PartialWeights>,
+    pub keyboard: Option<KeyboardConfig>,
+    // 差键位组合及其惩罚，例如 qz: 1.0
+    pub bad_pairs: Option<HashMap<String, f64>>,
+    pub corpus: Option<CorpusWeights>,
+    pub learning: Option<LearningWeights>,
+    pub reference: Option<ReferenceWeights>,
+    pub custom: Option<Vec<CustomWeights>>,
+    pub normalization: Option<Normalization>,
+}
+
+/// 用户用脚本自定义的指标
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomWeights {
+    pub name: String,
+    // Rhai 脚本，其中定义一个 score(entries) 函数
+    pub script: String,
+    // 脚本所评测的部分，默认为单字全码
+    pub partial: Option<String>,
+    pub weight: f64,
+}
+
+/// 学习成本：衡量记忆元素布局的难度，与频率无关
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LearningWeights {
+    // 单个按键上最多的元素数
+    pub max_elements: Option<f64>,
+    // 各个按键上元素数的方差
+    pub variance: Option<f64>,
+    // 放置了不同组元素的按键数
+    pub unrelated: Option<f64>,
+    // 单个按键上的元素数上限，超出上限的元素计入惩罚
+    pub overload: Option<OverloadWeights>,
+    // 元素分组，同一组中的元素彼此相关（例如形近的字根），便于放在同一个按键上记忆
+    pub groups: Option<Vec<Vec<String>>>,
+}
+
+/// 单键元素数上限：每个按键上超出 limit 的元素数之和计入惩罚
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverloadWeights {
+    pub limit: usize,
+    pub weight: f64,
+}
+
+/// 语料模拟：按切分好的语料逐项输入，跨越字词边界累计各项指标
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusWeights {
+    // 平均每个汉字的击键数
+    pub keys: Option<f64>,
+    // 组合当量，包括相邻两项之间的按键组合
+    pub pair_equivalence: Option<f64>,
+    // 需要选重的项所占的比例
+    pub duplication: Option<f64>,
+    // 同手连击，惩罚超过一定长度的同手连续击键
+    pub same_hand_runs: Option<SameHandRunWeights>,
+}
+
+/// 同手连击：同一只手连续击键的长度超过阈值的部分计入惩罚
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SameHandRunWeights {
+    pub weight: f64,
+    // 不计惩罚的最大连击长度，默认为 2，即相邻两键的组合交给组合当量处理
+    pub threshold: Option<usize>,
+}
+
+/// 参考布局：惩罚与参考布局中所在按键不同的元素，使优化结果不至于偏离旧版本太远
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceWeights {
+    pub mapping: HashMap<String, Mapped>,
+    pub weight: f64,
+}
+
+/// 键盘模型，用于计算指法相关的指标
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyboardConfig {
+    // 按键到手指的映射，手指从左到右编号为 0 至 9
+    pub fingers: Option<HashMap<char, usize>>,
+    // 按键到左右手的映射，默认由手指决定
+    pub hands: Option<HashMap<char, Hand>>,
+    // 按键到排的映射，排从上到下编号，数字排为 0
+    pub rows: Option<HashMap<char, usize>>,
+    // 按键到列的映射，列从左到右编号，左手小指的主列为 0，用于在约束中按列指定键盘区域
+    pub columns: Option<HashMap<char, usize>>,
+    // 十个手指的理想负荷（百分比），默认由用指分布数据汇总得到
+    pub finger_load: Option<Vec<f64>>,
+    // 所有按键的负荷上限（百分比），用于 hinge 形式的用指分布偏差
+    pub max_load: Option<f64>,
+    // 个别按键的负荷上限（百分比），优先于 max_load
+    pub caps: Option<HashMap<char, f64>>,
+    // 上档键模型，用于评测把元素放在上档字符（例如大写字母）上的方案
+    pub shift: Option<ShiftConfig>,
+}
+
+/// 上档键模型：上档字符与对应的基础按键共用手指和排，击打时额外按一次上档键
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShiftConfig {
+    // 每击打一次上档字符额外计入组合当量的代价
+    pub cost: Option<f64>,
+    // 上档键在速度当量表中的名称，用于计入上档键和基础按键的组合；不填时不计这一组合
+    pub key: Option<char>,
+    // 上档字符到基础按键的映射，补充或覆盖默认的美式键盘映射
+    pub keys: Option<HashMap<char, char>>,
+}
+
+/// 用指分布偏差的计算方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistributionLoss {
+    // 超出理想分布的部分之和
+    #[default]
+    Excess,
+    // 相对于理想分布的 KL 散度
+    KlDivergence,
+    // 与理想分布之差的平方和
+    Squared,
+    // 超出负荷上限的部分之和
+    Hinge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hand {
+    Left,
+    Right,
 }
 
 #[skip_serializing_none]
@@ -185,14 +521,96 @@ pub struct AtomicConstraint {
     pub element: Option<String>,
     pub index: Option<usize>,
     pub keys: Option<Vec<char>>,
+    // 允许的键盘区域，例如「左手」「右手食指区」「上排」，展开成字母表中属于这些区域的键，与 keys 合并
+    pub zones: Option<Vec<String>>,
+    // 不允许的键盘区域，展开之后与 exclude_keys 合并
+    pub exclude_zones: Option<Vec<String>>,
+    // 不允许的键，与 keys 同时提供时从 keys 中排除，否则从全部按键中排除
+    pub exclude_keys: Option<Vec<char>>,
+    // 软约束在违反时不是不可行，而是在目标函数中计入 weight 的惩罚，weight 默认为 1
+    pub soft: Option<bool>,
+    pub weight: Option<f64>,
+}
+
+/// 两个或更多元素之间的关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    // 必须在同一个键上
+    Same,
+    // 两两之间不能在同一个键上
+    Different,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationConstraint {
+    // 元素的名称，多编码方案中元素的第二码及以后写作「元素.1」这样的形式
+    pub elements: Vec<String>,
+    pub relation: Relation,
+}
+
+/// 一个元素在某些键上的条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyCondition {
+    pub element: String,
+    pub keys: Vec<char>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConditionalConstraint {
+    // 前一个元素在 if 给出的键上时，后一个元素必须在 then 给出的键上
+    #[serde(rename = "if")]
+    pub condition: KeyCondition,
+    #[serde(rename = "then")]
+    pub consequence: KeyCondition,
 }
 
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConstraintsConfig {
+    // 引用的约束预设，以预设中的约束为基础，再加上这里的约束
+    pub preset: Option<String>,
     pub elements: Option<Vec<AtomicConstraint>>,
     pub indices: Option<Vec<AtomicConstraint>>,
     pub element_indices: Option<Vec<AtomicConstraint>>,
+    pub relations: Option<Vec<RelationConstraint>>,
+    // 元素组，每组中的元素总是在同一个键上，作为一个整体移动
+    pub groups: Option<Vec<Vec<String>>>,
+    // 每个键上最多可以放的元素数，多码元素的每一码各算一个元素
+    pub max_elements_per_key: Option<usize>,
+    // 冻结的键和键盘区域：当前在这些键上的元素保持不动，其他元素也不能移到这些键上
+    pub frozen_keys: Option<Vec<char>>,
+    pub frozen_zones: Option<Vec<String>>,
+    // 条件约束：一个元素在某些键上时，另一个元素必须在另一些键上
+    pub conditionals: Option<Vec<ConditionalConstraint>>,
+}
+
+fn concat<T>(first: Option<Vec<T>>, second: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (first, second) {
+        (Some(mut first), Some(second)) => {
+            first.extend(second);
+            Some(first)
+        }
+        (first, second) => first.or(second),
+    }
+}
+
+impl ConstraintsConfig {
+    /// 以预设为基础合并这里的约束：列表形式的约束都接在预设的后面，按键容量以这里的为准。合并之后的约束不再引用预设
+    pub fn merge(self, preset: ConstraintsConfig) -> ConstraintsConfig {
+        ConstraintsConfig {
+            preset: None,
+            elements: concat(preset.elements, self.elements),
+            indices: concat(preset.indices, self.indices),
+            element_indices: concat(preset.element_indices, self.element_indices),
+            relations: concat(preset.relations, self.relations),
+            groups: concat(preset.groups, self.groups),
+            max_elements_per_key: self.max_elements_per_key.or(preset.max_elements_per_key),
+            frozen_keys: concat(preset.frozen_keys, self.frozen_keys),
+            frozen_zones: concat(preset.frozen_zones, self.frozen_zones),
+            conditionals: concat(preset.conditionals, self.conditionals),
+        }
+    }
 }
 
 #[skip_serializing_none]
@@ -201,6 +619,33 @@ pub struct SearchConfig {
     pub random_move: f64,
     pub random_swap: f64,
     pub random_full_key_swap: f64,
+    pub destroy_repair: Option<f64>,
+    pub rotation: Option<f64>,
+    pub feasible_swap: Option<f64>,
+    pub selection: Option<bandit::Strategy>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            random_move: 0.9,
+            random_swap: 0.09,
+            random_full_key_swap: 0.01,
+            destroy_repair: None,
+            rotation: None,
+            feasible_swap: None,
+            selection: None,
+        }
+    }
+}
+
+/// 初始方案的来源：方案文件中的布局，或者贪心构造
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Initialization {
+    #[default]
+    Config,
+    Greedy,
 }
 
 #[skip_serializing_none]
@@ -211,6 +656,23 @@ pub struct SolverConfig {
     pub parameters: Option<simulated_annealing::Parameters>,
     pub report_after: Option<f64>,
     pub search_method: Option<SearchConfig>,
+    pub restarts: Option<usize>,
+    pub seed: Option<u64>,
+    pub max_time: Option<u64>,
+    pub threads: Option<usize>,
+    pub encode_threads: Option<usize>,
+    pub initialization: Option<Initialization>,
+    pub record: Option<bool>,
+    pub freezing: Option<Vec<usize>>,
+    pub genetic_algorithm: Option<genetic_algorithm::Parameters>,
+    pub tabu_search: Option<tabu_search::Parameters>,
+    pub parallel_tempering: Option<parallel_tempering::Parameters>,
+    pub hill_climbing: Option<hill_climbing::Parameters>,
+    pub nsga2: Option<nsga2::Parameters>,
+    pub memetic: Option<memetic::Parameters>,
+    pub ant_colony: Option<ant_colony::Parameters>,
+    pub beam_search: Option<beam_search::Parameters>,
+    pub exhaustive: Option<exhaustive::Parameters>,
 }
 
 #[skip_serializing_none]
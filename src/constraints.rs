@@ -1,70 +1,246 @@
 //! 优化问题的约束。
 
 use crate::{
-    config::{AtomicConstraint, MappedKey},
+    config::{AtomicConstraint, KeyCondition, Mapped, MappedKey, Relation, RelationConstraint},
     error::Error,
-    representation::{assemble, Element, Key, KeyMap, Representation},
+    objectives::fingering::Keyboard,
+    representation::{assemble, is_symbol, Element, Key, KeyMap, Representation},
 };
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use rand::{seq::SliceRandom, Rng};
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
+use std::iter::zip;
 
+/// 变异算子产生的方案违反关系约束时，最多重新尝试的次数
+const MAX_ATTEMPTS: usize = 100;
+
+/// 一条原子约束编译的结果：受约束的元素，允许的按键（None 表示固定在初始方案的按键上），以及软约束的权重（None 表示硬约束）
+type CompiledConstraint = (Vec<Element>, Option<Vec<Key>>, Option<f64>);
+
+/// 软约束：元素不在允许的按键上时，在目标函数中按权重计入惩罚
+#[derive(Debug, Clone)]
+pub struct SoftConstraint {
+    pub element: Element,
+    pub keys: Vec<Key>,
+    pub weight: f64,
+}
+
+/// 条件约束：element 在 keys 中的某个键上时，then_element 必须在 then_keys 中的某个键上
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    pub element: Element,
+    pub keys: Vec<Key>,
+    pub then_element: Element,
+    pub then_keys: Vec<Key>,
+}
+
+impl Conditional {
+    pub fn violated(&self, map: &KeyMap) -> bool {
+        self.keys.contains(&map[self.element]) && !self.then_keys.contains(&map[self.then_element])
+    }
+}
+
+#[derive(Clone)]
 pub struct Constraints {
     pub alphabet: Vec<Key>,
     pub elements: usize,
     pub fixed: HashSet<Element>,
     pub narrowed: HashMap<Element, Vec<Key>>,
+    pub movable: Vec<Element>,
+    // 必须同键的元素组，不在任何组中的元素自成一组；group 是每个元素所在组的序号
+    pub groups: Vec<Vec<Element>>,
+    pub group: Vec<usize>,
+    // 不能同键的元素对
+    pub different: Vec<(Element, Element)>,
+    // 每个键上最多可以放的元素数
+    pub capacity: Option<usize>,
+    // 条件约束，与不同键的约束一样在变异时检查
+    pub conditionals: Vec<Conditional>,
+    // 按可行按键把可移动的元素分桶；accepting 是每个按键对应的、可行按键包含它的桶的序号
+    pub buckets: Vec<(Vec<Key>, Vec<Element>)>,
+    pub accepting: Vec<Vec<usize>>,
 }
 
 impl Constraints {
-    /// 传入配置表示来构造约束，把用户在配置文件中编写的约束「编译」成便于快速计算的数据结构
-    pub fn new(representation: &Representation) -> Result<Constraints, Error> {
-        let elements = representation.initial.len();
-        let alphabet = representation
+    fn alphabet(representation: &Representation) -> Vec<Key> {
+        representation
             .config
             .form
             .alphabet
             .chars()
             .map(|x| *representation.key_repr.get(&x).unwrap()) // 在生成表示的时候已经确保了这里一定有对应的键
-            .collect();
-        let mut fixed: HashSet<Element> = HashSet::new();
-        let mut narrowed: HashMap<Element, Vec<Key>> = HashMap::new();
-        let mut values: Vec<AtomicConstraint> = Vec::new();
+            .collect()
+    }
+
+    /// 约束中的元素名称可以是一个模式，以便用一条约束覆盖一族元素：以 `re:` 开头的是正则表达式，以 `glob:` 开头的是通配符，
+    /// `*` 匹配任意多个字符，`?` 匹配一个字符。模式与元素名称整体匹配，没有这两种前缀的名称按字面理解，返回 None
+    fn pattern(element: &str) -> Result<Option<Regex>, Error> {
+        let expression = if let Some(inner) = element.strip_prefix("re:") {
+            format!("^(?:{inner})$")
+        } else if let Some(glob) = element.strip_prefix("glob:") {
+            let mut expression = String::from("^");
+            for c in glob.chars() {
+                match c {
+                    '*' => expression.push_str(".*"),
+                    '?' => expression.push('.'),
+                    _ => expression.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            expression.push('$');
+            expression
+        } else {
+            return Ok(None);
+        };
+        let pattern =
+            Regex::new(&expression).map_err(|e| format!("约束中的模式 {element} 无效：{e}"))?;
+        Ok(Some(pattern))
+    }
+
+    /// 键盘映射中所有与模式匹配的元素的名称。模式与每个元素每一码的完整名称匹配，例如「口.*」匹配「口」的第二码及以后；
+    /// 给出码位时只选取这一码，没有模式时选取所有元素
+    fn matching(
+        mapping: &HashMap<String, Mapped>,
+        pattern: Option<&Regex>,
+        index: Option<usize>,
+    ) -> Vec<String> {
+        let mut names = Vec::new();
+        for (key, value) in mapping {
+            if is_symbol(key) {
+                continue;
+            }
+            for (i, x) in value.normalize().iter().enumerate() {
+                let name = assemble(key, i);
+                if matches!(x, MappedKey::Ascii(_))
+                    && index.is_none_or(|index| index == i)
+                    && pattern.is_none_or(|pattern| pattern.is_match(&name))
+                {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// 把关系约束和条件约束中的一个元素名称解析为元素：带有 `re:` 或 `glob:` 前缀的模式展开为所有匹配的元素（与原子约束中的模式一样），
+    /// 其他名称按字面查找
+    fn resolve(representation: &Representation, name: &str) -> Result<Vec<Element>, Error> {
+        let lookup = |x: &str| {
+            let element = representation.element_repr.get(x).copied();
+            element.ok_or(Error::from(format!("约束中的元素 {x} 不在键盘映射中")))
+        };
+        let Some(pattern) = Self::pattern(name)? else {
+            return Ok(vec![lookup(name)?]);
+        };
+        let mapping = &representation.config.form.mapping;
+        let mut elements = Vec::new();
+        for x in Self::matching(mapping, Some(&pattern), None) {
+            elements.push(lookup(&x)?);
+        }
+        if elements.is_empty() {
+            return Err(format!("约束中的模式 {name} 没有匹配到任何元素").into());
+        }
+        elements.sort();
+        Ok(elements)
+    }
+
+    /// 关系约束中的所有元素，模式展开之后去掉重复的元素
+    fn resolve_all(
+        representation: &Representation,
+        names: &[String],
+    ) -> Result<Vec<Element>, Error> {
+        let mut members = Vec::new();
+        for name in names {
+            members.extend(Self::resolve(representation, name)?);
+        }
+        members.sort();
+        members.dedup();
+        Ok(members)
+    }
+
+    /// 把约束中的键转换成内部表示，这些键必须在字母表中
+    fn transform_keys(representation: &Representation, keys: &[char]) -> Result<Vec<Key>, Error> {
+        let mut transformed = Vec::new();
+        for key in keys {
+            if !representation.config.form.alphabet.contains(*key) {
+                return Err(format!("约束中的键 {key} 不在字母表中").into());
+            }
+            transformed.push(representation.key_repr[key]);
+        }
+        Ok(transformed)
+    }
+
+    /// 把键盘区域展开成字母表中属于这些区域的键，区域中不在字母表里的键直接忽略
+    fn expand_zones(representation: &Representation, zones: &[String]) -> Result<Vec<Key>, Error> {
+        let keyboard = &representation.config.optimization.objective.keyboard;
+        let keyboard = Keyboard::new(keyboard.as_ref());
+        let mut keys = Vec::new();
+        for zone in zones {
+            let zone_keys = keyboard
+                .zone(zone)
+                .ok_or(format!("无法识别约束中的键盘区域 {zone}"))?;
+            for key in zone_keys {
+                if representation.config.form.alphabet.contains(key) {
+                    keys.push(representation.key_repr[&key]);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// 配置文件中所有的原子约束，以及它们在配置文件中的路径
+    fn atomic_constraints(representation: &Representation) -> Vec<(String, AtomicConstraint)> {
+        let mut values = Vec::new();
+        if let Some(constraints) = &representation.config.optimization.constraints {
+            let kinds = [
+                ("elements", &constraints.elements),
+                ("indices", &constraints.indices),
+                ("element_indices", &constraints.element_indices),
+            ];
+            for (name, kind) in kinds {
+                for (index, value) in kind.iter().flatten().enumerate() {
+                    let path = format!("optimization.constraints.{name}[{index}]");
+                    values.push((path, value.clone()));
+                }
+            }
+        }
+        values
+    }
+
+    /// 把配置文件中的原子约束逐条编译成受约束的元素、允许的按键和软约束的权重，每一条约束各自给出编译的结果
+    fn compile_each(
+        representation: &Representation,
+    ) -> Vec<(String, Result<CompiledConstraint, Error>)> {
+        let alphabet = Self::alphabet(representation);
         let lookup = |x: String| {
             let element_number = representation.element_repr.get(&x);
             element_number.ok_or(format!("{x} 不存在于键盘映射中"))
         };
-        if let Some(constraints) = &representation.config.optimization.constraints {
-            values.append(&mut constraints.elements.clone().unwrap_or_default());
-            values.append(&mut constraints.indices.clone().unwrap_or_default());
-            values.append(&mut constraints.element_indices.clone().unwrap_or_default());
-        }
+        let transform = |keys: &Vec<char>| Self::transform_keys(representation, keys);
+        let expand = |zones: &Vec<String>| Self::expand_zones(representation, zones);
         let mapping = &representation.config.form.mapping;
-        for atomic_constraint in &values {
+        let compile = |atomic_constraint: &AtomicConstraint| -> Result<CompiledConstraint, Error> {
             let AtomicConstraint {
                 element,
                 index,
                 keys,
+                zones,
+                exclude_keys,
+                exclude_zones,
+                soft,
+                weight,
             } = atomic_constraint;
+            let pattern = match element {
+                Some(element) => Self::pattern(element)?,
+                None => None,
+            };
             let elements: Vec<usize> = match (element, index) {
                 // 如果指定了元素和码位
-                (Some(element), Some(index)) => {
+                (Some(element), Some(index)) if pattern.is_none() => {
                     let element = *lookup(assemble(element, *index))?;
                     vec![element]
                 }
-                // 如果指定了码位
-                (None, Some(index)) => {
-                    let mut elements = Vec::new();
-                    for (key, value) in mapping {
-                        let normalized = value.normalize();
-                        if let Some(MappedKey::Ascii(_)) = normalized.get(*index) {
-                            let element = *lookup(assemble(key, *index))?;
-                            elements.push(element);
-                        }
-                    }
-                    elements
-                }
                 // 如果指定了元素
-                (Some(element), None) => {
+                (Some(element), None) if pattern.is_none() => {
                     let mapped = mapping
                         .get(element)
                         .ok_or(format!("约束中的元素 {element} 不在键盘映射中"))?;
@@ -76,54 +252,737 @@ impl Constraints {
                     }
                     elements
                 }
+                // 如果指定了元素的模式，或者只指定了码位，就在键盘映射中查找所有匹配的元素
+                (Some(_), _) | (None, Some(_)) => {
+                    let mut elements = Vec::new();
+                    for name in Self::matching(mapping, pattern.as_ref(), *index) {
+                        elements.push(*lookup(name)?);
+                    }
+                    if let (Some(element), true) = (element, elements.is_empty()) {
+                        return Err(format!("约束中的模式 {element} 没有匹配到任何元素").into());
+                    }
+                    elements
+                }
                 _ => return Err("约束必须至少提供 element 或 index 之一".into()),
             };
-            for element in elements {
-                if let Some(keys) = keys {
-                    let mut transformed = Vec::new();
-                    for key in keys {
-                        transformed.push(
-                            *representation
-                                .key_repr
-                                .get(key)
-                                .ok_or(format!("约束中的键 {key} 不在键盘映射中"))?,
-                        );
+            let mut allowed = keys.as_ref().map(transform).transpose()?;
+            if let Some(zones) = zones {
+                let mut keys = allowed.unwrap_or_default();
+                for key in expand(zones)? {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                allowed = Some(keys);
+            }
+            if exclude_keys.is_some() || exclude_zones.is_some() {
+                let mut excluded = match exclude_keys {
+                    Some(keys) => transform(keys)?,
+                    None => vec![],
+                };
+                if let Some(zones) = exclude_zones {
+                    excluded.extend(expand(zones)?);
+                }
+                let keys = allowed.unwrap_or_else(|| alphabet.clone());
+                allowed = Some(keys.into_iter().filter(|x| !excluded.contains(x)).collect());
+            }
+            if allowed.as_ref().is_some_and(|x| x.is_empty()) {
+                return Err("约束中的键列表不能为空".into());
+            }
+            let weight = match (soft, weight) {
+                (Some(true), weight) => Some(weight.unwrap_or(1.0)),
+                (_, None) => None,
+                (_, Some(_)) => return Err("只有软约束才能设置权重 weight".into()),
+            };
+            Ok((elements, allowed, weight))
+        };
+        Self::atomic_constraints(representation)
+            .into_iter()
+            .map(|(path, value)| {
+                let result = compile(&value);
+                (path, result)
+            })
+            .collect()
+    }
+
+    /// 编译所有的原子约束，遇到第一条有错误的约束时报告它的路径和错误
+    fn compile(
+        representation: &Representation,
+    ) -> Result<Vec<(String, CompiledConstraint)>, Error> {
+        let mut compiled = Vec::new();
+        for (path, result) in Self::compile_each(representation) {
+            let value = result.map_err(|e| format!("{path}：{}", e.message))?;
+            compiled.push((path, value));
+        }
+        Ok(compiled)
+    }
+
+    /// 固定或者窄化了一个元素的约束在配置文件中的路径
+    fn origin(sources: &HashMap<Element, Vec<String>>, element: Element) -> String {
+        match sources.get(&element) {
+            Some(paths) => paths.join("、"),
+            None => "关系约束".to_string(),
+        }
+    }
+
+    fn key_names(representation: &Representation, keys: &[Key]) -> String {
+        keys.iter().map(|x| representation.repr_key[x]).collect()
+    }
+
+    /// 约束无法同时满足时的错误，给出冲突的约束和一个能够消除冲突的最小放宽
+    fn infeasible(problem: String, suggestion: String) -> Error {
+        format!("约束无法同时满足：{problem}。修复建议：{suggestion}").into()
+    }
+
+    /// 配置文件中所有的关系约束，以及它们在配置文件中的路径。元素组等价于组内元素必须同键的关系约束
+    fn relations(representation: &Representation) -> Vec<(String, RelationConstraint)> {
+        let config = representation.config.optimization.constraints.as_ref();
+        let mut values = Vec::new();
+        let relations = config.and_then(|x| x.relations.as_ref());
+        for (index, relation) in relations.iter().copied().flatten().enumerate() {
+            let path = format!("optimization.constraints.relations[{index}]");
+            values.push((path, relation.clone()));
+        }
+        let groups = config.and_then(|x| x.groups.as_ref());
+        for (index, elements) in groups.iter().copied().flatten().enumerate() {
+            let path = format!("optimization.constraints.groups[{index}]");
+            let relation = RelationConstraint {
+                elements: elements.clone(),
+                relation: Relation::Same,
+            };
+            values.push((path, relation));
+        }
+        values
+    }
+
+    /// 编译配置文件中所有的条件约束，以及它们在配置文件中的路径
+    fn conditionals(representation: &Representation) -> Result<Vec<(String, Conditional)>, Error> {
+        let config = representation.config.optimization.constraints.as_ref();
+        let compile = |condition: &KeyCondition| -> Result<(Vec<Element>, Vec<Key>), Error> {
+            let KeyCondition { element, keys } = condition;
+            let elements = Self::resolve(representation, element)?;
+            if keys.is_empty() {
+                return Err("约束中的键列表不能为空".into());
+            }
+            Ok((elements, Self::transform_keys(representation, keys)?))
+        };
+        let mut values = Vec::new();
+        let conditionals = config.and_then(|x| x.conditionals.as_ref());
+        for (index, conditional) in conditionals.iter().copied().flatten().enumerate() {
+            let path = format!("optimization.constraints.conditionals[{index}]");
+            let result = compile(&conditional.condition).and_then(|condition| {
+                Ok((condition, compile(&conditional.consequence)?))
+            });
+            let ((elements, keys), (then_elements, then_keys)) =
+                result.map_err(|e| format!("{path}：{}", e.message))?;
+            // 模式匹配到多个元素时，任何一个 if 中的元素在给出的键上，每一个 then 中的元素都必须在给出的键上
+            for element in &elements {
+                for then_element in &then_elements {
+                    let value = Conditional {
+                        element: *element,
+                        keys: keys.clone(),
+                        then_element: *then_element,
+                        then_keys: then_keys.clone(),
+                    };
+                    values.push((path.clone(), value));
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// 检查配置文件中的约束，列出发现的所有问题，每个问题都带有它在配置文件中的路径：
+    /// 无法编译的原子约束（元素、键或者键盘区域不存在，可行的按键为空等），当前布局不满足的硬约束，以及编译约束时的其他错误
+    pub fn validate(representation: &Representation) -> Vec<String> {
+        let mut problems = Vec::new();
+        let initial = &representation.initial;
+        let describe = |element: Element| {
+            let name = &representation.repr_element[&element];
+            let key = representation.repr_key[&initial[element]];
+            format!("元素 {name} 当前在键 {key} 上")
+        };
+        // 有原子约束无法编译时，编译约束时的其他错误只会重复报告其中的第一条
+        let mut compiled = true;
+        for (path, result) in Self::compile_each(representation) {
+            match result {
+                Err(error) => {
+                    problems.push(format!("{path}：{}", error.message));
+                    compiled = false;
+                }
+                Ok((members, Some(allowed), None)) => {
+                    for element in members {
+                        if !allowed.contains(&initial[element]) {
+                            let current = describe(element);
+                            problems.push(format!("{path}：{current}，不在这条约束允许的按键中"));
+                        }
                     }
-                    if transformed.len() == 0 {
-                        return Err("约束中的键列表不能为空".into());
+                }
+                _ => {}
+            }
+        }
+        for (path, relation) in Self::relations(representation) {
+            let Ok(members) = Self::resolve_all(representation, &relation.elements) else {
+                continue;
+            };
+            for (i, first) in members.iter().enumerate() {
+                for second in &members[i + 1..] {
+                    let together = initial[*first] == initial[*second];
+                    let violated = match relation.relation {
+                        Relation::Same => !together,
+                        Relation::Different => together,
+                    };
+                    if violated {
+                        let (first, second) = (describe(*first), describe(*second));
+                        problems.push(format!("{path}：{first}，{second}，不满足这条关系约束"));
                     }
-                    narrowed.insert(element, transformed);
+                }
+            }
+        }
+        if let Ok(conditionals) = Self::conditionals(representation) {
+            for (path, conditional) in conditionals {
+                if conditional.violated(initial) {
+                    let (first, second) = (
+                        describe(conditional.element),
+                        describe(conditional.then_element),
+                    );
+                    problems.push(format!("{path}：{first}，{second}，不满足这条条件约束"));
+                }
+            }
+        }
+        if compiled {
+            match Self::new(representation) {
+                Err(error) => problems.push(error.message),
+                Ok(constraints) => {
+                    let overflow = constraints.overflow(initial);
+                    if overflow > 0 {
+                        let path = "optimization.constraints.max_elements_per_key";
+                        problems.push(format!(
+                            "{path}：当前布局中共有 {overflow} 个元素超出了按键的容量"
+                        ));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    /// 编译所有的软约束。固定的软约束以初始方案中的按键为允许的按键
+    pub fn soft(representation: &Representation) -> Result<Vec<SoftConstraint>, Error> {
+        let mut soft = Vec::new();
+        for (_, (members, allowed, weight)) in Self::compile(representation)? {
+            let Some(weight) = weight else {
+                continue;
+            };
+            for element in members {
+                let keys = allowed
+                    .clone()
+                    .unwrap_or_else(|| vec![representation.initial[element]]);
+                soft.push(SoftConstraint {
+                    element,
+                    keys,
+                    weight,
+                });
+            }
+        }
+        Ok(soft)
+    }
+
+    /// 传入配置表示来构造约束，把用户在配置文件中编写的约束「编译」成便于快速计算的数据结构
+    pub fn new(representation: &Representation) -> Result<Constraints, Error> {
+        let elements = representation.initial.len();
+        let alphabet = Self::alphabet(representation);
+        let mut fixed: HashSet<Element> = HashSet::new();
+        let mut narrowed: HashMap<Element, Vec<Key>> = HashMap::new();
+        // 每个元素受到哪些约束的固定或者窄化，用于在约束无法同时满足时指出冲突的约束
+        let mut sources: HashMap<Element, Vec<String>> = HashMap::new();
+        for (path, (members, allowed, weight)) in Self::compile(representation)? {
+            // 软约束不限制搜索空间，只在目标函数中计入惩罚
+            if weight.is_some() {
+                continue;
+            }
+            for element in members {
+                sources.entry(element).or_default().push(path.clone());
+                if let Some(allowed) = &allowed {
+                    narrowed.insert(element, allowed.clone());
                 } else {
                     fixed.insert(element);
                 }
             }
         }
-        Ok(Constraints {
+        // 冻结的键：当前在这些键上的元素全部固定，其他元素也不能移到这些键上
+        let config = representation.config.optimization.constraints.as_ref();
+        let mut frozen = Vec::new();
+        if let Some(keys) = config.and_then(|x| x.frozen_keys.as_ref()) {
+            let keys = Self::transform_keys(representation, keys);
+            frozen.extend(
+                keys.map_err(|e| format!("optimization.constraints.frozen_keys：{}", e.message))?,
+            );
+        }
+        if let Some(zones) = config.and_then(|x| x.frozen_zones.as_ref()) {
+            let keys = Self::expand_zones(representation, zones);
+            frozen.extend(
+                keys.map_err(|e| format!("optimization.constraints.frozen_zones：{}", e.message))?,
+            );
+        }
+        if !frozen.is_empty() {
+            let kinds = [
+                (
+                    "frozen_keys",
+                    config.and_then(|x| x.frozen_keys.as_ref()).is_some(),
+                ),
+                (
+                    "frozen_zones",
+                    config.and_then(|x| x.frozen_zones.as_ref()).is_some(),
+                ),
+            ];
+            let paths: Vec<String> = kinds
+                .iter()
+                .filter(|(_, present)| *present)
+                .map(|(kind, _)| format!("optimization.constraints.{kind}"))
+                .collect();
+            let path = paths.join("、");
+            for element in 0..elements {
+                if frozen.contains(&representation.initial[element]) {
+                    sources.entry(element).or_default().push(path.clone());
+                    fixed.insert(element);
+                    continue;
+                }
+                if fixed.contains(&element) {
+                    continue;
+                }
+                let allowed = narrowed.get(&element).unwrap_or(&alphabet);
+                let keys: Vec<Key> = allowed
+                    .iter()
+                    .filter(|x| !frozen.contains(x))
+                    .copied()
+                    .collect();
+                if keys.is_empty() {
+                    let name = &representation.repr_element[&element];
+                    let names = Self::key_names(representation, allowed);
+                    let origin = Self::origin(&sources, element);
+                    return Err(Self::infeasible(
+                        format!("元素 {name} 的可行按键 {names} 都被冻结了"),
+                        format!(
+                            "从冻结的键中去掉 {names} 之一，或者放宽 {origin} 中对 {name} 的约束"
+                        ),
+                    ));
+                }
+                sources.entry(element).or_default().push(path.clone());
+                narrowed.insert(element, keys);
+            }
+        }
+        let mut group: Vec<usize> = (0..elements).collect();
+        let mut different = Vec::new();
+        let mut different_paths = Vec::new();
+        for (path, relation) in Self::relations(representation) {
+            let members = Self::resolve_all(representation, &relation.elements)
+                .map_err(|e| format!("{path}：{}", e.message))?;
+            if members.len() < 2 {
+                return Err(format!("{path}：关系约束至少需要两个元素").into());
+            }
+            for (i, first) in members.iter().enumerate() {
+                for second in &members[i + 1..] {
+                    match relation.relation {
+                        Relation::Same => Self::union(&mut group, *first, *second),
+                        Relation::Different => {
+                            different.push((*first, *second));
+                            different_paths.push(path.clone());
+                        }
+                    }
+                }
+            }
+        }
+        let mut groups: Vec<Vec<Element>> = vec![vec![]; elements];
+        for element in 0..elements {
+            groups[Self::find(&mut group, element)].push(element);
+        }
+        groups.retain(|x| !x.is_empty());
+        for (index, members) in groups.iter().enumerate() {
+            for element in members {
+                group[*element] = index;
+            }
+        }
+        // 同键的元素作为一个整体移动：只要有一个被固定，整组都固定；可行的按键取它们的交集
+        let repr_element = &representation.repr_element;
+        for members in groups.iter().filter(|x| x.len() > 1) {
+            let names: Vec<&str> = members.iter().map(|x| repr_element[x].as_str()).collect();
+            let names = names.join("、");
+            let pinned: Vec<Element> = members
+                .iter()
+                .filter(|x| fixed.contains(x))
+                .copied()
+                .collect();
+            let mut keys = alphabet.clone();
+            for element in members {
+                if let Some(allowed) = narrowed.get(element) {
+                    keys.retain(|x| allowed.contains(x));
+                }
+            }
+            if keys.is_empty() {
+                // 找出去掉之后就有共同可行按键的那个窄化约束
+                let relaxable = members.iter().find(|x| {
+                    let others = members.iter().filter(|y| y != x);
+                    alphabet.iter().any(|key| {
+                        others
+                            .clone()
+                            .all(|y| narrowed.get(y).is_none_or(|z| z.contains(key)))
+                    })
+                });
+                let suggestion = match relaxable {
+                    Some(element) => {
+                        let origin = Self::origin(&sources, *element);
+                        format!("去掉 {origin} 中对 {} 的约束", repr_element[element])
+                    }
+                    None => "放宽其中至少两个元素的约束".to_string(),
+                };
+                let problem = format!("必须同键的元素 {names} 没有共同的可行按键");
+                return Err(Self::infeasible(problem, suggestion));
+            }
+            if let Some(first) = pinned.first() {
+                let key = representation.initial[*first];
+                let conflicting = pinned.iter().find(|x| representation.initial[**x] != key);
+                if let Some(second) = conflicting {
+                    let problem = format!(
+                        "必须同键的元素 {names} 中，{} 和 {} 被固定在不同的键上",
+                        repr_element[first], repr_element[second]
+                    );
+                    let origin = Self::origin(&sources, *second);
+                    let suggestion = format!("解除 {origin} 中对 {} 的固定", repr_element[second]);
+                    return Err(Self::infeasible(problem, suggestion));
+                }
+                if !keys.contains(&key) {
+                    let problem = format!(
+                        "必须同键的元素 {names} 中，{} 被固定的键 {} 不在其他元素的可行按键中",
+                        repr_element[first], representation.repr_key[&key]
+                    );
+                    let origin = Self::origin(&sources, *first);
+                    let suggestion = format!("解除 {origin} 中对 {} 的固定", repr_element[first]);
+                    return Err(Self::infeasible(problem, suggestion));
+                }
+                fixed.extend(members.iter().copied());
+            }
+            if members.iter().any(|x| narrowed.contains_key(x)) {
+                for element in members {
+                    narrowed.insert(*element, keys.clone());
+                }
+            }
+        }
+        // 每个元素唯一可行的按键，固定的元素就是它当前的按键
+        let only = |element: Element| {
+            if fixed.contains(&element) {
+                return Some(representation.initial[element]);
+            }
+            narrowed
+                .get(&element)
+                .filter(|x| x.len() == 1)
+                .map(|x| x[0])
+        };
+        for ((first, second), path) in zip(&different, &different_paths) {
+            let (name1, name2) = (&repr_element[first], &repr_element[second]);
+            if group[*first] == group[*second] {
+                let problem = format!("元素 {name1} 和 {name2} 既要同键又要不同键");
+                let suggestion = format!("从 {path} 中去掉其中一个，或者不再要求它们同键");
+                return Err(Self::infeasible(problem, suggestion));
+            }
+            if let (Some(key), true) = (only(*first), only(*first) == only(*second)) {
+                let key = representation.repr_key[&key];
+                let problem =
+                    format!("元素 {name1} 和 {name2} 要求不同键，但都只能放在键 {key} 上");
+                let origin = Self::origin(&sources, *second);
+                let suggestion =
+                    format!("放宽 {origin} 中对 {name2} 的约束，或者从 {path} 中去掉其中一个");
+                return Err(Self::infeasible(problem, suggestion));
+            }
+        }
+        let capacity = config.and_then(|x| x.max_elements_per_key);
+        if let Some(capacity) = capacity {
+            let path = "optimization.constraints.max_elements_per_key";
+            let keys = alphabet.len();
+            if elements > capacity * keys {
+                let problem =
+                    format!("{elements} 个元素无法放进 {keys} 个键，每个键最多 {capacity} 个元素");
+                let minimum = elements.div_ceil(keys);
+                let suggestion = format!("把 {path} 放宽到至少 {minimum}");
+                return Err(Self::infeasible(problem, suggestion));
+            }
+            let mut occupants: Vec<Vec<Element>> = vec![vec![]; representation.radix];
+            for element in &fixed {
+                occupants[representation.initial[*element]].push(*element);
+            }
+            for (key, members) in occupants.iter_mut().enumerate() {
+                let count = members.len();
+                if count > capacity {
+                    members.sort();
+                    let key = representation.repr_key[&key];
+                    let names: Vec<&str> =
+                        members.iter().map(|x| repr_element[x].as_str()).collect();
+                    let names = names.join("、");
+                    let problem = format!(
+                        "键 {key} 上固定了 {names} 共 {count} 个元素，超过了每个键最多 {capacity} 个元素的限制"
+                    );
+                    let mut origins: Vec<String> =
+                        members.iter().map(|x| Self::origin(&sources, *x)).collect();
+                    origins.dedup();
+                    let origins = origins.join("、");
+                    let excess = count - capacity;
+                    let suggestion = format!(
+                        "把 {path} 放宽到至少 {count}，或者在 {origins} 中解除其中 {excess} 个元素的固定"
+                    );
+                    return Err(Self::infeasible(problem, suggestion));
+                }
+            }
+        }
+        let mut conditionals = Vec::new();
+        for (path, conditional) in Self::conditionals(representation)? {
+            // 前一个元素固定在条件的键上时，后一个元素必须能放到要求的键上
+            let Conditional {
+                element,
+                then_element,
+                ..
+            } = conditional;
+            if only(element).is_some_and(|x| conditional.keys.contains(&x)) {
+                let feasible = match only(then_element) {
+                    Some(key) => conditional.then_keys.contains(&key),
+                    None => narrowed
+                        .get(&then_element)
+                        .unwrap_or(&alphabet)
+                        .iter()
+                        .any(|x| conditional.then_keys.contains(x)),
+                };
+                if !feasible {
+                    let (name1, name2) = (&repr_element[&element], &repr_element[&then_element]);
+                    let keys = Self::key_names(representation, &conditional.then_keys);
+                    let problem =
+                        format!("元素 {name1} 被固定在条件的键上，但 {name2} 不能放在 {keys} 上");
+                    let origin = Self::origin(&sources, then_element);
+                    let suggestion = format!("放宽 {origin} 中对 {name2} 的约束，或者去掉 {path}");
+                    return Err(Self::infeasible(problem, suggestion));
+                }
+            }
+            conditionals.push(conditional);
+        }
+        // 预先列出所有可移动的元素，这样采样时不需要反复拒绝固定的元素
+        let movable: Vec<Element> = (0..elements).filter(|x| !fixed.contains(x)).collect();
+        if movable.is_empty() {
+            return Err("所有元素都被固定了，没有可以优化的元素".into());
+        }
+        let mut constraints = Constraints {
             alphabet,
             elements,
             fixed,
             narrowed,
-        })
+            movable,
+            groups,
+            group,
+            different,
+            capacity,
+            conditionals,
+            buckets: vec![],
+            accepting: vec![],
+        };
+        constraints.bucketize();
+        Ok(constraints)
+    }
+
+    /// 按可行按键把可移动的元素分桶，并为每个按键列出可以移到它上面的桶，可移动的元素变化时需要重新分桶
+    fn bucketize(&mut self) {
+        let mut index: HashMap<&Vec<Key>, usize> = HashMap::new();
+        let mut buckets: Vec<(Vec<Key>, Vec<Element>)> = Vec::new();
+        for element in &self.movable {
+            let destinations = self.narrowed.get(element).unwrap_or(&self.alphabet);
+            let i = *index.entry(destinations).or_insert_with(|| {
+                buckets.push((destinations.clone(), vec![]));
+                buckets.len() - 1
+            });
+            buckets[i].1.push(*element);
+        }
+        let radix = self.alphabet.iter().max().map_or(0, |x| x + 1);
+        let mut accepting = vec![vec![]; radix];
+        for (i, (keys, _)) in buckets.iter().enumerate() {
+            for key in keys {
+                accepting[*key].push(i);
+            }
+        }
+        self.buckets = buckets;
+        self.accepting = accepting;
+    }
+
+    fn find(parent: &mut [usize], element: usize) -> usize {
+        let mut root = element;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[element] = root;
+        root
+    }
+
+    fn union(parent: &mut [usize], first: usize, second: usize) {
+        let (first, second) = (Self::find(parent, first), Self::find(parent, second));
+        parent[first.max(second)] = first.min(second);
+    }
+
+    /// 与这个元素必须同键的所有元素，包括它自己
+    pub fn members(&self, element: Element) -> &[Element] {
+        &self.groups[self.group[element]]
+    }
+
+    /// 把一个元素连同与它同键的所有元素放到某个按键上
+    pub fn place(&self, map: &mut KeyMap, element: Element, key: Key) {
+        for member in self.members(element) {
+            map[*member] = key;
+        }
+    }
+
+    /// 贪心地放置元素时，把一个元素连同与它同键的元素放到某个按键上是否满足约束。
+    /// 只与已经放好的元素比较，placed 中为 false 的元素还没有放好，不参与判断
+    pub fn admits(&self, map: &KeyMap, placed: &[bool], element: Element, key: Key) -> bool {
+        self.admits_with(|x| placed[x].then_some(map[x]), element, key)
+    }
+
+    /// 与 `admits` 相同，但是由 position 给出已经放好的元素所在的按键，还没有放好的元素返回 None。
+    /// 构造式算法用它在部分方案上判断一个选择是否可行，不需要先组装出完整的方案
+    pub fn admits_with(
+        &self,
+        position: impl Fn(Element) -> Option<Key>,
+        element: Element,
+        key: Key,
+    ) -> bool {
+        let members = self.members(element);
+        // 已经放好、又不随这个元素一起移动的元素所在的按键
+        let settled = |x: Element| {
+            if members.contains(&x) {
+                None
+            } else {
+                position(x)
+            }
+        };
+        let together = self.different.iter().any(|(x, y)| {
+            members.contains(x) && settled(*y) == Some(key)
+                || members.contains(y) && settled(*x) == Some(key)
+        });
+        let full = self.capacity.is_some_and(|capacity| {
+            let occupied = (0..self.elements).filter(|x| settled(*x) == Some(key)).count();
+            occupied + members.len() > capacity
+        });
+        // 只检查涉及这个元素的条件约束，两个元素都已经放好时才能判断
+        let position = |x: Element| {
+            if members.contains(&x) {
+                Some(key)
+            } else {
+                position(x)
+            }
+        };
+        let conditional = self.conditionals.iter().any(|x| {
+            match (position(x.element), position(x.then_element)) {
+                (Some(first), Some(second)) => {
+                    (members.contains(&x.element) || members.contains(&x.then_element))
+                        && x.keys.contains(&first)
+                        && !x.then_keys.contains(&second)
+                }
+                _ => false,
+            }
+        });
+        !together && !full && !conditional
+    }
+
+    /// 把每个元素组都放到同一个键上：组里有固定的元素时以它的按键为准，否则以组里第一个元素的按键为准，这个按键不可行时改用第一个可行的按键
+    pub fn align(&self, map: &mut KeyMap) {
+        for members in self.groups.iter().filter(|x| x.len() > 1) {
+            let leader = members
+                .iter()
+                .find(|x| self.fixed.contains(x))
+                .unwrap_or(&members[0]);
+            let destinations = self.narrowed.get(leader).unwrap_or(&self.alphabet);
+            let key = if self.fixed.contains(leader) || destinations.contains(&map[*leader]) {
+                map[*leader]
+            } else {
+                destinations[0]
+            };
+            self.place(map, *leader, key);
+        }
     }
 
-    fn get_movable_element(&self) -> usize {
-        let mut rng = thread_rng();
-        loop {
-            let key = rng.gen_range(0..self.elements);
-            if !self.fixed.contains(&key) {
-                return key;
+    /// 一个方案违反关系约束、条件约束和按键容量的次数：同组的元素不在同一个键上，不能同键的元素在同一个键上，条件成立但结论不成立的条件约束，以及每个键上超出容量的元素数
+    pub fn violations(&self, map: &KeyMap) -> usize {
+        let separated = self
+            .groups
+            .iter()
+            .filter(|x| x.iter().any(|y| map[*y] != map[x[0]]))
+            .count();
+        let together = self
+            .different
+            .iter()
+            .filter(|(x, y)| map[*x] == map[*y])
+            .count();
+        let conditional = self.conditionals.iter().filter(|x| x.violated(map)).count();
+        separated + together + conditional + self.overflow(map)
+    }
+
+    /// 所有键上超出容量的元素数之和
+    pub fn overflow(&self, map: &KeyMap) -> usize {
+        let Some(capacity) = self.capacity else {
+            return 0;
+        };
+        let mut counts = vec![0_usize; self.alphabet.len() + 1];
+        for key in map {
+            if let Some(count) = counts.get_mut(*key) {
+                *count += 1;
             }
         }
+        counts.iter().map(|x| x.saturating_sub(capacity)).sum()
     }
 
-    fn get_swappable_element(&self) -> usize {
-        let mut rng = thread_rng();
-        loop {
-            let key = rng.gen_range(0..self.elements);
-            if !self.fixed.contains(&key) {
-                return key;
+    /// 反复调用变异算子，直到得到的方案违反约束的次数不多于原方案；多次尝试都不成功时保持原方案不变。
+    /// 这样满足约束的方案总是变异成满足约束的方案，而不满足约束的初始方案也会逐渐被修复
+    fn with_retry<R: Rng + ?Sized>(
+        &self,
+        map: &KeyMap,
+        rng: &mut R,
+        operator: impl Fn(&mut R) -> KeyMap,
+    ) -> KeyMap {
+        if self.groups.len() == self.elements
+            && self.different.is_empty()
+            && self.capacity.is_none()
+            && self.conditionals.is_empty()
+        {
+            return operator(rng);
+        }
+        let current = self.violations(map);
+        for _ in 0..MAX_ATTEMPTS {
+            let next = operator(rng);
+            if self.violations(&next) <= current {
+                return next;
             }
         }
+        map.clone()
+    }
+
+    /// 在优化过程中追加固定一些元素，它们保持在当前方案中的按键上
+    pub fn freeze(&mut self, elements: &[Element]) {
+        self.fixed.extend(elements.iter().copied());
+        let fixed = &self.fixed;
+        self.movable.retain(|x| !fixed.contains(x));
+        self.bucketize();
+    }
+
+    /// 搜索空间的大小：可移动元素的数量，以及所有可行布局数量的常用对数
+    pub fn search_space(&self) -> (usize, f64) {
+        // 同键的元素一起移动，每组只计算一次
+        let size = self
+            .movable
+            .iter()
+            .filter(|x| self.members(**x)[0] == **x)
+            .map(|x| (self.narrowed.get(x).unwrap_or(&self.alphabet).len() as f64).log10())
+            .sum();
+        (self.movable.len(), size)
+    }
+
+    fn get_movable_element<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        *self.movable.choose(rng).unwrap() // 在编译约束时已经确保了至少有一个可移动的元素
+    }
+
+    fn get_swappable_element<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        *self.movable.choose(rng).unwrap() // 同上
     }
 
     fn swap_narrowed_elements(&self, map: &KeyMap, element1: Element, element2: Element) -> KeyMap {
@@ -132,56 +991,290 @@ impl Constraints {
         let destinations2 = self.narrowed.get(&element2).unwrap_or(&self.alphabet);
         //分开判断可行性。这样如果无法交换，至少移动一下。
         if destinations1.contains(&map[element2]) {
-            next[element1] = map[element2];
+            self.place(&mut next, element1, map[element2]);
         }
         if destinations2.contains(&map[element1]) {
-            next[element2] = map[element1];
+            self.place(&mut next, element2, map[element1]);
         }
         next
     }
 
-    pub fn constrained_random_swap(&self, map: &KeyMap) -> KeyMap {
-        let element1 = self.get_swappable_element();
-        let element2 = self.get_swappable_element();
-        self.swap_narrowed_elements(map, element1, element2)
+    pub fn constrained_random_swap<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        self.with_retry(map, rng, |rng| {
+            let element1 = self.get_swappable_element(rng);
+            let element2 = self.get_swappable_element(rng);
+            self.swap_narrowed_elements(map, element1, element2)
+        })
+    }
+
+    /// 可行交换：先随机选出一个元素，再从交换之后双方都在各自可行按键上的元素中随机选出另一个，与它交换按键。
+    /// 与随机交换不同，它不会因为一方无法移动而退化成单方面的移动；找不到这样的元素时退化为随机移动
+    pub fn constrained_feasible_swap<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        self.with_retry(map, rng, |rng| self.feasible_swap(map, rng))
+    }
+
+    fn feasible_swap<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        let element1 = self.get_swappable_element(rng);
+        let key1 = map[element1];
+        let destinations1 = self.narrowed.get(&element1).unwrap_or(&self.alphabet);
+        // 可行按键包含 key1 的桶里，当前按键又在 element1 的可行按键中的元素
+        let candidates: Vec<Element> = self
+            .accepting
+            .get(key1)
+            .into_iter()
+            .flatten()
+            .flat_map(|x| &self.buckets[*x].1)
+            .filter(|x| map[**x] != key1 && destinations1.contains(&map[**x]))
+            .copied()
+            .collect();
+        let Some(element2) = candidates.choose(rng) else {
+            return self.random_move(map, rng);
+        };
+        let mut next = map.clone();
+        self.place(&mut next, element1, map[*element2]);
+        self.place(&mut next, *element2, key1);
+        next
+    }
+
+    pub fn constrained_full_key_swap<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        self.with_retry(map, rng, |rng| self.full_key_swap(map, rng))
     }
 
-    pub fn constrained_full_key_swap(&self, map: &KeyMap) -> KeyMap {
-        let mut rng = thread_rng();
+    /// 交换两个按键上的所有可移动元素。同组的元素可行按键相同、又在同一个键上，所以总是一起移动
+    fn full_key_swap<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
         let mut next = map.clone();
         // 寻找一个可移动元素和一个它的可行移动位置，然后把这两个键上的所有元素交换
         // 这样交换不成也至少能移动一次
-        let movable_element = self.get_movable_element();
+        let movable_element = self.get_movable_element(rng);
         let key1 = map[movable_element];
         let destinations = self
             .narrowed
             .get(&movable_element)
             .unwrap_or(&self.alphabet);
-        let key2 = destinations.choose(&mut rng).unwrap(); // 在编译约束时已经确保了这里一定有可行的移动位置
-        for (element, key) in map.iter().enumerate() {
-            if (*key == key1 || *key == *key2) && !self.fixed.contains(&element) {
-                let destination = if *key == *key2 { key1 } else { *key2 };
-                //将元素移动到目标
-                //考虑到组合中的元素必然在同样的键上，有同样的约束条件，也必然跟随移动，这里不再判断组合
+        let key2 = destinations.choose(rng).unwrap(); // 在编译约束时已经确保了这里一定有可行的移动位置
+        for members in &self.groups {
+            // 同组的元素以组里的第一个元素为准整体移动，即使它们当前不在同一个键上
+            let element = members[0];
+            let key = map[element];
+            if (key == key1 || key == *key2) && !self.fixed.contains(&element) {
+                let destination = if key == *key2 { key1 } else { *key2 };
                 let destinations2 = self.narrowed.get(&element).unwrap_or(&self.alphabet);
                 if destinations2.contains(&destination) {
-                    next[element] = destination;
+                    self.place(&mut next, element, destination);
                 }
             }
         }
         next
     }
 
-    pub fn constrained_random_move(&self, map: &KeyMap) -> KeyMap {
-        let mut rng = thread_rng();
+    /// 循环轮换：随机选出三到五个位于不同按键上的可移动元素，第一个元素移到第二个元素的按键上，第二个移到第三个的按键上，依此类推，最后一个移到第一个的按键上
+    /// 两两交换无法到达某些窄化约束下的布局（例如三个元素的可行按键两两之间各不相容），轮换可以做到。如果多次尝试都找不到满足窄化约束的轮换，就退化为随机移动
+    pub fn constrained_rotation<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        self.with_retry(map, rng, |rng| self.rotation(map, rng))
+    }
+
+    fn rotation<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        let movable = self.movable.len();
+        if movable >= 3 {
+            for _ in 0..100 {
+                let length = rng.gen_range(3..=5.min(movable));
+                let mut cycle: Vec<Element> = Vec::with_capacity(length);
+                for _ in 0..length * 4 {
+                    let element = self.get_movable_element(rng);
+                    // 同一个按键上的元素互相轮换没有意义
+                    if cycle.iter().all(|x| map[*x] != map[element]) {
+                        cycle.push(element);
+                    }
+                    if cycle.len() == length {
+                        break;
+                    }
+                }
+                if cycle.len() < length {
+                    continue;
+                }
+                let feasible = (0..length).all(|i| {
+                    let destination = map[cycle[(i + 1) % length]];
+                    let destinations = self.narrowed.get(&cycle[i]).unwrap_or(&self.alphabet);
+                    destinations.contains(&destination)
+                });
+                if feasible {
+                    let mut next = map.clone();
+                    for i in 0..length {
+                        self.place(&mut next, cycle[i], map[cycle[(i + 1) % length]]);
+                    }
+                    return next;
+                }
+            }
+        }
+        self.random_move(map, rng)
+    }
+
+    /// 为大邻域搜索选出一组相关的元素：先取两个按键上的所有可移动元素，如果不足 5 个则随机补充，如果超过 15 个则随机保留 15 个
+    pub fn related_elements<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> Vec<Element> {
+        let key1 = map[self.get_movable_element(rng)];
+        let key2 = map[self.get_movable_element(rng)];
+        let mut elements: Vec<Element> = (0..self.elements)
+            .filter(|x| (map[*x] == key1 || map[*x] == key2) && !self.fixed.contains(x))
+            .collect();
+        elements.shuffle(rng);
+        elements.truncate(15);
+        let movable = self.movable.len();
+        while elements.len() < 5.min(movable) {
+            let element = self.get_movable_element(rng);
+            if !elements.contains(&element) {
+                elements.push(element);
+            }
+        }
+        elements
+    }
+
+    /// 均匀杂交：每个元素随机继承两个亲本之一的按键
+    /// 固定的元素保持不变，窄化的元素只会继承到允许的按键，同键的元素一起继承
+    pub fn constrained_crossover<R: Rng + ?Sized>(
+        &self,
+        map1: &KeyMap,
+        map2: &KeyMap,
+        rng: &mut R,
+    ) -> KeyMap {
+        self.with_retry(map1, rng, |rng| {
+            let mut next = map1.clone();
+            for members in &self.groups {
+                let element = members[0];
+                if self.fixed.contains(&element) || !rng.gen::<bool>() {
+                    continue;
+                }
+                let destinations = self.narrowed.get(&element).unwrap_or(&self.alphabet);
+                if destinations.contains(&map2[element]) {
+                    self.place(&mut next, element, map2[element]);
+                }
+            }
+            next
+        })
+    }
+
+    pub fn constrained_random_move<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
+        self.with_retry(map, rng, |rng| self.random_move(map, rng))
+    }
+
+    fn random_move<R: Rng + ?Sized>(&self, map: &KeyMap, rng: &mut R) -> KeyMap {
         let mut next = map.clone();
-        let movable_element = self.get_movable_element();
+        let movable_element = self.get_movable_element(rng);
         let destinations = self
             .narrowed
             .get(&movable_element)
             .unwrap_or(&self.alphabet);
-        let key = destinations.choose(&mut rng).unwrap(); // 在编译约束时已经确保了这里一定有可行的移动位置
-        next[movable_element] = *key;
+        let key = destinations.choose(rng).unwrap(); // 在编译约束时已经确保了这里一定有可行的移动位置
+        self.place(&mut next, movable_element, *key);
         next
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Constraints;
+    use crate::config::Mapped;
+    use crate::error::Error;
+    use crate::representation::Representation;
+    use crate::testing;
+
+    /// 在公共的方案中再加上名称里有 `*` 的元素、以「口」开头的元素和一个两码的元素，然后编译给出的约束
+    fn compile(constraints: &str) -> Result<(Representation, Constraints), Error> {
+        let optimization = format!(
+            r#"
+  objective:
+    characters_full:
+      duplication: 1.0
+  constraints:
+{constraints}
+  metaheuristic:
+    algorithm: SimulatedAnnealing
+"#
+        );
+        let mut config = testing::config(&optimization, None);
+        for (name, keys) in [("米*", "c"), ("口口", "a"), ("日月", "ab")] {
+            config.form.mapping.insert(name.to_string(), Mapped::Basic(keys.to_string()));
+        }
+        let representation = Representation::new(config)?;
+        let constraints = Constraints::new(&representation)?;
+        Ok((representation, constraints))
+    }
+
+    /// 返回被这条原子约束固定的元素
+    fn fixed(constraint: &str) -> Result<Vec<String>, Error> {
+        let (representation, constraints) =
+            compile(&format!("    elements:\n      - {{ {constraint} }}"))?;
+        let mut names: Vec<String> = constraints
+            .fixed
+            .iter()
+            .map(|x| representation.repr_element[x].clone())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    #[test]
+    fn element_names_are_literal_without_a_prefix() {
+        assert_eq!(fixed("element: '米*'").unwrap(), ["米*"]);
+        assert_eq!(fixed("element: 'glob:米*'").unwrap(), ["米", "米*"]);
+        assert_eq!(fixed("element: 日月").unwrap(), ["日月", "日月.1"]);
+        assert!(fixed("element: '口?'").is_err());
+    }
+
+    #[test]
+    fn patterns_match_whole_element_names() {
+        assert_eq!(fixed("element: 're:口'").unwrap(), ["口"]);
+        assert_eq!(fixed("element: 're:^口$'").unwrap(), ["口"]);
+        assert_eq!(fixed("element: 're:口.*'").unwrap(), ["口", "口口"]);
+        assert_eq!(fixed("element: 'glob:日*'").unwrap(), ["日", "日月", "日月.1"]);
+        assert_eq!(fixed("element: 're:日月\\.1'").unwrap(), ["日月.1"]);
+        // 给出码位时，模式仍然与完整的名称匹配，只是只选取这一码
+        assert_eq!(fixed("element: 'glob:日*', index: 1").unwrap(), ["日月.1"]);
+        assert!(fixed("element: 're:日月', index: 1").is_err());
+    }
+
+    #[test]
+    fn patterns_expand_in_relations_groups_and_conditionals() {
+        let names = |representation: &Representation, elements: &[usize]| {
+            let mut names: Vec<&str> = elements
+                .iter()
+                .map(|x| representation.repr_element[x].as_str())
+                .collect();
+            names.sort();
+            names.join(" ")
+        };
+        let (representation, constraints) = compile(
+            r#"
+    groups:
+      - ['glob:日*', 木]
+    relations:
+      - { elements: ['re:口.*', 口, 米], relation: different }
+    conditionals:
+      - { if: { element: 'glob:日月*', keys: [b] }, then: { element: 're:米.*', keys: [c] } }"#,
+        )
+        .unwrap();
+        let groups: Vec<String> = constraints
+            .groups
+            .iter()
+            .filter(|x| x.len() > 1)
+            .map(|x| names(&representation, x))
+            .collect();
+        assert_eq!(groups, ["日 日月 日月.1 木"]);
+        // 字面写出的「口」与模式匹配到的「口」只算一次
+        let mut different: Vec<String> = constraints
+            .different
+            .iter()
+            .map(|(x, y)| names(&representation, &[*x, *y]))
+            .collect();
+        different.sort();
+        assert_eq!(different, ["口 口口", "口 米", "口口 米"]);
+        let mut conditionals: Vec<String> = constraints
+            .conditionals
+            .iter()
+            .map(|x| names(&representation, &[x.element, x.then_element]))
+            .collect();
+        conditionals.sort();
+        assert_eq!(conditionals, ["日月 米", "日月 米*", "日月.1 米", "日月.1 米*"]);
+        let error = compile("    groups:\n      - ['glob:月*', 木]").err().unwrap();
+        assert!(error.message.contains("groups[0]"), "{}", error.message);
+    }
+}
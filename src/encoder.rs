@@ -1,22 +1,46 @@
 //! 编码引擎
 
 use crate::{
-    config::{EncoderConfig, ShortCodeConfig, WordRule},
+    config::{
+        AlternativeSplits, AutoShortCodeConfig, DuplicateOrder, EncoderConfig, FullCodePolicy,
+        ShortCodeConfig, WordRule,
+    },
     error::Error,
+    objectives::corpus::{self, CorpusItem},
     representation::{
-        Assets, Buffer, Codes, EncodeExport, Entry, Key, KeyMap, Occupation, RawSequenceMap,
-        Representation, Sequence, SequenceMap,
+        Assets, Buffer, Code, CodeEntry, CodeIndex, Codes, EncodeExport, Entry, Key, KeyMap,
+        Occupation, RawSequenceMap, Representation, Sequence, SequenceMap, ShortCodeAssignment,
+        SimulationExport,
     },
 };
-use std::{cmp::Reverse, fmt::Debug, iter::zip};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    iter::zip,
+    sync::Arc,
+};
+
+// 受影响的字词数乘以这个倍数仍不超过总字词数时，才使用增量编码
+const INCREMENTAL_RATIO: usize = 4;
 
 // 支持二字词直到十字词
 const MAX_WORD_LENGTH: usize = 10;
 
-type Lookup = [Vec<(isize, isize)>; MAX_WORD_LENGTH - 1];
+// 每种词长的构词规则，依次尝试，取第一个能用的
+type Lookup = [Vec<Vec<(isize, isize)>>; MAX_WORD_LENGTH - 1];
+
+/// 编码器：根据元素布局计算字表中所有字的全码。默认编码器按照方案文件中的拆分表和取码规则编码，
+/// 二笔、郑码这样取码规则比较特殊的方案可以自己实现这个 trait，然后用 `Objective::with_encoder`
+/// 替换默认编码器，同时沿用配置表示、约束和各种优化算法
+pub trait Encoder: Send + Sync {
+    /// 按照默认编码器字表的顺序，返回每个字的全码以及它是否与更常用的字重码
+    fn encode(&self, keymap: &KeyMap) -> Codes;
+}
 
-#[derive(Debug)]
-pub struct Encoder {
+#[derive(Debug, Clone)]
+pub struct DefaultEncoder {
     pub characters: Vec<char>,
     characters_sequence: Vec<Sequence>,
     pub words: Option<Vec<String>>,
@@ -25,30 +49,110 @@ pub struct Encoder {
     pub radix: usize,
     pub alphabet_radix: usize,
     auto_select: Vec<bool>,
-    select_keys: Vec<Key>,
+    pub select_keys: Vec<Key>,
     short_code_schemes: Option<Vec<CompiledShortCodeConfig>>,
+    // 元素到字词的倒排索引，移动少数元素时只需要重新编码用到这些元素的字词
+    characters_entries: Vec<Vec<usize>>,
+    words_entries: Vec<Vec<usize>>,
+    // 设置了编码线程数时，编码和累计指标都在这个线程池中并行进行
+    pool: Option<Arc<ThreadPool>>,
+    // 按字表的顺序，每个字除第一种拆分以外的其他拆分
+    alternatives: Vec<Vec<Sequence>>,
+    // 标点符号和它们固定的编码，按标点排序
+    pub punctuation: Vec<(char, Code)>,
+    // 重码的候选顺序与字表、词表的顺序不同时，字和词各自的候选顺序，以及字词合在一起的候选顺序
+    characters_priority: Option<Priority>,
+    words_priority: Option<Priority>,
+    merged_order: Option<Vec<Candidate>>,
+}
+
+/// 一组字词在同一编码上的候选顺序：每个字词的名次，以及按名次排列的字词序号
+#[derive(Debug, Clone)]
+struct Priority {
+    rank: Vec<usize>,
+    order: Vec<usize>,
+}
+
+/// 字词合在一起排列候选时的一项
+#[derive(Debug, Clone, Copy)]
+enum Candidate {
+    Character(usize),
+    Word(usize),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct CompiledShortCodeConfig {
     pub prefix: usize,
     pub select_keys: Vec<usize>,
+    pub limit: Option<usize>,
+    // 按字表的顺序标记每个字能否取得这一级简码，None 表示所有字都可以
+    pub eligible: Option<Vec<bool>>,
 }
 
-impl Encoder {
+impl DefaultEncoder {
+    /// 计算每个元素的频率，即所有用到这个元素的字的频率之和
+    pub fn element_frequencies(&self, frequencies: &[f64], elements: usize) -> Vec<f64> {
+        let mut result = vec![0.0; elements];
+        for (sequence, frequency) in zip(&self.characters_sequence, frequencies) {
+            for element in sequence {
+                result[*element] += frequency;
+            }
+        }
+        result
+    }
+
+    /// 将构词规则转换成若干个依次尝试的数对列表，规则之间用 | 分隔
+    fn parse_formulas(s: &str, max_length: usize) -> Result<Vec<Vec<(isize, isize)>>, Error> {
+        let mut formulas = vec![];
+        for formula in s.split('|').map(|x| x.trim()) {
+            if formula.chars().any(|x| x.is_ascii_digit()) {
+                formulas.push(Self::parse_counts(formula, max_length)?);
+            } else {
+                formulas.push(Self::parse_formula(&formula.to_string(), max_length)?);
+            }
+        }
+        Ok(formulas)
+    }
+
+    /// 将［1+1+2］这样的字符串转换成一个数对的列表，每一项表示依次从每个字取前几码。
+    /// 数字前面也可以用 Rime 格式的大写字母指定取哪个字，例如［1+1+1+Z1］表示最后一项取末字的第一码
+    fn parse_counts(s: &str, max_length: usize) -> Result<Vec<(isize, isize)>, Error> {
+        let message = Error::from(format!("构词规则 {s} 不合法"));
+        let mut ret: Vec<(isize, isize)> = Vec::new();
+        for (position, part) in s.split('+').map(|x| x.trim()).enumerate() {
+            let (character_index, count) = match part.chars().next() {
+                Some(symbol) if symbol.is_ascii_uppercase() => {
+                    let index = symbol as isize - ('A' as isize);
+                    (if index > 13 { index - 26 } else { index }, &part[1..])
+                }
+                _ => (position as isize, part),
+            };
+            let count: isize = count.parse().map_err(|_| message.clone())?;
+            if count <= 0 {
+                return Err(message);
+            }
+            ret.extend((0..count).map(|code_index| (character_index, code_index)));
+        }
+        if ret.len() > max_length {
+            Err(format!("构词规则 {s} 的长度超过了最大长度 {max_length}").into())
+        } else {
+            Ok(ret)
+        }
+    }
+
     /// 将 Rime 格式的［AaAbBaBb］这样的字符串转换成一个数对的列表
     /// 每个数对表示要取哪个字的哪个码
     fn parse_formula(s: &String, max_length: usize) -> Result<Vec<(isize, isize)>, Error> {
         let message = Error::from(format!("构词规则 {s} 不合法"));
         let mut ret: Vec<(isize, isize)> = Vec::new();
         let chars: Vec<char> = s.chars().collect();
-        if chars.len() % 2 != 0 {
+        if !chars.len().is_multiple_of(2) {
             return Err(message.clone());
         }
         let pairs = chars.len() / 2;
         let normalize = |x: isize| {
             // 有效的值是 0 到 25 之间
-            if x < 0 || x > 25 {
+            if !(0..=25).contains(&x) {
                 return Err(message.clone());
             }
             Ok(if x > 13 { x - 26 } else { x })
@@ -86,7 +190,7 @@ impl Encoder {
                 };
                 if is_matched {
                     one_matched = true;
-                    quick_lookup[(i - 2) as usize] = Self::parse_formula(formula, max_length)?;
+                    quick_lookup[i - 2] = Self::parse_formulas(formula, max_length)?;
                     break;
                 }
             }
@@ -105,35 +209,135 @@ impl Encoder {
         sequence_map: RawSequenceMap,
         words: Vec<String>,
         assets: &Assets,
-    ) -> Result<Encoder, Error> {
-        // 预处理单字拆分表
+    ) -> Result<DefaultEncoder, Error> {
+        // 限定字集时，只保留字集中的字，以及完全由这些字组成的词
+        let (sequence_map, words) = match &representation.config.encoder.character_set {
+            Some(name) => {
+                let set = assets
+                    .character_sets
+                    .get(name)
+                    .ok_or(format!("字集「{name}」未定义，需要提供字集文件 {name}.txt"))?;
+                let set: HashSet<char> = set.chars().filter(|x| !x.is_whitespace()).collect();
+                let mut sequence_map = sequence_map;
+                sequence_map.retain(|char, _| set.contains(char));
+                let mut words = words;
+                words.retain(|word| word.chars().all(|x| set.contains(&x)));
+                (sequence_map, words)
+            }
+            None => (sequence_map, words),
+        };
+        // 音形方案要先给每个字加上读音元素；含有多音字的词语可以在读音表中单独列出读音
+        let pronunciation = &assets.pronunciation;
+        let (sequence_map, word_readings) = match &representation.config.encoder.pronunciation {
+            Some(config) => {
+                if pronunciation.is_empty() {
+                    return Err("音形方案需要提供读音表 pronunciation.txt".into());
+                }
+                let readings = representation.transform_word_pronunciation(
+                    config,
+                    &sequence_map,
+                    pronunciation,
+                    &words,
+                )?;
+                let merged =
+                    Representation::transform_pronunciation(config, &sequence_map, pronunciation)?;
+                (merged, readings)
+            }
+            None => (sequence_map, HashMap::new()),
+        };
+        // 预处理单字拆分表，一字多拆时第一种拆分是主要的拆分，也用于构词
+        let alternatives = match representation.config.encoder.alternative_splits {
+            Some(AlternativeSplits::First) | None => HashMap::new(),
+            Some(_) => representation.transform_alternatives(&sequence_map)?,
+        };
         let sequence_map = representation.transform_elements(&sequence_map)?;
 
         // 将拆分序列映射降序排列，然后拆分成两个数组，一个只放字，一个只放序列
+        // 频率相同时按字排序，保证每次运行得到的顺序都相同
         let mut characters_all: Vec<(char, Sequence)> = sequence_map.clone().into_iter().collect();
-        characters_all
-            .sort_by_key(|x| Reverse(*assets.character_frequency.get(&x.0).unwrap_or(&0)));
-        let (characters, characters_sequence): (Vec<_>, Vec<_>) =
+        characters_all.sort_by_key(|x| {
+            (Reverse(*assets.character_frequency.get(&x.0).unwrap_or(&0)), x.0)
+        });
+        let (characters, mut characters_sequence): (Vec<_>, Vec<_>) =
             characters_all.into_iter().unzip();
+        let mut alternatives: Vec<Vec<Sequence>> = characters
+            .iter()
+            .map(|x| alternatives.get(x).cloned().unwrap_or_default())
+            .collect();
+        // 补码只用于单字全码，构词仍然用补码之前的拆分
+        if let Some(padding) = &representation.config.encoder.padding {
+            let identifiers = &assets.identifiers;
+            let sequences = zip(&mut characters_sequence, &mut alternatives);
+            for (char, (sequence, others)) in zip(&characters, sequences) {
+                representation.pad_sequence(padding, *char, sequence, identifiers)?;
+                for other in others {
+                    representation.pad_sequence(padding, *char, other, identifiers)?;
+                }
+            }
+        }
         let raw_schemes = &representation.config.encoder.short_code_schemes;
-        let short_code_schemes = if let Some(schemes) = raw_schemes {
-            Some(Self::build_short_code_schemes(schemes, representation)?)
-        } else {
-            None
+        let auto_short_codes = &representation.config.encoder.auto_short_codes;
+        let short_code_schemes = match (raw_schemes, auto_short_codes) {
+            (Some(_), Some(_)) => {
+                return Err("简码方式 short_code_schemes 和自动简码 auto_short_codes 只能配置一个".into());
+            }
+            (Some(schemes), None) => {
+                Some(Self::build_short_code_schemes(schemes, representation, &characters)?)
+            }
+            (None, Some(auto)) => {
+                Some(Self::build_auto_short_codes(auto, representation, &characters)?)
+            }
+            (None, None) => None,
         };
 
         // 对词也是一样的操作
         let rules = &representation.config.encoder.rules;
         let max_length = representation.config.encoder.max_length;
         let (words, words_sequence) = if let Some(rule) = rules {
-            let mut words_all = Self::build_word_sequence(rule, sequence_map, words, max_length)?;
-            words_all.sort_by_key(|x| Reverse(*assets.word_frequency.get(&x.0).unwrap_or(&0)));
-            let (words, words_sequence) = words_all.into_iter().unzip();
+            let mut words_all =
+                Self::build_word_sequence(rule, sequence_map, &word_readings, words, max_length)?;
+            words_all.sort_by(|a, b| {
+                let frequency = |x: &String| Reverse(*assets.word_frequency.get(x).unwrap_or(&0));
+                (frequency(&a.0), &a.0).cmp(&(frequency(&b.0), &b.0))
+            });
+            let (words, words_sequence): (Vec<_>, Vec<_>) = words_all.into_iter().unzip();
             (Some(words), Some(words_sequence))
         } else {
             (None, None)
         };
-        let encoder = Encoder {
+        let threads = representation.config.optimization.metaheuristic.encode_threads;
+        let pool = match threads {
+            Some(threads) if threads > 1 => {
+                let pool = ThreadPoolBuilder::new().num_threads(threads).build();
+                Some(Arc::new(pool.map_err(|e| format!("无法创建编码线程池：{e}"))?))
+            }
+            _ => None,
+        };
+        let merged_order = Self::build_merged_order(
+            &representation.config.encoder,
+            &characters,
+            words.as_deref().unwrap_or_default(),
+            assets,
+        );
+        let (characters_priority, words_priority) = match &merged_order {
+            Some(merged) => (
+                Self::build_priority(merged, characters.len(), |x| match x {
+                    Candidate::Character(index) => Some(index),
+                    Candidate::Word(_) => None,
+                }),
+                Self::build_priority(merged, words.as_ref().map_or(0, |x| x.len()), |x| {
+                    match x {
+                        Candidate::Word(index) => Some(index),
+                        Candidate::Character(_) => None,
+                    }
+                }),
+            ),
+            None => (None, None),
+        };
+        let elements = representation.initial.len();
+        let characters_entries = Self::entries_index(&characters_sequence, elements);
+        let words_entries = Self::entries_index(words_sequence.as_deref().unwrap_or(&[]), elements);
+        let encoder = DefaultEncoder {
             characters,
             characters_sequence,
             words,
@@ -144,13 +348,86 @@ impl Encoder {
             auto_select: representation.transform_auto_select()?,
             select_keys: representation.select_keys.clone(),
             short_code_schemes,
+            characters_entries,
+            words_entries,
+            pool,
+            alternatives,
+            punctuation: representation.transform_punctuation()?,
+            characters_priority,
+            words_priority,
+            merged_order,
         };
         Ok(encoder)
     }
 
+    /// 按照重码的排列方式和固定首选的字词，把字和词合在一起排列候选。
+    /// 单字在前、词语在后且没有固定首选时，候选顺序就是字表和词表的顺序，返回 None
+    fn build_merged_order(
+        config: &EncoderConfig,
+        characters: &[char],
+        words: &[String],
+        assets: &Assets,
+    ) -> Option<Vec<Candidate>> {
+        let order = config.duplicate_order.unwrap_or_default();
+        let pinned = config.pinned.clone().unwrap_or_default();
+        if order == DuplicateOrder::CharacterFirst && pinned.is_empty() {
+            return None;
+        }
+        let pinned: HashMap<String, usize> = pinned.into_iter().zip(0..).collect();
+        // 依次比较：固定首选的字词按列出的顺序在前，然后是排列方式决定的类别和频率，
+        // 最后字在词前，字表和词表中靠前的在前
+        let key = |item: String, length: usize, frequency: u64, candidate: Candidate| {
+            let pin = *pinned.get(&item).unwrap_or(&usize::MAX);
+            let is_word = matches!(candidate, Candidate::Word(_));
+            let (class, frequency) = match order {
+                DuplicateOrder::CharacterFirst => (usize::from(is_word), 0),
+                DuplicateOrder::Frequency => (0, frequency),
+                DuplicateOrder::Length => (length, 0),
+            };
+            let index = match candidate {
+                Candidate::Character(index) | Candidate::Word(index) => index,
+            };
+            (pin, class, Reverse(frequency), is_word, index)
+        };
+        let mut merged: Vec<_> = characters
+            .iter()
+            .enumerate()
+            .map(|(index, x)| {
+                let frequency = *assets.character_frequency.get(x).unwrap_or(&0);
+                let candidate = Candidate::Character(index);
+                (key(x.to_string(), 1, frequency, candidate), candidate)
+            })
+            .chain(words.iter().enumerate().map(|(index, x)| {
+                let frequency = *assets.word_frequency.get(x).unwrap_or(&0);
+                let candidate = Candidate::Word(index);
+                (key(x.clone(), x.chars().count(), frequency, candidate), candidate)
+            }))
+            .collect();
+        merged.sort_by_key(|x| x.0);
+        Some(merged.into_iter().map(|x| x.1).collect())
+    }
+
+    /// 从字词合在一起的候选顺序中取出字或者词的候选顺序，与字表或词表的顺序相同时返回 None
+    fn build_priority(
+        merged: &[Candidate],
+        length: usize,
+        select: impl Fn(Candidate) -> Option<usize>,
+    ) -> Option<Priority> {
+        let order: Vec<usize> = merged.iter().filter_map(|x| select(*x)).collect();
+        if order.iter().copied().eq(0..length) {
+            return None;
+        }
+        let mut rank = vec![0; length];
+        for (position, index) in order.iter().enumerate() {
+            rank[*index] = position;
+        }
+        Some(Priority { rank, order })
+    }
+
     fn build_short_code_schemes(
         schemes: &Vec<ShortCodeConfig>,
         representation: &Representation,
+        characters: &[char],
     ) -> Result<Vec<CompiledShortCodeConfig>, Error> {
         let mut configs = Vec::new();
         for scheme in schemes {
@@ -161,7 +438,7 @@ impl Encoder {
                 for key in keys {
                     let transformed_key = representation
                         .key_repr
-                        .get(&key)
+                        .get(key)
                         .ok_or(format!("简码的选择键 {key} 不在全局选择键中"))?;
                     transformed_keys.push(*transformed_key);
                 }
@@ -169,20 +446,59 @@ impl Encoder {
             } else {
                 representation.select_keys.clone()
             };
-            if count as usize > select_keys.len() {
+            if count > select_keys.len() {
                 return Err("选重数量不能高于选择键数量".into());
             }
+            // 不在字表中的字不会被编码，直接略去
+            let eligible = scheme.characters.as_ref().map(|allowed| {
+                let allowed: HashSet<char> = allowed.chars().collect();
+                characters.iter().map(|x| allowed.contains(x)).collect()
+            });
             configs.push(CompiledShortCodeConfig {
                 prefix,
                 select_keys: select_keys[..count].to_vec(),
+                limit: scheme.limit,
+                eligible,
             });
         }
         Ok(configs)
     }
 
+    /// 自动简码相当于从一码到最长码长依次取全码的前缀、每个前缀只出一重的简码方式，再限定可以取得简码的字
+    fn build_auto_short_codes(
+        auto: &AutoShortCodeConfig,
+        representation: &Representation,
+        characters: &[char],
+    ) -> Result<Vec<CompiledShortCodeConfig>, Error> {
+        let max_length = auto.max_length.unwrap_or(2);
+        if max_length == 0 || max_length >= representation.config.encoder.max_length {
+            return Err("自动简码的最长码长必须大于零并且小于最大码长".into());
+        }
+        let schemes: Vec<ShortCodeConfig> = (1..=max_length)
+            .map(|prefix| ShortCodeConfig {
+                prefix,
+                count: None,
+                select_keys: None,
+                limit: None,
+                characters: auto.characters.clone(),
+            })
+            .collect();
+        let mut configs = Self::build_short_code_schemes(&schemes, representation, characters)?;
+        if let Some(top) = auto.top {
+            for config in configs.iter_mut() {
+                let eligible = config.eligible.get_or_insert_with(|| vec![true; characters.len()]);
+                for flag in eligible.iter_mut().skip(top) {
+                    *flag = false;
+                }
+            }
+        }
+        Ok(configs)
+    }
+
     fn build_word_sequence(
         rules: &Vec<WordRule>,
         sequence_map: SequenceMap,
+        word_readings: &HashMap<String, Vec<Sequence>>,
         words: Vec<String>,
         max_length: usize,
     ) -> Result<Vec<(String, Sequence)>, Error> {
@@ -195,20 +511,29 @@ impl Encoder {
             if chars.len() > MAX_WORD_LENGTH {
                 continue;
             }
-            let rule = &lookup[chars.len() - 2]; // 二字词的下标是 0，所以要减二
-            let mut word_elements: Vec<usize> = Vec::new();
-            let mut has_invalid_char = false;
-            for (char_index, code_index) in rule {
-                let char = Self::signed_index(&chars, *char_index);
-                if let Some(sequence) = sequence_map.get(char) {
-                    let value = Self::signed_index(sequence, *code_index);
-                    word_elements.push(*value);
-                } else {
-                    has_invalid_char = true;
+            let rules = &lookup[chars.len() - 2]; // 二字词的下标是 0，所以要减二
+            // 单独列出了读音的词语，按照词中每个字的实际读音取码
+            let reading = word_readings.get(&word);
+            // 依次尝试每个规则，字的拆分不够长而取不到所需的码时换下一个规则
+            let mut word_elements = None;
+            for rule in rules {
+                let elements: Option<Sequence> = rule
+                    .iter()
+                    .map(|(char_index, code_index)| {
+                        let sequence = match reading {
+                            Some(reading) => Self::signed_index(reading, *char_index)?,
+                            None => sequence_map.get(Self::signed_index(&chars, *char_index)?)?,
+                        };
+                        Self::signed_index(sequence, *code_index).copied()
+                    })
+                    .collect();
+                if elements.is_some() {
+                    word_elements = elements;
                     break;
                 }
             }
-            if !has_invalid_char {
+            // 缺少字的拆分，或者所有规则都不适用，就无法构词
+            if let Some(word_elements) = word_elements {
                 words_all.push((word.clone(), word_elements));
             }
         }
@@ -223,25 +548,215 @@ impl Encoder {
     pub fn encode_full(
         &self,
         keymap: &KeyMap,
-        data: &Vec<Sequence>,
+        data: &[Sequence],
         output: &mut Codes,
         occupation: &mut Occupation,
     ) {
-        for (sequence, pointer) in zip(data, output) {
-            let mut code = 0_usize;
-            let mut weight = 1_usize;
+        for (code, pointer) in zip(self.encode_sequences(keymap, data), output) {
+            *pointer = (code, occupation[code]);
+            occupation[code] = true;
+        }
+    }
+
+    /// 由拆分序列建立元素到字词的倒排索引，每个元素对应用到它的字词序号（不重复、从小到大）
+    pub(crate) fn entries_index(sequences: &[Sequence], elements: usize) -> Vec<Vec<usize>> {
+        let mut index = vec![vec![]; elements];
+        for (entry, sequence) in sequences.iter().enumerate() {
             for element in sequence {
-                code += keymap[*element] * weight;
-                weight *= self.radix;
+                if index[*element].last() != Some(&entry) {
+                    index[*element].push(entry);
+                }
             }
-            // 全码时，忽略次选及之后的选择键，给所有不能自动上屏的码统一添加首选键
-            // 这是为了便于计算重码，否则还要判断
-            if !self.auto_select[code] {
-                code += self.select_keys[0] * weight;
+        }
+        index
+    }
+
+    /// 增量编码：与上一次编码所用的布局相比只移动了少数元素时，只重新编码用到这些元素的字词，
+    /// 并且只更新编码发生变化的那些码位上的重码标记；否则完整地重新编码一遍。两种方式的结果完全相同
+    pub fn encode_incremental(
+        &self,
+        candidate: &KeyMap,
+        sequences: &[Sequence],
+        entries: &[Vec<usize>],
+        codes: &mut Codes,
+        index: &mut CodeIndex,
+    ) {
+        self.encode_incremental_ordered(candidate, sequences, entries, codes, index, None)
+    }
+
+    /// 按照给定的候选顺序增量编码，每个码位上名次最靠前的字词是首选；不给出时按字词的序号排列
+    fn encode_incremental_ordered(
+        &self,
+        candidate: &KeyMap,
+        sequences: &[Sequence],
+        entries: &[Vec<usize>],
+        codes: &mut Codes,
+        index: &mut CodeIndex,
+        priority: Option<&Priority>,
+    ) {
+        let rank = |entry: &usize| priority.map_or(*entry, |x| x.rank[*entry]);
+        let moved: Vec<usize> = match &index.keymap {
+            Some(keymap) => (0..candidate.len())
+                .filter(|x| keymap[*x] != candidate[*x])
+                .collect(),
+            None => (0..candidate.len()).collect(),
+        };
+        let affected: usize = moved.iter().map(|x| entries[*x].len()).sum();
+        // 受影响的字词太多时，增量编码反而更慢
+        if index.keymap.is_none() || affected * INCREMENTAL_RATIO > codes.len() {
+            index.buckets.clear();
+            index.occupation = vec![false; self.get_space()];
+            let new_codes = self.encode_sequences(candidate, sequences);
+            for (code, pointer) in zip(&new_codes, codes.iter_mut()) {
+                pointer.0 = *code;
             }
-            *pointer = (code, occupation[code]);
-            occupation[code] = true;
+            let order: Box<dyn Iterator<Item = usize>> = match priority {
+                Some(priority) => Box::new(priority.order.iter().copied()),
+                None => Box::new(0..codes.len()),
+            };
+            for entry in order {
+                let code = new_codes[entry];
+                codes[entry].1 = index.occupation[code];
+                index.occupation[code] = true;
+                index.buckets.entry(code).or_default().push(entry);
+            }
+            index.keymap = Some(candidate.clone());
+            return;
+        }
+        let mut affected: Vec<usize> =
+            moved.iter().flat_map(|x| entries[*x].iter().copied()).collect();
+        affected.sort_unstable();
+        affected.dedup();
+        let mut touched: Vec<Code> = vec![];
+        for entry in affected {
+            let old = codes[entry].0;
+            let new = self.encode_sequence(candidate, &sequences[entry]);
+            if old == new {
+                continue;
+            }
+            codes[entry].0 = new;
+            if let Some(bucket) = index.buckets.get_mut(&old) {
+                if let Ok(position) = bucket.binary_search_by_key(&rank(&entry), rank) {
+                    bucket.remove(position);
+                }
+            }
+            let bucket = index.buckets.entry(new).or_default();
+            if let Err(position) = bucket.binary_search_by_key(&rank(&entry), rank) {
+                bucket.insert(position, entry);
+            }
+            touched.push(old);
+            touched.push(new);
+        }
+        // 重新标记受影响码位上的重码：每个码位上名次最靠前的字词是首选，其余都是重码
+        for code in touched {
+            let bucket = index.buckets.get(&code).map_or(&[][..], |x| &x[..]);
+            for (position, entry) in bucket.iter().enumerate() {
+                codes[*entry].1 = position > 0;
+            }
+            index.occupation[code] = !bucket.is_empty();
+            if bucket.is_empty() {
+                index.buckets.remove(&code);
+            }
+        }
+        index.keymap = Some(candidate.clone());
+    }
+
+    /// 增量编码单字全码
+    pub fn encode_characters_incremental(
+        &self,
+        candidate: &KeyMap,
+        codes: &mut Codes,
+        index: &mut CodeIndex,
+    ) {
+        // 选取拆分时每个字的编码取决于更常用的字占用了哪些编码，只能完整地重新编码
+        if self.choose_splits() {
+            index.buckets.clear();
+            index.occupation.clear();
+            index.occupation.resize(self.get_space(), false);
+            self.encode_character_full(candidate, codes, &mut index.occupation);
+            for (entry, (code, _)) in codes.iter().enumerate() {
+                index.buckets.entry(*code).or_default().push(entry);
+            }
+            index.keymap = Some(candidate.clone());
+            return;
+        }
+        let entries = &self.characters_entries;
+        let sequences = &self.characters_sequence;
+        let priority = self.characters_priority.as_ref();
+        self.encode_incremental_ordered(candidate, sequences, entries, codes, index, priority);
+    }
+
+    /// 用自定义的编码器计算单字全码，然后按结果重建编码索引，使之与增量编码的结果一样可以继续使用
+    pub fn encode_characters_with(
+        &self,
+        encoder: &dyn Encoder,
+        candidate: &KeyMap,
+        codes: &mut Codes,
+        index: &mut CodeIndex,
+    ) {
+        *codes = encoder.encode(candidate);
+        index.buckets.clear();
+        index.occupation.clear();
+        index.occupation.resize(self.get_space(), false);
+        for (entry, (code, _)) in codes.iter().enumerate() {
+            index.occupation[*code] = true;
+            index.buckets.entry(*code).or_default().push(entry);
+        }
+        index.keymap = Some(candidate.clone());
+    }
+
+    /// 增量编码词语全码，没有定义组词规则时什么也不做
+    pub fn encode_words_incremental(
+        &self,
+        candidate: &KeyMap,
+        codes: &mut Codes,
+        index: &mut CodeIndex,
+    ) {
+        if let Some(sequences) = self.words_sequence.as_ref() {
+            let entries = &self.words_entries;
+            let priority = self.words_priority.as_ref();
+            self.encode_incremental_ordered(candidate, sequences, entries, codes, index, priority);
+        }
+    }
+
+    /// 计算一组拆分序列的全码，设置了编码线程数时并行计算
+    fn encode_sequences(&self, keymap: &KeyMap, sequences: &[Sequence]) -> Vec<Code> {
+        let encode = |sequence| self.encode_sequence(keymap, sequence);
+        match &self.pool {
+            Some(pool) => pool.install(|| sequences.par_iter().map(encode).collect()),
+            None => sequences.iter().map(encode).collect(),
+        }
+    }
+
+    /// 并行编码和累计指标所用的线程池，没有设置编码线程数时为 None
+    pub fn pool(&self) -> Option<&ThreadPool> {
+        self.pool.as_deref()
+    }
+
+    /// 计算一个拆分序列的全码
+    pub fn encode_sequence(&self, keymap: &KeyMap, sequence: &Sequence) -> Code {
+        let mut code = 0_usize;
+        let mut weight = 1_usize;
+        for element in sequence {
+            code += keymap[*element] * weight;
+            weight *= self.radix;
+        }
+        // 全码时，忽略次选及之后的选择键，给所有不能自动上屏的码统一添加首选键
+        // 这是为了便于计算重码，否则还要判断
+        if !self.auto_select[code] {
+            code += self.select_keys[0] * weight;
         }
+        code
+    }
+
+    /// 单字的拆分序列
+    pub fn characters_sequence(&self) -> &[Sequence] {
+        &self.characters_sequence
+    }
+
+    /// 词语的拆分序列，没有定义组词规则时为 None
+    pub fn words_sequence(&self) -> Option<&[Sequence]> {
+        self.words_sequence.as_deref()
     }
 
     pub fn encode_short(
@@ -252,17 +767,27 @@ impl Encoder {
     ) {
         let schemes = self.short_code_schemes.as_ref().unwrap(); // 调用函数之前已经判断过了
         let mut short_occupation = vec![false; self.get_space()];
-        for ((full, _), pointer) in zip(full_codes, short_codes) {
+        // 每一级简码已经分配出去的数量
+        let mut assigned = vec![0; schemes.len()];
+        for (index, ((full, _), pointer)) in zip(full_codes, short_codes).enumerate() {
             let mut has_reduced = false;
-            for scheme in schemes {
+            for (scheme_index, scheme) in schemes.iter().enumerate() {
                 let CompiledShortCodeConfig {
                     prefix,
                     select_keys,
+                    limit,
+                    eligible,
                 } = scheme;
                 // 如果根本没有这么多码，就放弃
                 if *full < self.radix.pow((*prefix - 1) as u32) {
                     continue;
                 }
+                // 这个字不能取这一级简码，或者这一级简码已经分配完了
+                if eligible.as_ref().is_some_and(|x| !x[index])
+                    || limit.is_some_and(|x| assigned[scheme_index] >= x)
+                {
+                    continue;
+                }
                 // 首先将全码截取一部分出来
                 let modulo = self.radix.pow(*prefix as u32);
                 let prefix = full % modulo;
@@ -277,6 +802,7 @@ impl Encoder {
                     if !full_occupation[short] && !short_occupation[short] {
                         short_occupation[short] = true;
                         *pointer = (short, false);
+                        assigned[scheme_index] += 1;
                         has_reduced = true;
                         break;
                     }
@@ -285,20 +811,88 @@ impl Encoder {
                     break;
                 }
             }
-            if has_reduced == false {
+            if !has_reduced {
                 *pointer = (*full, short_occupation[*full]);
                 short_occupation[*full] = true;
             }
         }
     }
 
+    /// 取得简码的字如何处理它的全码
+    pub fn full_code_policy(&self) -> FullCodePolicy {
+        self.config.full_code_policy.unwrap_or_default()
+    }
+
+    /// 按照全码处理方式调整单字全码，评测指标、导出的码表和词语的重码都以调整的结果为准。取得简码的字在删除全码时
+    /// 不再占用全码，它实际输出的编码就是简码；在后置全码时排在同一编码上所有没有简码的字之后；保留全码时不需要调用
+    pub fn adjust_full(&self, full_codes: &Codes, short_codes: &Codes) -> Codes {
+        let policy = self.full_code_policy();
+        let mut occupied: HashSet<Code> = HashSet::with_capacity(full_codes.len());
+        let mut result: Codes = full_codes.clone();
+        let shortened = |index: usize| short_codes[index].0 != full_codes[index].0;
+        // 先让没有简码的字按字频依次占用全码
+        for (index, (code, duplicated)) in result.iter_mut().enumerate() {
+            if !shortened(index) {
+                *duplicated = !occupied.insert(*code);
+            }
+        }
+        for (index, pointer) in result.iter_mut().enumerate() {
+            if !shortened(index) {
+                continue;
+            }
+            match policy {
+                FullCodePolicy::Keep => pointer.1 = false,
+                FullCodePolicy::Remove => *pointer = short_codes[index],
+                FullCodePolicy::Demote => pointer.1 = !occupied.insert(pointer.0),
+            }
+        }
+        result
+    }
+
     pub fn encode_character_full(
         &self,
         keymap: &KeyMap,
         output: &mut Codes,
         occupation: &mut Occupation,
     ) {
-        self.encode_full(keymap, &self.characters_sequence, output, occupation)
+        if !self.choose_splits() {
+            return self.encode_full(keymap, &self.characters_sequence, output, occupation);
+        }
+        for (index, pointer) in output.iter_mut().enumerate() {
+            let code = self.encode_sequence(keymap, &self.characters_sequence[index]);
+            // 第一种拆分与更常用的字重码时，依次尝试其他拆分
+            let code = if occupation[code] {
+                self.alternatives[index]
+                    .iter()
+                    .map(|x| self.encode_sequence(keymap, x))
+                    .find(|x| !occupation[*x])
+                    .unwrap_or(code)
+            } else {
+                code
+            };
+            *pointer = (code, occupation[code]);
+            occupation[code] = true;
+        }
+    }
+
+    /// 是否在每次编码时为一字多拆的字选取拆分
+    fn choose_splits(&self) -> bool {
+        self.config.alternative_splits == Some(AlternativeSplits::Best)
+            && self.alternatives.iter().any(|x| !x.is_empty())
+    }
+
+    /// 容错码：一字多拆时，第二种及以后的拆分得到的编码，按字表的顺序排列
+    fn tolerance_codes(&self, keymap: &KeyMap) -> Vec<(usize, Code)> {
+        if self.config.alternative_splits != Some(AlternativeSplits::Tolerance) {
+            return vec![];
+        }
+        let mut codes = vec![];
+        for (index, alternatives) in self.alternatives.iter().enumerate() {
+            for sequence in alternatives {
+                codes.push((index, self.encode_sequence(keymap, sequence)));
+            }
+        }
+        codes
     }
 
     pub fn encode_words_full(
@@ -315,12 +909,168 @@ impl Encoder {
         )
     }
 
-    fn signed_index<T: Debug>(vector: &Vec<T>, index: isize) -> &T {
-        return if index >= 0 {
-            &vector[index as usize]
+    fn signed_index<T: Debug>(vector: &[T], index: isize) -> Option<&T> {
+        if index >= 0 {
+            vector.get(index as usize)
         } else {
-            &vector[vector.len() - (-index as usize)]
+            vector.len().checked_sub(-index as usize).and_then(|x| vector.get(x))
+        }
+    }
+
+    /// 列出每个单字简码的分配结果。简码由全码的前缀和可能补上的选择键组成，
+    /// 全码有同样前缀、自己却没有取得这个简码的字都是竞争者，它们因为字频较低或者不符合条件而落选
+    fn audit_short_codes(
+        &self,
+        full_codes: &Codes,
+        short_codes: &Codes,
+        representation: &Representation,
+    ) -> Vec<ShortCodeAssignment> {
+        // 以前缀的长度和前缀本身为键，记录取得了以之为前缀的简码的字
+        let mut prefixes: Vec<Option<(u32, Code)>> = vec![None; full_codes.len()];
+        let mut winners: HashMap<(u32, Code), Vec<usize>> = HashMap::new();
+        for (index, ((full, _), (short, _))) in zip(full_codes, short_codes).enumerate() {
+            if full == short {
+                continue;
+            }
+            let mut length = short.ilog(self.radix) + 1;
+            if full % self.radix.pow(length) != *short {
+                length -= 1; // 简码末尾补了选择键
+            }
+            let key = (length, short % self.radix.pow(length));
+            prefixes[index] = Some(key);
+            winners.entry(key).or_default().push(index);
+        }
+        // 已经取得了更短的简码的字不参与竞争
+        let lengths: HashSet<u32> = winners.keys().map(|x| x.0).collect();
+        let mut contenders: HashMap<(u32, Code), Vec<usize>> = HashMap::new();
+        for (index, (full, _)) in full_codes.iter().enumerate() {
+            for length in lengths.iter() {
+                let key = (*length, full % self.radix.pow(*length));
+                let competing = prefixes[index].is_none_or(|x| x.0 > *length);
+                if competing && winners.contains_key(&key) {
+                    contenders.entry(key).or_default().push(index);
+                }
+            }
+        }
+        let mut assignments = vec![];
+        for (index, key) in prefixes.iter().enumerate() {
+            if let Some(key) = key {
+                let losers = contenders.get(key).map(|x| x.as_slice()).unwrap_or_default();
+                assignments.push(ShortCodeAssignment {
+                    code: representation.repr_code(short_codes[index].0).iter().collect(),
+                    item: self.characters[index].to_string(),
+                    contenders: losers.iter().map(|x| self.characters[*x].to_string()).collect(),
+                });
+            }
+        }
+        assignments
+    }
+
+    /// 每个编码在候选中的位置（首选为 0），counts 记录了此前每个编码已经出现的次数
+    fn candidate_ranks(codes: &Codes, counts: &mut HashMap<Code, usize>) -> Vec<usize> {
+        codes
+            .iter()
+            .map(|(code, _)| {
+                let count = counts.entry(*code).or_insert(0);
+                *count += 1;
+                *count - 1
+            })
+            .collect()
+    }
+
+    /// 第 rank 个候选的击键：全码中统一添加的首选键换成相应的选择键，没有选择键的编码在末尾补上
+    fn candidate_keys(&self, code: Code, rank: usize, representation: &Representation) -> String {
+        let mut keys = representation.repr_code(code);
+        if rank > 0 {
+            let key = self.select_keys.get(rank).or(self.select_keys.last()).unwrap(); // 选择键不能为空
+            let key = representation.repr_key[key];
+            if keys.last() == Some(&representation.repr_key[&self.select_keys[0]]) {
+                keys.pop();
+            }
+            keys.push(key);
+        }
+        keys.into_iter().collect()
+    }
+
+    /// 按照当前方案输入一段文本：用正向最大匹配把文本切分成词和单字，单字有简码时按简码输入，
+    /// 词按全码输入，重码按候选中的位置使用相应的选择键
+    pub fn simulate(
+        &self,
+        keymap: &KeyMap,
+        representation: &Representation,
+        text: &str,
+    ) -> SimulationExport {
+        let buffer = self.encode_buffer(keymap);
+        let characters = buffer.characters_short.as_ref().unwrap_or(&buffer.characters_full);
+        let words = buffer.words_full.as_ref();
+        let (character_ranks, word_ranks) = match &self.merged_order {
+            // 词语排在所有单字的全码之后
+            None => {
+                let character_ranks = Self::candidate_ranks(characters, &mut HashMap::new());
+                let mut counts = HashMap::new();
+                Self::candidate_ranks(&buffer.characters_full, &mut counts);
+                let word_ranks =
+                    words.map(|x| Self::candidate_ranks(x, &mut counts)).unwrap_or_default();
+                (character_ranks, word_ranks)
+            }
+            Some(merged) => {
+                let mut counts: HashMap<Code, usize> = HashMap::new();
+                let mut character_ranks = vec![0; self.characters.len()];
+                let mut word_ranks = vec![0; words.map_or(0, |x| x.len())];
+                for candidate in merged {
+                    let (code, rank) = match *candidate {
+                        Candidate::Character(index) => {
+                            (buffer.characters_full[index].0, &mut character_ranks[index])
+                        }
+                        Candidate::Word(index) => (words.unwrap()[index].0, &mut word_ranks[index]),
+                    };
+                    let count = counts.entry(code).or_insert(0);
+                    *rank = *count;
+                    *count += 1;
+                }
+                // 有简码时单字按简码输入，只与其他字的简码比较
+                if let Some(short_codes) = buffer.characters_short.as_ref() {
+                    character_ranks = Self::candidate_ranks(short_codes, &mut HashMap::new());
+                }
+                (character_ranks, word_ranks)
+            }
+        };
+        let word_list = self.words.as_deref().unwrap_or_default();
+        let mut result = SimulationExport {
+            keystrokes: String::new(),
+            characters: 0,
+            keys: 0,
+            selections: 0,
         };
+        let symbols: Vec<char> = self.punctuation.iter().map(|x| x.0).collect();
+        let known: HashSet<char> = self.characters.iter().chain(&symbols).copied().collect();
+        let chars: Vec<char> = text.chars().collect();
+        let mut position = 0;
+        for item in corpus::segment(text, &self.characters, word_list, &symbols) {
+            let (code, rank, length) = match item {
+                CorpusItem::Character(index) => (characters[index].0, character_ranks[index], 1),
+                // 标点的编码已经包括了选择键，不需要选重
+                CorpusItem::Symbol(index) => (self.punctuation[index].1, 0, 1),
+                CorpusItem::Word { index, length } => {
+                    (words.unwrap()[index].0, word_ranks[index], length) // 切分出词说明有词表
+                }
+                CorpusItem::Break => {
+                    // 连续的中断在切分时合并成了一项，这里原样输出所有不在字词表中的字符
+                    while position < chars.len() && !known.contains(&chars[position]) {
+                        result.keystrokes.push(chars[position]);
+                        position += 1;
+                    }
+                    continue;
+                }
+            };
+            let keys = self.candidate_keys(code, rank, representation);
+            result.keys += keys.chars().count();
+            result.keystrokes.push_str(&keys);
+            result.characters += length;
+            result.selections += usize::from(rank > 0);
+            position += length;
+        }
+        result
     }
 
     pub fn init_buffer(&self) -> Buffer {
@@ -331,23 +1081,129 @@ impl Encoder {
                 .as_ref()
                 .map(|_| vec![(0, false); self.characters.len()]),
             words_full: self.words.as_ref().map(|x| vec![(0, false); x.len()]),
+            characters_index: CodeIndex::default(),
+            words_index: CodeIndex::default(),
+            traditional_full: None,
+            traditional_index: CodeIndex::default(),
         }
     }
 
-    pub fn encode(&self, keymap: &KeyMap, representation: &Representation) -> EncodeExport {
+    /// 出简不出全时，按照全码处理方式调整之后的单字全码；其他的处理方式不改变全码，返回 None
+    fn adjusted_full(&self, buffer: &Buffer) -> Option<Codes> {
+        let short_codes = buffer.characters_short.as_ref()?;
+        (self.full_code_policy() == FullCodePolicy::Remove)
+            .then(|| self.adjust_full(&buffer.characters_full, short_codes))
+    }
+
+    /// 依次计算单字全码、单字简码和词语全码，与 encode 的结果一致
+    fn encode_buffer(&self, keymap: &KeyMap) -> Buffer {
         let mut buffer = self.init_buffer();
         let mut occupation: Occupation = vec![false; self.get_space()];
         self.encode_character_full(keymap, &mut buffer.characters_full, &mut occupation);
         if self.short_code_schemes.is_some() {
             self.encode_short(
-                &mut buffer.characters_full,
+                &buffer.characters_full,
                 buffer.characters_short.as_mut().unwrap(),
-                &mut occupation,
+                &occupation,
             );
+            // 出简不出全时，取得简码的字的全码让给词语使用，词语按照调整之后的单字编码判断重码
+            if let Some(adjusted) = self.adjusted_full(&buffer) {
+                for (code, _) in &buffer.characters_full {
+                    occupation[*code] = false;
+                }
+                for (code, _) in &adjusted {
+                    occupation[*code] = true;
+                }
+            }
+        }
+        if let Some(words_full) = buffer.words_full.as_mut() {
+            self.encode_words_full(keymap, words_full, &mut occupation);
+        }
+        buffer
+    }
+
+    /// 按编码汇总所有字词，同一编码上的字词按候选顺序排列：单字简码在前，然后是按重码排列方式
+    /// 排列的单字全码和词语全码（按照全码处理方式删除或者后置取得简码的字），然后是标点，最后是容错码
+    fn reverse_lookup(
+        &self,
+        keymap: &KeyMap,
+        buffer: &Buffer,
+        representation: &Representation,
+    ) -> Vec<CodeEntry> {
+        let mut table: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut insert = |code: Code, item: String| {
+            let code: String = representation.repr_code(code).into_iter().collect();
+            table.entry(code).or_default().push(item);
+        };
+        let full_codes = &buffer.characters_full;
+        let shortened = |index: usize| {
+            buffer.characters_short.as_ref().is_some_and(|x| x[index].0 != full_codes[index].0)
+        };
+        if let Some(short_codes) = buffer.characters_short.as_ref() {
+            for (index, (code, _)) in short_codes.iter().enumerate() {
+                if shortened(index) {
+                    insert(*code, self.characters[index].to_string());
+                }
+            }
+        }
+        let policy = self.full_code_policy();
+        let words = self.words.as_ref().zip(buffer.words_full.as_ref());
+        let default_order: Vec<Candidate>;
+        let order = match &self.merged_order {
+            Some(merged) => &merged[..],
+            None => {
+                let characters = (0..full_codes.len()).map(Candidate::Character);
+                let words = (0..words.map_or(0, |x| x.0.len())).map(Candidate::Word);
+                default_order = characters.chain(words).collect();
+                &default_order[..]
+            }
+        };
+        // 后置的全码排在所有单字全码之后；字词合在一起排列候选时，排在所有字词之后
+        let mut demoted = vec![];
+        for candidate in order {
+            match *candidate {
+                Candidate::Character(index) => {
+                    if !shortened(index) || policy == FullCodePolicy::Keep {
+                        insert(full_codes[index].0, self.characters[index].to_string());
+                    } else if policy == FullCodePolicy::Demote {
+                        demoted.push(index);
+                    }
+                }
+                Candidate::Word(index) => {
+                    if self.merged_order.is_none() {
+                        for index in demoted.drain(..) {
+                            insert(full_codes[index].0, self.characters[index].to_string());
+                        }
+                    }
+                    let (words, codes) = words.unwrap(); // 有词语的候选说明有词表
+                    insert(codes[index].0, words[index].clone());
+                }
+            }
         }
+        for index in demoted {
+            insert(full_codes[index].0, self.characters[index].to_string());
+        }
+        for (symbol, code) in &self.punctuation {
+            insert(*code, symbol.to_string());
+        }
+        for (index, code) in self.tolerance_codes(keymap) {
+            insert(code, self.characters[index].to_string());
+        }
+        table.into_iter().map(|(code, items)| CodeEntry { code, items }).collect()
+    }
+
+    pub fn encode(&self, keymap: &KeyMap, representation: &Representation) -> EncodeExport {
+        let buffer = self.encode_buffer(keymap);
+        let short_codes = buffer
+            .characters_short
+            .as_ref()
+            .map(|x| self.audit_short_codes(&buffer.characters_full, x, representation));
+        let codes = self.reverse_lookup(keymap, &buffer, representation);
+        let adjusted = self.adjusted_full(&buffer);
+        let full_codes = adjusted.as_ref().unwrap_or(&buffer.characters_full);
         let mut character_entries: Vec<Entry> = Vec::new();
         for (index, character) in self.characters.iter().enumerate() {
-            let full = representation.repr_code(buffer.characters_full[index].0);
+            let full = representation.repr_code(full_codes[index].0);
             let short = buffer
                 .characters_short
                 .as_ref()
@@ -360,7 +1216,6 @@ impl Encoder {
         }
         let mut word_entries: Option<Vec<Entry>> = None;
         if let Some(words) = self.words.as_ref() {
-            self.encode_words_full(keymap, buffer.words_full.as_mut().unwrap(), &mut occupation);
             let entries = words
                 .iter()
                 .enumerate()
@@ -376,9 +1231,160 @@ impl Encoder {
                 .collect();
             word_entries = Some(entries);
         }
+        let punctuation_entries = (!self.punctuation.is_empty()).then(|| {
+            self.punctuation
+                .iter()
+                .map(|(symbol, code)| Entry {
+                    item: symbol.to_string(),
+                    full: representation.repr_code(*code).iter().collect(),
+                    short: None,
+                })
+                .collect()
+        });
         EncodeExport {
             characters: character_entries,
             words: word_entries,
+            punctuation: punctuation_entries,
+            short_codes,
+            codes,
+        }
+    }
+}
+
+impl Encoder for DefaultEncoder {
+    fn encode(&self, keymap: &KeyMap) -> Codes {
+        let mut codes = vec![(0, false); self.characters.len()];
+        let mut occupation: Occupation = vec![false; self.get_space()];
+        self.encode_character_full(keymap, &mut codes, &mut occupation);
+        codes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DefaultEncoder;
+    use crate::{
+        constraints::Constraints, objectives::Objective, representation::Representation, testing,
+    };
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::HashSet;
+
+    const OPTIMIZATION: &str = r#"
+  objective:
+    characters_full:
+      duplication: 1.0
+    characters_short:
+      duplication: 1.0
+    words_full:
+      duplication: 1.0
+  metaheuristic:
+    algorithm: SimulatedAnnealing
+"#;
+
+    const ENCODER: &str = r#"
+max_length: 4
+select_keys: ["_"]
+rules:
+  - length_equal: 2
+    formula: AaAbBaBb
+  - length_in_range: [3, 10]
+    formula: AaBaCaZa
+short_code_schemes:
+  - { prefix: 1 }
+  - { prefix: 2 }
+"#;
+
+    /// 经过一串随机的移动和交换之后，增量编码的结果与从头完整编码的结果完全相同，包括简码和重码的重新判断。
+    /// 编码器内部的倒排索引和各个编码上的候选顺序也与从头建立的相同
+    #[test]
+    fn incremental_encoding_matches_full_encoding() {
+        let variants = ["", "duplicate_order: frequency\n", "full_code_policy: demote\n"];
+        for variant in variants {
+            check_incremental_encoding(&format!("{ENCODER}{variant}"));
+        }
+    }
+
+    fn check_incremental_encoding(encoder: &str) {
+        let config = testing::config(OPTIMIZATION, Some(encoder));
+        let representation = Representation::new(config).unwrap();
+        let assets = testing::assets(true);
+        let encoder = testing::encoder(&representation, &assets, true);
+        let objective = Objective::new(&representation, encoder.clone(), assets).unwrap();
+        let constraints = Constraints::new(&representation).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut candidate = representation.initial.clone();
+        let mut buffer = encoder.init_buffer();
+        for _ in 0..500 {
+            candidate = if rng.gen_bool(0.5) {
+                constraints.constrained_random_move(&candidate, &mut rng)
+            } else {
+                constraints.constrained_random_swap(&candidate, &mut rng)
+            };
+            let (metric, loss) = objective.evaluate(&candidate, &mut buffer).unwrap();
+            let mut fresh = encoder.init_buffer();
+            let (fresh_metric, fresh_loss) = objective.evaluate(&candidate, &mut fresh).unwrap();
+            assert_eq!(buffer.characters_full, fresh.characters_full);
+            assert_eq!(buffer.characters_short, fresh.characters_short);
+            assert_eq!(buffer.words_full, fresh.words_full);
+            assert_eq!(buffer.characters_index.buckets, fresh.characters_index.buckets);
+            assert_eq!(buffer.words_index.buckets, fresh.words_index.buckets);
+            assert_eq!(loss, fresh_loss);
+            assert_eq!(metric.to_string(), fresh_metric.to_string());
+            let full = encoder.encode_buffer(&candidate);
+            assert_eq!(buffer.characters_full, full.characters_full);
+            assert_eq!(buffer.characters_short, full.characters_short);
+        }
+    }
+
+    /// 出简不出全时，导出的码表与评测指标一致：按导出的单字全码统计的选重率与单字全码的选重率相同。
+    /// 这里「香」和「困」的全码相同，「香」取得简码之后删除全码，「困」就不再是重码
+    #[test]
+    fn removed_full_codes_are_exported_as_measured() {
+        let optimization = r#"
+  objective:
+    characters_full:
+      duplication: 1.0
+  metaheuristic:
+    algorithm: SimulatedAnnealing
+"#;
+        let encoder = format!("{ENCODER}full_code_policy: remove\n");
+        let encoder = encoder.replace("{ prefix: 2 }", "{ prefix: 2, characters: 香 }");
+        let config = testing::config(optimization, Some(&encoder));
+        let representation = Representation::new(config).unwrap();
+        let assets = testing::assets(false);
+        let frequency = assets.character_frequency.clone();
+        let characters = [('口', "口"), ('木', "木"), ('一', "一"), ('香', "日 禾 一"), ('困', "口 木 一")];
+        let characters = characters.iter().map(|(x, y)| (*x, y.to_string())).collect();
+        let encoder = DefaultEncoder::new(&representation, characters, vec![], &assets).unwrap();
+        let objective = Objective::new(&representation, encoder.clone(), assets).unwrap();
+        let keymap = &representation.initial;
+        let (metric, _) = objective.evaluate(keymap, &mut encoder.init_buffer()).unwrap();
+        let export = encoder.encode(keymap, &representation);
+        let entry = |item: &str| export.characters.iter().find(|x| x.item == item).unwrap();
+        assert_eq!(entry("香").short.as_deref(), Some("ab"));
+        assert_eq!(entry("香").full, "ab");
+        assert_eq!(entry("困").full, "abe");
+        let mut occupied = HashSet::new();
+        let (mut duplicated, mut total) = (0, 0);
+        for entry in &export.characters {
+            let frequency = frequency[&entry.item.chars().next().unwrap()];
+            total += frequency;
+            if !occupied.insert(entry.full.clone()) {
+                duplicated += frequency;
+            }
+        }
+        let expected = duplicated as f64 / total as f64;
+        let measured = metric.characters.unwrap().duplication.unwrap();
+        assert!((expected - measured).abs() < 1e-12, "{} {}", expected, measured);
+    }
+
+    #[test]
+    fn counts_must_be_positive() {
+        assert_eq!(DefaultEncoder::parse_counts("1+Z2", 4).unwrap().len(), 3);
+        for formula in ["1+0", "1+-1", "A1+Z-2"] {
+            let error = DefaultEncoder::parse_counts(formula, 4).err().unwrap();
+            assert!(error.message.contains("不合法"), "{}", error.message);
         }
     }
 }
@@ -3,12 +3,16 @@
 //! 定义了一个特征，指定了所有在退火计算的过程中需要向用户反馈的数据。命令行界面、Web 界面只需要各自实现这些方法，就可向用户报告各种用户数据，实现方式可以很不一样。
 
 use crate::config::Config;
+use crate::metaheuristics::simulated_annealing::Schedule;
+use crate::representation::TrajectoryEntry;
 
 pub trait Interface {
     fn prepare_output(&self);
 
     fn init_autosolve(&self);
 
+    fn report_search_space(&self, movable: usize, size: f64);
+
     fn report_trial_t_max(&self, temperature: f64, accept_rate: f64);
 
     fn report_t_max(&self, temperature: f64);
@@ -21,7 +25,78 @@ pub trait Interface {
 
     fn report_elapsed(&self, time: u128);
 
-    fn report_schedule(&self, step: usize, temperature: f64, metric: String);
+    fn report_step_estimate(&self, per_step: f64, budget: u64, steps: usize);
+
+    fn report_schedule(&self, step: usize, temperature: f64, schedule: Schedule, metric: String);
+
+    fn report_progress(&self, step: usize, metric: String);
+
+    fn report_status(&self, step: usize, steps: usize, temperature: f64, best: f64);
+
+    fn report_early_stop(&self, step: usize, stagnation: usize);
+
+    fn report_reheat(&self, step: usize, temperature: f64);
 
     fn report_solution(&self, config: Config, metric: String, save: bool);
+
+    fn report_restarts(&self, losses: &[f64]);
+
+    fn report_stage(&self, stage: usize, total: usize, fixed: usize);
+
+    fn report_warm_start(&self, losses: &[(String, f64)], best: usize);
+
+    fn report_pareto_front(&self, components: &[String], front: Vec<(Config, Vec<f64>, String)>);
+
+    fn report_checkpoint(&self, step: usize, checkpoint: Vec<u8>);
+
+    fn report_move(&self, entry: TrajectoryEntry);
+}
+
+/// 不向用户报告任何数据的接口，用于在后台运行的求解过程（例如多次重启中的每一次）
+pub struct SilentInterface;
+
+impl Interface for SilentInterface {
+    fn prepare_output(&self) {}
+
+    fn init_autosolve(&self) {}
+
+    fn report_search_space(&self, _: usize, _: f64) {}
+
+    fn report_trial_t_max(&self, _: f64, _: f64) {}
+
+    fn report_t_max(&self, _: f64) {}
+
+    fn report_trial_t_min(&self, _: f64, _: f64) {}
+
+    fn report_t_min(&self, _: f64) {}
+
+    fn report_parameters(&self, _: f64, _: f64, _: usize) {}
+
+    fn report_elapsed(&self, _: u128) {}
+
+    fn report_step_estimate(&self, _: f64, _: u64, _: usize) {}
+
+    fn report_schedule(&self, _: usize, _: f64, _: Schedule, _: String) {}
+
+    fn report_progress(&self, _: usize, _: String) {}
+
+    fn report_status(&self, _: usize, _: usize, _: f64, _: f64) {}
+
+    fn report_early_stop(&self, _: usize, _: usize) {}
+
+    fn report_reheat(&self, _: usize, _: f64) {}
+
+    fn report_solution(&self, _: Config, _: String, _: bool) {}
+
+    fn report_restarts(&self, _: &[f64]) {}
+
+    fn report_stage(&self, _: usize, _: usize, _: usize) {}
+
+    fn report_warm_start(&self, _: &[(String, f64)], _: usize) {}
+
+    fn report_pareto_front(&self, _: &[String], _: Vec<(Config, Vec<f64>, String)>) {}
+
+    fn report_checkpoint(&self, _: usize, _: Vec<u8>) {}
+
+    fn report_move(&self, _: TrajectoryEntry) {}
 }
@@ -8,16 +8,21 @@ pub mod metaheuristics;
 pub mod objectives;
 pub mod problem;
 pub mod representation;
+#[cfg(test)]
+mod testing;
+
+pub use encoder::{DefaultEncoder, Encoder};
+pub use metaheuristics::{Constructive, Evolutionary, Metaheuristic, Metaheuristics, MultiObjective};
 
 use crate::constraints::Constraints;
 use crate::problem::ElementPlacementProblem;
 use crate::{
     config::Config,
-    encoder::Encoder,
     objectives::Objective,
-    representation::{Assets, Representation},
+    representation::{Assets, Representation, TrajectoryEntry},
 };
 use interface::Interface;
+use metaheuristics::simulated_annealing::Schedule;
 use js_sys::Function;
 use representation::{RawSequenceMap, WordList};
 use serde::{Deserialize, Serialize};
@@ -49,7 +54,8 @@ enum Message {
     },
     Progress {
         steps: usize,
-        temperature: f64,
+        temperature: Option<f64>,
+        schedule: Option<String>,
         metric: String,
     },
     BetterSolution {
@@ -57,6 +63,36 @@ enum Message {
         config: String,
         save: bool,
     },
+    Stage {
+        stage: usize,
+        total: usize,
+        fixed: usize,
+    },
+    Restarts {
+        losses: Vec<f64>,
+    },
+    EarlyStop {
+        steps: usize,
+    },
+    SearchSpace {
+        movable: usize,
+        size: f64,
+    },
+    Reheat {
+        steps: usize,
+        temperature: f64,
+    },
+    ParetoFront {
+        components: Vec<String>,
+        front: Vec<ParetoSolution>,
+    },
+}
+
+#[derive(Serialize)]
+struct ParetoSolution {
+    config: String,
+    values: Vec<f64>,
+    metric: String,
 }
 
 impl WebInterface {
@@ -78,6 +114,12 @@ impl Interface for WebInterface {
 
     fn report_elapsed(&self, _: u128) {}
 
+    fn report_search_space(&self, movable: usize, size: f64) {
+        let _ = self.post(Message::SearchSpace { movable, size });
+    }
+
+    fn report_step_estimate(&self, _: f64, _: u64, _: usize) {}
+
     fn report_trial_t_max(&self, t_max: f64, _: f64) {
         let _ = self.post(Message::Parameters {
             t_max: Some(t_max),
@@ -119,10 +161,21 @@ impl Interface for WebInterface {
         let _ = self.post(message);
     }
 
-    fn report_schedule(&self, steps: usize, temperature: f64, metric: String) {
+    fn report_schedule(&self, steps: usize, temperature: f64, schedule: Schedule, metric: String) {
         let message = Message::Progress {
             steps,
-            temperature,
+            temperature: Some(temperature),
+            schedule: Some(format!("{:?}", schedule)),
+            metric,
+        };
+        let _ = self.post(message);
+    }
+
+    fn report_progress(&self, steps: usize, metric: String) {
+        let message = Message::Progress {
+            steps,
+            temperature: None,
+            schedule: None,
             metric,
         };
         let _ = self.post(message);
@@ -135,9 +188,58 @@ impl Interface for WebInterface {
             save,
         });
     }
+
+    fn report_early_stop(&self, steps: usize, _: usize) {
+        let _ = self.post(Message::EarlyStop { steps });
+    }
+
+    fn report_reheat(&self, steps: usize, temperature: f64) {
+        let _ = self.post(Message::Reheat { steps, temperature });
+    }
+
+    fn report_pareto_front(&self, components: &[String], front: Vec<(Config, Vec<f64>, String)>) {
+        let front = front
+            .into_iter()
+            .map(|(config, values, metric)| ParetoSolution {
+                config: serde_yaml::to_string(&config).unwrap(),
+                values,
+                metric,
+            })
+            .collect();
+        let _ = self.post(Message::ParetoFront {
+            components: components.to_vec(),
+            front,
+        });
+    }
+
+    fn report_restarts(&self, losses: &[f64]) {
+        let _ = self.post(Message::Restarts {
+            losses: losses.to_vec(),
+        });
+    }
+
+    fn report_stage(&self, stage: usize, total: usize, fixed: usize) {
+        let _ = self.post(Message::Stage {
+            stage,
+            total,
+            fixed,
+        });
+    }
+
+    // Web 界面只有一个方案文件，不会从多个方案热启动
+    fn report_warm_start(&self, _: &[(String, f64)], _: usize) {}
+
+    // 浏览器中无法写入文件，所以 Web 界面不保存检查点
+    fn report_checkpoint(&self, _: usize, _: Vec<u8>) {}
+
+    // 同理，Web 界面也不保存优化轨迹
+    fn report_move(&self, _: TrajectoryEntry) {}
+
+    // Web 界面根据 report_schedule 的消息自行显示进度
+    fn report_status(&self, _: usize, _: usize, _: f64, _: f64) {}
 }
 
-fn prepare(js_input: JsValue) -> Result<(Representation, Encoder, Assets), JsError> {
+fn prepare(js_input: JsValue) -> Result<(Representation, DefaultEncoder, Assets), JsError> {
     let Input {
         config,
         characters,
@@ -145,7 +247,7 @@ fn prepare(js_input: JsValue) -> Result<(Representation, Encoder, Assets), JsErr
         assets,
     } = serde_wasm_bindgen::from_value(js_input)?;
     let representation = Representation::new(config)?;
-    let encoder = Encoder::new(&representation, characters, words, &assets)?;
+    let encoder = DefaultEncoder::new(&representation, characters, words, &assets)?;
     Ok((representation, encoder, assets))
 }
 
@@ -162,7 +264,7 @@ pub fn evaluate(js_input: JsValue) -> Result<JsValue, JsError> {
     console_error_panic_hook::set_once();
     let (representation, encoder, assets) = prepare(js_input)?;
     let mut buffer = encoder.init_buffer();
-    let objective = Objective::new(&representation, encoder, assets);
+    let objective = Objective::new(&representation, encoder, assets)?;
     let (metric, _) = objective.evaluate(&representation.initial, &mut buffer)?;
     let metric = format!("{}", metric);
     Ok(to_value(&metric)?)
@@ -173,11 +275,11 @@ pub fn optimize(js_input: JsValue, post_message: Function) -> Result<(), JsError
     console_error_panic_hook::set_once();
     let (representation, encoder, assets) = prepare(js_input)?;
     let mut buffer = encoder.init_buffer();
-    let objective = Objective::new(&representation, encoder, assets);
+    let objective = Objective::new(&representation, encoder, assets)?;
     let constraints = Constraints::new(&representation)?;
     let _ = objective.evaluate(&representation.initial, &mut buffer)?;
-    let mut problem = ElementPlacementProblem::new(representation, constraints, objective, buffer);
+    let mut problem = ElementPlacementProblem::new(representation, constraints, objective, buffer)?;
     let web_interface = WebInterface::new(post_message);
-    problem.solve(&web_interface);
+    problem.solve(&web_interface)?;
     Ok(())
 }
@@ -5,37 +5,129 @@
 //! 具体用法详见 README.md 和 config.md。
 
 use chai::{representation::Representation, error::Error};
-use chai::encoder::Encoder;
+use chai::encoder::DefaultEncoder;
 use chai::objectives::Objective;
 use chai::constraints::Constraints;
 use chai::problem::ElementPlacementProblem;
+use chai::interface::Interface;
 mod cli;
 use crate::cli::{Cli, Command};
 use clap::Parser;
 
 fn main() -> Result<(), Error> {
     let cli = Cli::parse();
-    let (config, characters, words, assets) = cli.prepare_file();
+    cli.init_logger();
+    let (config, characters, words, assets) = cli.prepare_file()?;
     let representation = Representation::new(config)?;
-    let encoder = Encoder::new(&representation, characters, words, &assets)?;
+    let encoder = DefaultEncoder::new(&representation, characters, words, &assets)?;
     match cli.command {
-        Command::Encode => {
+        Command::Encode { reverse } => {
             let codes = encoder.encode(&representation.initial, &representation);
-            Cli::write_encode_results(codes);
+            cli.write_encode_results(codes, reverse);
         }
         Command::Evaluate => {
             let mut buffer = encoder.init_buffer();
-            let objective = Objective::new(&representation, encoder, assets);
+            let objective = Objective::new(&representation, encoder, assets)?;
             let (metric, _) = objective.evaluate(&representation.initial, &mut buffer)?;
-            Cli::report_metric(metric);
+            cli.report_metric(metric);
         }
-        Command::Optimize => {
+        Command::Optimize(ref args) => {
+            let mut candidates = Vec::new();
+            if let Some(directory) = &args.warm_start {
+                for (name, config) in Cli::read_warm_start(directory) {
+                    let keymap = representation
+                        .read_keymap(&config)
+                        .map_err(|e| Error::from(format!("{name}：{}", e.message)))?;
+                    candidates.push((name, keymap));
+                }
+            }
             let buffer = encoder.init_buffer();
-            let objective = Objective::new(&representation, encoder, assets);
+            let objective = Objective::new(&representation, encoder, assets)?;
             let constraints = Constraints::new(&representation)?;
             let mut problem =
-                ElementPlacementProblem::new(representation, constraints, objective, buffer);
-            problem.solve(&cli);
+                ElementPlacementProblem::new(representation, constraints, objective, buffer)?;
+            problem.warm_start(candidates, &cli)?;
+            problem.solve(&cli)?;
+        }
+        Command::Resume { ref checkpoint } => {
+            let checkpoint = Cli::read_checkpoint(checkpoint);
+            let buffer = encoder.init_buffer();
+            let objective = Objective::new(&representation, encoder, assets)?;
+            let constraints = Constraints::new(&representation)?;
+            let mut problem =
+                ElementPlacementProblem::new(representation, constraints, objective, buffer)?;
+            problem.resume(&checkpoint, &cli)?;
+        }
+        Command::Diff { ref new, top, .. } => {
+            let config = Cli::read_config(new);
+            let keymap = representation.read_keymap(&config)?;
+            let moves = representation.describe_moves(&representation.initial, &keymap);
+            let old_codes = encoder.encode(&representation.initial, &representation);
+            let new_codes = encoder.encode(&keymap, &representation);
+            let mut buffer = encoder.init_buffer();
+            let objective = Objective::new(&representation, encoder, assets.clone())?;
+            let (old_metric, old_loss) = objective.evaluate(&representation.initial, &mut buffer)?;
+            let (new_metric, new_loss) = objective.evaluate(&keymap, &mut buffer)?;
+            let old = (old_codes, old_metric, old_loss);
+            let new = (new_codes, new_metric, new_loss);
+            cli.report_diff(&moves, old, new, &assets, top);
+        }
+        Command::Rank {
+            ref directory,
+            ref csv,
+        } => {
+            let mut buffer = encoder.init_buffer();
+            let objective = Objective::new(&representation, encoder, assets)?;
+            let mut results = Vec::new();
+            for (name, config) in Cli::read_warm_start(directory) {
+                let keymap = representation
+                    .read_keymap(&config)
+                    .map_err(|e| Error::from(format!("{name}：{}", e.message)))?;
+                let (metric, loss) = objective.evaluate(&keymap, &mut buffer)?;
+                results.push((name, metric, loss));
+            }
+            results.sort_by(|a, b| a.2.total_cmp(&b.2));
+            cli.report_rank(&results, csv.as_deref());
+        }
+        Command::Export { target, ref name } => {
+            let codes = encoder.encode(&representation.initial, &representation);
+            let config = &representation.config;
+            cli.write_export(codes, config, &assets, target, name.clone());
+        }
+        Command::Simulate { ref text } => {
+            let text = Cli::read_text(text);
+            let result = encoder.simulate(&representation.initial, &representation, &text);
+            cli.write_simulation(result);
+        }
+        Command::Validate => {
+            let problems = Constraints::validate(&representation);
+            cli.report_validation(&problems);
+            if !problems.is_empty() {
+                return Err("方案文件的约束有问题".into());
+            }
+        }
+        Command::Replay { ref trajectory } => {
+            let entries = Cli::read_trajectory(trajectory);
+            let mut buffer = encoder.init_buffer();
+            let objective = Objective::new(&representation, encoder, assets)?;
+            let mut keymap = representation.initial.clone();
+            let (_, initial_loss) = objective.evaluate(&keymap, &mut buffer)?;
+            let mut expected_loss = initial_loss;
+            for (index, entry) in entries.iter().enumerate() {
+                representation.apply_moves(&mut keymap, &entry.changes)?;
+                expected_loss += entry.delta;
+                if (index + 1) % 1000 == 0 {
+                    let (metric, _) = objective.evaluate(&keymap, &mut buffer)?;
+                    cli.report_replay(entry.step, index + 1, &metric);
+                }
+            }
+            let (metric, loss) = objective.evaluate(&keymap, &mut buffer)?;
+            let step = entries.last().map_or(0, |x| x.step);
+            cli.report_replay(step, entries.len(), &metric);
+            cli.report_replay_result(loss, expected_loss);
+            cli.prepare_output();
+            let config = representation.update_config(&keymap);
+            cli.report_solution(config, format!("{}", metric), true);
         }
     }
     Ok(())
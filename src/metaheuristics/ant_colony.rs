@@ -0,0 +1,180 @@
+//! 蚁群算法
+//!
+//! 与其他基于扰动的算法不同，蚁群算法是构造式的：每只蚂蚁按照重要程度从高到低的顺序依次为每个元素选择按键，选择的概率由信息素和启发信息共同决定。启发信息倾向于把元素放在目前负载较轻的按键上，信息素则记录了历史上好的方案所做的选择。每一轮结束后，信息素挥发一部分，本轮最好和历史最好的方案再在它们所做的选择上留下信息素（最大最小蚂蚁系统）。
+//!
+//! 由于每只蚂蚁只在可行的按键中做选择，这个算法适合那些大部分元素都被窄化到少数几个按键上的方案。每一步的选择还要满足与已经做出的选择之间的约束（不能同键、按键容量、条件约束），如果某一步没有可行的选择，这只蚂蚁就放弃，不参与比较。
+
+use super::Constructive;
+use crate::interface::Interface;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::fmt::Display;
+
+/// 蚁群算法的参数，包括每一轮的蚂蚁数量、轮数、信息素挥发率，以及信息素和启发信息的指数
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub ants: usize,
+    pub iterations: usize,
+    pub evaporation: f64,
+    pub alpha: Option<f64>,
+    pub beta: Option<f64>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            ants: 20,
+            iterations: 500,
+            evaporation: 0.1,
+            alpha: Some(1.0),
+            beta: Some(2.0),
+        }
+    }
+}
+
+/// 按照权重随机选择一个下标
+fn roulette(weights: &[f64], threshold: f64) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut accumulated = 0.0;
+    for (index, weight) in weights.iter().enumerate() {
+        accumulated += weight;
+        if accumulated >= threshold * total {
+            return index;
+        }
+    }
+    weights.len() - 1
+}
+
+/// 蚁群算法求解的主函数
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Constructive<T, M>,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters {
+        ants,
+        iterations,
+        evaporation,
+        alpha,
+        beta,
+    } = parameters;
+    let ants = ants.max(1);
+    let evaporation = evaporation.clamp(0.001, 1.0);
+    let alpha = alpha.unwrap_or(1.0);
+    let beta = beta.unwrap_or(2.0);
+    let space = problem.construction_space();
+    // 只有一个取值的决策（例如固定的元素）先做，以便之后的选择与它们比较；其余的按照重要程度从高到低的顺序做决策
+    let mut order: Vec<usize> = (0..space.len()).collect();
+    order.sort_by(|&a, &b| {
+        let free = |x: usize| space[x].0.len() > 1;
+        free(a).cmp(&free(b)).then(space[b].1.total_cmp(&space[a].1))
+    });
+    let values = space
+        .iter()
+        .flat_map(|(choices, _)| choices.iter().copied())
+        .max()
+        .map_or(0, |x| x + 1);
+    let mean_load = space.iter().map(|(_, weight)| weight).sum::<f64>() / values.max(1) as f64;
+    // 信息素限制在一个区间内，防止过早收敛到某一个方案上
+    let tau_max = 1.0 / evaporation;
+    let tau_min = tau_max / 100.0;
+    let mut pheromone: Vec<Vec<f64>> = space
+        .iter()
+        .map(|(choices, _)| vec![tau_max; choices.len()])
+        .collect();
+
+    // 初始方案只有在满足约束时才作为比较的基准
+    let initial = problem.generate_candidate();
+    let mut best: Option<(T, (M, f64))> = problem.feasible(&initial).then(|| {
+        let rank = problem.rank_candidate(&initial);
+        (problem.clone_candidate(&initial), rank)
+    });
+    let mut best_choices: Option<Vec<usize>> = None;
+
+    for iteration in 0..iterations {
+        let progress = iteration as f64 / iterations as f64;
+        let mut iteration_best: Option<(Vec<usize>, T, (M, f64))> = None;
+        'ant: for _ in 0..ants {
+            let mut load = vec![0.0; values];
+            let mut choices = vec![0; space.len()];
+            let mut assignment = vec![0; space.len()];
+            let mut decided = vec![false; space.len()];
+            for &decision in &order {
+                let (options, weight) = &space[decision];
+                let weights: Vec<f64> = options
+                    .iter()
+                    .zip(&pheromone[decision])
+                    .map(|(value, tau)| {
+                        if !problem.admissible(&assignment, &decided, decision, *value) {
+                            return 0.0;
+                        }
+                        let eta = 1.0 / (1.0 + load[*value] / mean_load.max(f64::EPSILON));
+                        tau.powf(alpha) * eta.powf(beta)
+                    })
+                    .collect();
+                if weights.iter().all(|x| *x == 0.0) {
+                    continue 'ant;
+                }
+                let index = roulette(&weights, problem.rng().gen::<f64>());
+                choices[decision] = index;
+                assignment[decision] = options[index];
+                decided[decision] = true;
+                load[options[index]] += weight;
+            }
+            let candidate = problem.assemble_candidate(&assignment);
+            if !problem.feasible(&candidate) {
+                continue;
+            }
+            let rank = problem.rank_candidate(&candidate);
+            let is_better = iteration_best
+                .as_ref()
+                .is_none_or(|(_, _, best)| rank.1 < best.1);
+            if is_better {
+                iteration_best = Some((choices, candidate, rank));
+            }
+        }
+        let choices = iteration_best.as_ref().map(|(choices, _, _)| choices.clone());
+        if let Some((choices, candidate, rank)) = iteration_best {
+            if best.as_ref().is_none_or(|(_, best_rank)| rank.1 < best_rank.1) {
+                best_choices = Some(choices);
+                best = Some((candidate, rank));
+                let (best_candidate, best_rank) = best.as_ref().unwrap(); // 刚刚赋值
+                problem.save_candidate(
+                    best_candidate,
+                    best_rank,
+                    progress > report_after.unwrap_or(0.9),
+                    interface,
+                );
+            }
+        }
+        // 信息素挥发，然后由本轮最好和历史最好的方案留下信息素
+        for trail in pheromone.iter_mut() {
+            for tau in trail.iter_mut() {
+                *tau *= 1.0 - evaporation;
+            }
+        }
+        for deposit in [choices.as_ref(), best_choices.as_ref()].iter().flatten() {
+            for (trail, index) in pheromone.iter_mut().zip(deposit.iter()) {
+                trail[*index] += 0.5;
+            }
+        }
+        for trail in pheromone.iter_mut() {
+            for tau in trail.iter_mut() {
+                *tau = tau.clamp(tau_min, tau_max);
+            }
+        }
+        if let (0, Some((_, rank))) = (iteration % 10, &best) {
+            interface.report_progress(iteration, format!("{}", rank.0));
+        }
+    }
+    // 所有的蚂蚁都没有构造出可行的方案，并且初始方案也不可行时，只能返回初始方案
+    let Some((best_candidate, best_rank)) = best else {
+        return initial;
+    };
+    interface.report_progress(iterations, format!("{}", best_rank.0));
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
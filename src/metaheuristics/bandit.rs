@@ -0,0 +1,101 @@
+//! 多臂老虎机
+//!
+//! 用于自适应地选择扰动算子：记录每个算子最近的成功率（产生的新解比原来的解更好的比例），并且倾向于选择当前最有效的算子。由于优化的不同阶段各个算子的效果不同，成功率采用指数加权的滑动平均，使得较早的结果逐渐被遗忘。
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// 选择策略：固定是按照配置的比例随机选择；UCB 在成功率的基础上加上一个鼓励探索较少使用的算子的奖励；Softmax 按照成功率的指数函数成比例地选择
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    #[default]
+    Fixed,
+    Ucb,
+    Softmax,
+}
+
+/// 滑动平均的学习率
+const LEARNING_RATE: f64 = 0.01;
+/// UCB 中探索奖励的系数
+const EXPLORATION: f64 = 0.1;
+/// Softmax 的温度
+const SOFTMAX_TEMPERATURE: f64 = 0.05;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bandit {
+    strategy: Strategy,
+    weights: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub success_rates: Vec<f64>,
+}
+
+impl Bandit {
+    /// 创建一个老虎机，weights 是各个算子在固定策略下的比例，同时也作为其他策略下成功率的初始值的参考
+    pub fn new(strategy: Strategy, weights: Vec<f64>) -> Self {
+        let arms = weights.len();
+        Self {
+            strategy,
+            weights,
+            counts: vec![0; arms],
+            success_rates: vec![0.5; arms],
+        }
+    }
+
+    /// 按照权重随机选择一个下标
+    fn roulette<R: Rng + ?Sized>(weights: &[f64], rng: &mut R) -> usize {
+        let total: f64 = weights.iter().sum();
+        let threshold = rng.gen::<f64>() * total;
+        let mut accumulated = 0.0;
+        for (index, weight) in weights.iter().enumerate() {
+            accumulated += weight;
+            if accumulated > threshold {
+                return index;
+            }
+        }
+        weights.len() - 1
+    }
+
+    /// 选择一个算子
+    pub fn select<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        match self.strategy {
+            Strategy::Fixed => Self::roulette(&self.weights, rng),
+            Strategy::Ucb => {
+                let total = self.counts.iter().sum::<u64>() as f64;
+                let score = |arm: usize| {
+                    // 比例为零的算子不参与选择
+                    if self.weights[arm] <= 0.0 {
+                        return f64::NEG_INFINITY;
+                    }
+                    if self.counts[arm] == 0 {
+                        return f64::INFINITY;
+                    }
+                    let bonus = (total.ln() / self.counts[arm] as f64).sqrt();
+                    self.success_rates[arm] + EXPLORATION * bonus
+                };
+                (0..self.weights.len())
+                    .max_by(|&a, &b| score(a).total_cmp(&score(b)))
+                    .unwrap() // 至少有一个算子
+            }
+            Strategy::Softmax => {
+                let preferences: Vec<f64> = (0..self.weights.len())
+                    .map(|arm| {
+                        if self.weights[arm] <= 0.0 {
+                            0.0
+                        } else {
+                            (self.success_rates[arm] / SOFTMAX_TEMPERATURE).exp()
+                        }
+                    })
+                    .collect();
+                Self::roulette(&preferences, rng)
+            }
+        }
+    }
+
+    /// 记录一次使用某个算子的结果
+    pub fn update(&mut self, arm: usize, success: bool) {
+        self.counts[arm] += 1;
+        let reward = if success { 1.0 } else { 0.0 };
+        self.success_rates[arm] += LEARNING_RATE * (reward - self.success_rates[arm]);
+    }
+}
@@ -0,0 +1,61 @@
+//! 集束搜索
+//!
+//! 频率最高的十几个元素对目标函数的影响最大，值得系统地搜索。本模块按照频率从高到低的顺序，依次为这些元素尝试所有可行的按键，丢弃不满足约束的部分方案，每一步只保留目标函数最小的若干个部分方案（集束），其余元素保持初始方案中的按键不变。搜索的结果作为后续优化算法的初始方案。
+
+use super::Constructive;
+use crate::interface::Interface;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// 集束搜索的参数，包括参与搜索的元素数量和集束宽度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub elements: usize,
+    pub width: usize,
+}
+
+/// 集束搜索的主函数，返回搜索到的最好的方案
+pub fn search<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Constructive<T, M>,
+    parameters: Parameters,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters { elements, width } = parameters;
+    let width = width.max(1);
+    let space = problem.construction_space();
+    // 只搜索有不止一个可行按键的元素，按照频率从高到低排列
+    let mut order: Vec<usize> = (0..space.len())
+        .filter(|&decision| space[decision].0.len() > 1)
+        .collect();
+    order.sort_by(|&a, &b| space[b].1.total_cmp(&space[a].1));
+    order.truncate(elements);
+    let initial = problem.generate_candidate();
+    let rank = problem.rank_candidate(&initial);
+    let mut beam = vec![(problem.disassemble_candidate(&initial), rank)];
+
+    for (depth, &decision) in order.iter().enumerate() {
+        let mut expanded = Vec::with_capacity(beam.len() * space[decision].0.len());
+        for (values, _) in &beam {
+            for &choice in &space[decision].0 {
+                let mut next = values.clone();
+                next[decision] = choice;
+                let candidate = problem.assemble_candidate(&next);
+                // 不满足约束的部分方案直接丢弃
+                if !problem.feasible(&candidate) {
+                    continue;
+                }
+                let rank = problem.rank_candidate(&candidate);
+                expanded.push((next, rank));
+            }
+        }
+        // 这个元素没有满足约束的按键时，保持原来的集束不变
+        if expanded.is_empty() {
+            continue;
+        }
+        expanded.sort_by(|a, b| a.1 .1.total_cmp(&b.1 .1));
+        expanded.truncate(width);
+        beam = expanded;
+        interface.report_progress(depth + 1, format!("{}", beam[0].1 .0));
+    }
+    problem.assemble_candidate(&beam[0].0)
+}
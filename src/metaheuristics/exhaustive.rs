@@ -0,0 +1,112 @@
+//! 穷举搜索
+//!
+//! 对于规模很小的问题（例如在约束之后只剩二三十个可移动的元素，并且每个元素只有少数几个可行按键），可以枚举所有可行的方案，从而保证找到最优解。
+//!
+//! 枚举时做了以下剪枝：固定的元素和只有一个可行按键的元素不参与枚举；没有被任何字用到的元素（权重为零）不影响目标函数，也不参与枚举，保持初始方案中的按键。
+//! 违反约束（不能同键、按键容量、条件约束等）的方案不参与比较。
+
+use super::Constructive;
+use crate::error::Error;
+use crate::interface::Interface;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::fmt::Display;
+
+/// 穷举搜索的参数：为了避免意外地开始一个不可能完成的枚举，方案数量超过上限时拒绝运行
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub max_candidates: Option<u64>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            max_candidates: Some(10_000_000),
+        }
+    }
+}
+
+/// 需要枚举的决策：有不止一个可行取值，并且会影响目标函数
+fn decisions(space: &[(Vec<usize>, f64)]) -> Vec<usize> {
+    (0..space.len())
+        .filter(|&decision| space[decision].0.len() > 1 && space[decision].1 > 0.0)
+        .collect()
+}
+
+/// 需要枚举的方案数量，超过上限时返回错误
+pub fn count_candidates(
+    space: &[(Vec<usize>, f64)],
+    parameters: &Parameters,
+) -> Result<u64, Error> {
+    let max_candidates = parameters.max_candidates.unwrap_or(10_000_000);
+    let decisions = decisions(space);
+    decisions
+        .iter()
+        .try_fold(1_u64, |acc, &decision| acc.checked_mul(space[decision].0.len() as u64))
+        .filter(|&total| total <= max_candidates)
+        .ok_or_else(|| {
+            let path = "optimization.metaheuristic.exhaustive.max_candidates";
+            let elements = decisions.len();
+            let message = format!("需要枚举的元素有 {elements} 个，方案数量超过了 {path} 的上限 {max_candidates}");
+            format!("{message}，请增加约束或者改用其他算法").into()
+        })
+}
+
+/// 穷举搜索的主函数，返回满足约束的方案中目标函数最小的一个
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Constructive<T, M>,
+    parameters: Parameters,
+    interface: &dyn Interface,
+) -> T {
+    let space = problem.construction_space();
+    let total = count_candidates(&space, &parameters).unwrap(); // 在构造问题时已经检查过
+    let initial = problem.generate_candidate();
+    let mut values = problem.disassemble_candidate(&initial);
+    let decisions = decisions(&space);
+    // 按照里程表的方式枚举：每一位是一个元素在它的可行按键中的下标
+    let mut digits = vec![0_usize; decisions.len()];
+    for &decision in &decisions {
+        values[decision] = space[decision].0[0];
+    }
+    let mut best: Option<(T, (M, f64))> = None;
+    let mut count: u64 = 0;
+    loop {
+        let candidate = problem.assemble_candidate(&values);
+        count += 1;
+        if problem.feasible(&candidate) {
+            let rank = problem.rank_candidate(&candidate);
+            if best.as_ref().is_none_or(|(_, best_rank)| rank.1 < best_rank.1) {
+                problem.save_candidate(&candidate, &rank, false, interface);
+                best = Some((candidate, rank));
+            }
+        }
+        if let (0, Some((_, rank))) = (count % 10000, &best) {
+            interface.report_progress(count as usize, format!("{}", rank.0));
+        }
+        // 找到最低的一个还能进位的位置，把它加一，把更低的位置清零
+        let mut position = 0;
+        while position < digits.len() {
+            let decision = decisions[position];
+            digits[position] += 1;
+            if digits[position] < space[decision].0.len() {
+                values[decision] = space[decision].0[digits[position]];
+                break;
+            }
+            digits[position] = 0;
+            values[decision] = space[decision].0[0];
+            position += 1;
+        }
+        if position == digits.len() {
+            break;
+        }
+    }
+    debug_assert_eq!(count, total);
+    // 没有任何方案满足约束时，只能返回初始方案
+    let Some((best_candidate, best_rank)) = best else {
+        return initial;
+    };
+    interface.report_progress(count as usize, format!("{}", best_rank.0));
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
@@ -0,0 +1,119 @@
+//! 遗传算法
+
+use super::{initial_population, Evolutionary};
+use crate::interface::Interface;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::fmt::Display;
+
+/// 遗传算法的参数，包括种群大小、代数、变异率、精英数量和锦标赛规模
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub population_size: usize,
+    pub generations: usize,
+    pub mutation_rate: f64,
+    pub elitism: Option<usize>,
+    pub tournament_size: Option<usize>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            population_size: 50,
+            generations: 1000,
+            mutation_rate: 0.5,
+            elitism: Some(2),
+            tournament_size: Some(3),
+        }
+    }
+}
+
+/// 锦标赛选择：随机挑选若干个个体，返回其中最好的一个的下标
+fn tournament<M>(population: &[(usize, (M, f64))], size: usize, rng: &mut dyn RngCore) -> usize {
+    let mut winner = rng.gen_range(0..population.len());
+    for _ in 1..size {
+        let challenger = rng.gen_range(0..population.len());
+        if population[challenger].1 .1 < population[winner].1 .1 {
+            winner = challenger;
+        }
+    }
+    population[winner].0
+}
+
+/// 遗传算法求解的主函数
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Evolutionary<T, M>,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters {
+        population_size,
+        generations,
+        mutation_rate,
+        elitism,
+        tournament_size,
+    } = parameters;
+    let population_size = population_size.max(2);
+    let elitism = elitism.unwrap_or(2).min(population_size);
+    let tournament_size = tournament_size.unwrap_or(3).max(1);
+    let mut candidates = initial_population(problem, population_size);
+    let mut ranks: Vec<(M, f64)> = candidates
+        .iter()
+        .map(|x| problem.rank_candidate(x))
+        .collect();
+    let mut best_index = 0;
+    for (index, rank) in ranks.iter().enumerate() {
+        if rank.1 < ranks[best_index].1 {
+            best_index = index;
+        }
+    }
+    let mut best_candidate = problem.clone_candidate(&candidates[best_index]);
+    let mut best_rank = ranks[best_index].clone();
+
+    for generation in 0..generations {
+        let progress = generation as f64 / generations as f64;
+        // 按照目标函数从小到大排序，排在前面的精英直接进入下一代
+        let mut order: Vec<(usize, (M, f64))> = ranks.into_iter().enumerate().collect();
+        order.sort_by(|a, b| a.1 .1.total_cmp(&b.1 .1));
+        let mut next_candidates: Vec<T> = Vec::with_capacity(population_size);
+        let mut next_ranks: Vec<(M, f64)> = Vec::with_capacity(population_size);
+        for (index, rank) in order.iter().take(elitism) {
+            next_candidates.push(problem.clone_candidate(&candidates[*index]));
+            next_ranks.push(rank.clone());
+        }
+        while next_candidates.len() < population_size {
+            let parent1 = tournament(&order, tournament_size, problem.rng());
+            let parent2 = tournament(&order, tournament_size, problem.rng());
+            let mut child = problem.crossover_candidates(&candidates[parent1], &candidates[parent2]);
+            if problem.rng().gen::<f64>() < mutation_rate {
+                child = problem.tweak_candidate(&child);
+            }
+            let rank = problem.rank_candidate(&child);
+            next_candidates.push(child);
+            next_ranks.push(rank);
+        }
+        candidates = next_candidates;
+        ranks = next_ranks;
+        for (candidate, rank) in candidates.iter().zip(ranks.iter()) {
+            if rank.1 < best_rank.1 {
+                best_rank = rank.clone();
+                best_candidate = problem.clone_candidate(candidate);
+                problem.save_candidate(
+                    &best_candidate,
+                    &best_rank,
+                    progress > report_after.unwrap_or(0.9),
+                    interface,
+                );
+            }
+        }
+        if generation % 10 == 0 {
+            interface.report_progress(generation, format!("{}", best_rank.0));
+        }
+    }
+    interface.report_progress(generations, format!("{}", best_rank.0));
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
@@ -0,0 +1,86 @@
+//! 爬山算法
+
+use super::Metaheuristics;
+use crate::interface::Interface;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::fmt::Display;
+
+/// 爬山的策略：首次改进是遇到一个更好的邻居就接受，最优改进是在若干个邻居中选择最好的一个
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    FirstImprovement,
+    BestImprovement,
+}
+
+/// 爬山算法的参数，包括步数、策略和最优改进时每步考察的邻域大小
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub steps: usize,
+    pub strategy: Option<Strategy>,
+    pub neighborhood_size: Option<usize>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            steps: 10000,
+            strategy: Some(Strategy::FirstImprovement),
+            neighborhood_size: Some(20),
+        }
+    }
+}
+
+/// 爬山算法求解的主函数，只接受使目标函数变小的移动
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters {
+        steps,
+        strategy,
+        neighborhood_size,
+    } = parameters;
+    let neighborhood_size = match strategy.unwrap_or(Strategy::FirstImprovement) {
+        Strategy::FirstImprovement => 1,
+        Strategy::BestImprovement => neighborhood_size.unwrap_or(20).max(1),
+    };
+    let mut best_candidate = problem.generate_candidate();
+    let mut best_rank = problem.rank_candidate(&best_candidate);
+
+    for step in 0..steps {
+        let progress = step as f64 / steps as f64;
+        let mut best_neighbor: Option<(T, (M, f64))> = None;
+        for _ in 0..neighborhood_size {
+            let neighbor = problem.tweak_candidate(&best_candidate);
+            let rank = problem.rank_candidate(&neighbor);
+            problem.report_improvement(rank.1 - best_rank.1);
+            let is_better = best_neighbor
+                .as_ref()
+                .map_or(rank.1 < best_rank.1, |(_, best)| rank.1 < best.1);
+            if is_better {
+                best_neighbor = Some((neighbor, rank));
+            }
+        }
+        if let Some((neighbor, rank)) = best_neighbor {
+            best_candidate = neighbor;
+            best_rank = rank;
+            problem.save_candidate(
+                &best_candidate,
+                &best_rank,
+                progress > report_after.unwrap_or(0.9),
+                interface,
+            );
+        }
+        if step % 1000 == 0 {
+            interface.report_progress(step, format!("{}", best_rank.0));
+        }
+    }
+    interface.report_progress(steps, format!("{}", best_rank.0));
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
@@ -0,0 +1,149 @@
+//! 模因算法（种群与退火的混合算法）
+//!
+//! 维护一个较小的种群，每一代从种群中选出亲本杂交产生子代，再以子代为起点各自进行一段短暂的退火作为局部搜索，最后用较好的子代替换种群中较差的个体。杂交负责在不同的局部最优之间组合出新的方案，而退火负责把新方案打磨到附近的局部最优。各个子代的退火在不同的线程上并行进行。
+
+use super::{initial_population, simulated_annealing, Evolutionary, Metaheuristics};
+use crate::interface::Interface;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::thread;
+
+/// 模因算法的参数，包括种群大小、代数、每一代产生的子代数量和每个子代退火的步数；温度范围沿用退火算法的参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub population_size: usize,
+    pub generations: usize,
+    pub offspring: usize,
+    pub burst_steps: usize,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            population_size: 8,
+            generations: 100,
+            offspring: 4,
+            burst_steps: 10000,
+        }
+    }
+}
+
+/// 从给定的解出发，在若干步内从 t_start 指数降温到 t_end，返回过程中找到的最好的解
+fn burst<T: Clone, M: Clone>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    start: (T, (M, f64)),
+    t_start: f64,
+    t_end: f64,
+    steps: usize,
+) -> (T, (M, f64)) {
+    let (mut candidate, mut rank) = start;
+    let mut best_candidate = problem.clone_candidate(&candidate);
+    let mut best_rank = rank.clone();
+    for step in 0..steps {
+        let progress = step as f64 / steps as f64;
+        let temperature = t_start * (t_end / t_start).powf(progress);
+        let next_candidate = problem.tweak_candidate(&candidate);
+        let next_rank = problem.rank_candidate(&next_candidate);
+        let improvement = next_rank.1 - rank.1;
+        problem.report_improvement(improvement);
+        if improvement < 0.0 || (problem.rng().gen::<f64>() < (-improvement / temperature).exp()) {
+            candidate = next_candidate;
+            rank = next_rank;
+        }
+        if rank.1 < best_rank.1 {
+            best_rank = rank.clone();
+            best_candidate = problem.clone_candidate(&candidate);
+        }
+    }
+    (best_candidate, best_rank)
+}
+
+/// 二元锦标赛选择，返回较好的个体的下标
+fn tournament<T, M>(population: &[(T, (M, f64))], rng: &mut dyn RngCore) -> usize {
+    let a = rng.gen_range(0..population.len());
+    let b = rng.gen_range(0..population.len());
+    if population[a].1 .1 < population[b].1 .1 {
+        a
+    } else {
+        b
+    }
+}
+
+/// 模因算法求解的主函数
+pub fn solve<T: Clone + Send, M: Clone + Display + Send>(
+    problem: &mut dyn Evolutionary<T, M>,
+    annealing: simulated_annealing::Parameters,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let simulated_annealing::Parameters { t_max, t_min, .. } = annealing;
+    let Parameters {
+        population_size,
+        generations,
+        offspring,
+        burst_steps,
+    } = parameters;
+    let population_size = population_size.max(2);
+    let offspring = offspring.max(1);
+    let mut workers: Vec<_> = (0..offspring).map(|_| problem.clone_problem()).collect();
+    let mut population: Vec<(T, (M, f64))> = initial_population(problem, population_size)
+        .into_iter()
+        .map(|candidate| {
+            let rank = problem.rank_candidate(&candidate);
+            (candidate, rank)
+        })
+        .collect();
+    let mut best = population
+        .iter()
+        .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+        .cloned()
+        .unwrap(); // 种群不为空
+
+    for generation in 0..generations {
+        let progress = generation as f64 / generations as f64;
+        // 局部搜索的起始温度随代数指数下降，前期允许较大的扰动，后期只做精细的调整
+        let t_start = t_max * (t_min / t_max).powf(progress);
+        let mut children = Vec::with_capacity(offspring);
+        for _ in 0..offspring {
+            let parent1 = tournament(&population, problem.rng());
+            let parent2 = tournament(&population, problem.rng());
+            let child = problem.crossover_candidates(&population[parent1].0, &population[parent2].0);
+            let rank = problem.rank_candidate(&child);
+            children.push((child, rank));
+        }
+        let children: Vec<(T, (M, f64))> = thread::scope(|scope| {
+            let handles: Vec<_> = workers
+                .iter_mut()
+                .zip(children)
+                .map(|(worker, child)| {
+                    scope.spawn(move || burst(worker.as_mut(), child, t_start, t_min, burst_steps))
+                })
+                .collect();
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+        // 子代如果比种群中最差的个体更好，就替换掉它
+        for child in children {
+            let worst = (0..population.len())
+                .max_by(|&a, &b| population[a].1 .1.total_cmp(&population[b].1 .1))
+                .unwrap(); // 种群不为空
+            if child.1 .1 < population[worst].1 .1 {
+                if child.1 .1 < best.1 .1 {
+                    best = (problem.clone_candidate(&child.0), child.1.clone());
+                    problem.save_candidate(
+                        &best.0,
+                        &best.1,
+                        progress > report_after.unwrap_or(0.9),
+                        interface,
+                    );
+                }
+                population[worst] = child;
+            }
+        }
+        interface.report_progress(generation, format!("{}", best.1 .0));
+    }
+    interface.report_progress(generations, format!("{}", best.1 .0));
+    problem.save_candidate(&best.0, &best.1, true, interface);
+    best.0
+}
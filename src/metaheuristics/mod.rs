@@ -4,9 +4,43 @@
 //!
 //! 但是，为了保证可扩展性，仍然保留了这个库中对于不同类型算法和不同类型问题的特征抽象，即只要一个问题定义了 Metaheuristic 这个 trait，就能用所有不同的算法求解；而任何一个算法都可以只依赖于 Metaheuristic 这个 trait 里提供的方法来求解一个问题。相当于建立了一个多对多的模块化设计，这样也许以后使用遗传算法等其他方法也不需要大改结构。
 //!
+//! 只有部分算法才需要的操作放在扩展的 trait 中：进化算法需要 `Evolutionary`，多目标优化需要 `MultiObjective`，构造式算法需要 `Constructive`。这样新的问题只需要实现基本的 `Metaheuristics` 就能使用退火等算法，需要其他算法时再实现对应的扩展。
+//!
 
 use crate::interface::Interface;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+pub mod ant_colony;
+pub mod bandit;
+pub mod beam_search;
+pub mod exhaustive;
+pub mod genetic_algorithm;
+pub mod hill_climbing;
+pub mod memetic;
+pub mod nsga2;
+pub mod parallel_annealing;
+pub mod parallel_tempering;
 pub mod simulated_annealing;
+pub mod tabu_search;
+
+/// 解的一处变化：某个元素从一个按键移动到了另一个按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Change {
+    pub element: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// 一个求解算法。实现了这个 trait 的算法可以通过 `ElementPlacementProblem::solve_with` 来求解元素布局问题，这样不需要修改编码和评测的代码就能试验新的搜索策略
+///
+///```ignore
+/// let mut algorithm = SimulatedAnnealing::new(Some(parameters), 10, None, None);
+/// let solution = problem.solve_with(&mut algorithm, &interface);
+///```
+pub trait Metaheuristic<T, M> {
+    /// 用这个算法求解一个问题，返回找到的最好的解
+    fn solve(&mut self, problem: &mut dyn Metaheuristics<T, M>, interface: &dyn Interface) -> T;
+}
 
 /// 任何问题只要实现了这个 trait，就能用所有算法来求解
 pub trait Metaheuristics<T, M> {
@@ -35,6 +69,50 @@ pub trait Metaheuristics<T, M> {
     ///```
     fn tweak_candidate(&mut self, candidate: &T) -> T;
 
+    /// 告知问题上一次扰动使目标函数变化了多少（负数表示变好），用于自适应地选择扰动算子
+    ///
+    ///```ignore
+    /// let new_candidate = problem.tweak_candidate(&old_candidate);
+    /// let new_rank = problem.rank_candidate(&new_candidate);
+    /// problem.report_improvement(new_rank.1 - old_rank.1);
+    ///```
+    fn report_improvement(&mut self, improvement: f64);
+
+    /// 比较两个解，列出从旧解到新解发生的所有变化
+    ///
+    ///```ignore
+    /// let changes = problem.compare_candidates(&old_candidate, &new_candidate);
+    ///```
+    fn compare_candidates(&self, old_candidate: &T, new_candidate: &T) -> Vec<Change>;
+
+    /// 复制出一个独立的问题，用于在其他线程上并行求解；新问题的随机数生成器由原问题的随机数生成器派生，二者产生不同的随机数序列
+    ///
+    ///```ignore
+    /// let replica = problem.clone_problem();
+    ///```
+    fn clone_problem(&mut self) -> Box<dyn Metaheuristics<T, M> + Send>;
+
+    /// 问题所使用的随机数生成器。算法中所有的随机性都应该来自于它，这样才能完整地保存和恢复优化的状态
+    ///
+    ///```ignore
+    /// let threshold: f64 = problem.rng().gen();
+    ///```
+    fn rng(&mut self) -> &mut dyn RngCore;
+
+    /// 保存算法的检查点，以便中断之后继续优化。state 是算法自己序列化的状态，问题把它连同自身的状态（如随机数生成器）一起保存，
+    /// 继续优化时再把它交还给算法
+    fn save_checkpoint(&mut self, step: usize, state: Vec<u8>, interface: &dyn Interface);
+
+    /// 记录优化过程中接受的一步，用于保存优化轨迹
+    fn save_move(
+        &self,
+        step: usize,
+        old_candidate: &T,
+        new_candidate: &T,
+        delta: f64,
+        interface: &dyn Interface,
+    );
+
     /// 保存当前的一个解
     fn save_candidate(
         &self,
@@ -44,3 +122,83 @@ pub trait Metaheuristics<T, M> {
         interface: &dyn Interface,
     );
 }
+
+/// 遗传算法一类的进化算法还需要问题能够杂交两个解
+pub trait Evolutionary<T, M>: Metaheuristics<T, M> {
+    /// 将两个解杂交产生一个新的解，新解的每一部分都继承自两个解中的某一个
+    ///
+    ///```ignore
+    /// let child = problem.crossover_candidates(&parent1, &parent2);
+    ///```
+    fn crossover_candidates(&mut self, candidate1: &T, candidate2: &T) -> T;
+}
+
+/// 多目标优化还需要问题能够把评测指标拆分成若干个目标，并保存得到的帕累托前沿
+pub trait MultiObjective<T, M>: Evolutionary<T, M> {
+    /// 从评测指标中取出若干个分量，用于多目标优化。每个分量都是越小越好
+    ///
+    ///```ignore
+    /// let values = problem.split_metric(&metric, &["characters_full.duplication".to_string()]);
+    ///```
+    fn split_metric(&self, metric: &M, components: &[String]) -> Vec<f64>;
+
+    /// 保存多目标优化得到的帕累托前沿
+    fn save_front(&self, front: &[(T, (M, f64))], components: &[String], interface: &dyn Interface);
+}
+
+/// 蚁群算法、集束搜索和穷举搜索这样的构造式算法还需要问题把一个解表示成一组决策的取值
+pub trait Constructive<T, M>: Metaheuristics<T, M> {
+    /// 构造式算法所需的决策空间：一个解由若干个决策确定，每个决策有若干个可行的取值，并附有一个表示其重要程度的权重
+    ///
+    ///```ignore
+    /// let space = problem.construction_space();
+    ///```
+    fn construction_space(&self) -> Vec<(Vec<usize>, f64)>;
+
+    /// 由每个决策的取值组装出一个解
+    ///
+    ///```ignore
+    /// let candidate = problem.assemble_candidate(&values);
+    ///```
+    fn assemble_candidate(&self, values: &[usize]) -> T;
+
+    /// 列出一个解在每个决策上的取值，是 `assemble_candidate` 的逆操作
+    ///
+    ///```ignore
+    /// let values = problem.disassemble_candidate(&candidate);
+    ///```
+    fn disassemble_candidate(&self, candidate: &T) -> Vec<usize>;
+
+    /// 在部分决策已经做出的情况下（decided 中为 true 的决策，取值在 values 中），某个决策取某个值是否满足约束。
+    /// 逐个做出决策的算法用它排除不可行的选择
+    ///
+    ///```ignore
+    /// let admissible = problem.admissible(&values, &decided, decision, value);
+    ///```
+    fn admissible(&self, values: &[usize], decided: &[bool], decision: usize, value: usize) -> bool;
+
+    /// 一个解是否满足所有的约束，不满足的解不参与比较
+    ///
+    ///```ignore
+    /// let feasible = problem.feasible(&candidate);
+    ///```
+    fn feasible(&self, candidate: &T) -> bool;
+}
+
+/// 进化算法共用的初始种群：第一个个体是初始解，其余的个体由初始解经过若干次随机扰动得到
+///
+///```ignore
+/// let candidates = initial_population(problem, population_size);
+///```
+pub fn initial_population<T, M>(problem: &mut dyn Metaheuristics<T, M>, size: usize) -> Vec<T> {
+    let initial = problem.generate_candidate();
+    let mut candidates = vec![problem.clone_candidate(&initial)];
+    while candidates.len() < size {
+        let mut candidate = problem.clone_candidate(&initial);
+        for _ in 0..10 {
+            candidate = problem.tweak_candidate(&candidate);
+        }
+        candidates.push(candidate);
+    }
+    candidates
+}
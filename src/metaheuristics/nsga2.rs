@@ -0,0 +1,211 @@
+//! 多目标优化（NSGA-II）
+//!
+//! 选重率和当量等指标之间往往此消彼长，把它们加权成一个标量再优化，只能得到权重所对应的一个折中方案。本算法同时优化若干个指标，维护一个种群，按照非支配排序和拥挤距离来选择下一代，最终得到一组互不支配的方案，即帕累托前沿。
+
+use super::{initial_population, MultiObjective};
+use crate::interface::Interface;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// 多目标优化的参数，包括种群大小、代数、变异率和需要同时优化的指标
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub population_size: usize,
+    pub generations: usize,
+    pub mutation_rate: f64,
+    pub objectives: Vec<String>,
+}
+
+/// 种群中的一个个体
+struct Individual<T, M> {
+    candidate: T,
+    rank: (M, f64),
+    objectives: Vec<f64>,
+    front: usize,
+    crowding: f64,
+}
+
+/// a 支配 b 当且仅当 a 在所有目标上都不差于 b，并且至少在一个目标上严格优于 b
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+}
+
+/// 快速非支配排序，返回各个前沿所包含的个体下标，同时记录每个个体所在的前沿
+fn non_dominated_sort<T, M>(population: &mut [Individual<T, M>]) -> Vec<Vec<usize>> {
+    let size = population.len();
+    let mut dominated_by: Vec<Vec<usize>> = vec![vec![]; size];
+    let mut domination_count = vec![0_usize; size];
+    let mut fronts: Vec<Vec<usize>> = vec![vec![]];
+    for p in 0..size {
+        for q in 0..size {
+            if dominates(&population[p].objectives, &population[q].objectives) {
+                dominated_by[p].push(q);
+            } else if dominates(&population[q].objectives, &population[p].objectives) {
+                domination_count[p] += 1;
+            }
+        }
+        if domination_count[p] == 0 {
+            population[p].front = 0;
+            fronts[0].push(p);
+        }
+    }
+    let mut current = 0;
+    while !fronts[current].is_empty() {
+        let mut next = vec![];
+        for &p in &fronts[current] {
+            for &q in &dominated_by[p] {
+                domination_count[q] -= 1;
+                if domination_count[q] == 0 {
+                    population[q].front = current + 1;
+                    next.push(q);
+                }
+            }
+        }
+        current += 1;
+        fronts.push(next);
+    }
+    fronts.pop(); // 最后一个前沿总是空的
+    fronts
+}
+
+/// 计算一个前沿中各个个体的拥挤距离，位于边界上的个体距离为无穷大
+fn assign_crowding<T, M>(population: &mut [Individual<T, M>], front: &[usize]) {
+    for &index in front {
+        population[index].crowding = 0.0;
+    }
+    let dimensions = population[front[0]].objectives.len();
+    for dimension in 0..dimensions {
+        let mut sorted = front.to_vec();
+        sorted.sort_by(|&a, &b| {
+            population[a].objectives[dimension].total_cmp(&population[b].objectives[dimension])
+        });
+        let first = sorted[0];
+        let last = sorted[sorted.len() - 1];
+        let range = population[last].objectives[dimension] - population[first].objectives[dimension];
+        population[first].crowding = f64::INFINITY;
+        population[last].crowding = f64::INFINITY;
+        if range <= 0.0 {
+            continue;
+        }
+        for window in sorted.windows(3) {
+            let gap = population[window[2]].objectives[dimension]
+                - population[window[0]].objectives[dimension];
+            population[window[1]].crowding += gap / range;
+        }
+    }
+}
+
+/// 二元锦标赛选择：前沿靠前者胜，同一前沿中拥挤距离大者胜
+fn tournament<T, M>(population: &[Individual<T, M>], rng: &mut dyn RngCore) -> usize {
+    let a = rng.gen_range(0..population.len());
+    let b = rng.gen_range(0..population.len());
+    let (x, y) = (&population[a], &population[b]);
+    if x.front < y.front || (x.front == y.front && x.crowding > y.crowding) {
+        a
+    } else {
+        b
+    }
+}
+
+/// 多目标优化求解的主函数，返回帕累托前沿中加权目标函数最小的解
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn MultiObjective<T, M>,
+    parameters: Parameters,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters {
+        population_size,
+        generations,
+        mutation_rate,
+        objectives,
+    } = parameters;
+    let population_size = population_size.max(2);
+    let evaluate = |problem: &mut dyn MultiObjective<T, M>, candidate: T| {
+        let rank = problem.rank_candidate(&candidate);
+        let values = problem.split_metric(&rank.0, &objectives);
+        Individual {
+            candidate,
+            rank,
+            objectives: values,
+            front: 0,
+            crowding: 0.0,
+        }
+    };
+    let mut population: Vec<Individual<T, M>> = initial_population(problem, population_size)
+        .into_iter()
+        .map(|candidate| evaluate(problem, candidate))
+        .collect();
+    for front in non_dominated_sort(&mut population) {
+        assign_crowding(&mut population, &front);
+    }
+
+    for generation in 0..generations {
+        // 通过选择、杂交和变异产生同样数量的子代
+        let mut offspring = Vec::with_capacity(population_size);
+        while offspring.len() < population_size {
+            let parent1 = tournament(&population, problem.rng());
+            let parent2 = tournament(&population, problem.rng());
+            let mut child = problem.crossover_candidates(
+                &population[parent1].candidate,
+                &population[parent2].candidate,
+            );
+            if problem.rng().gen::<f64>() < mutation_rate {
+                child = problem.tweak_candidate(&child);
+            }
+            offspring.push(evaluate(problem, child));
+        }
+        // 亲代和子代合并之后，按前沿依次选入下一代，最后一个放不下的前沿按拥挤距离选择
+        population.extend(offspring);
+        let fronts = non_dominated_sort(&mut population);
+        let mut selected: Vec<usize> = Vec::with_capacity(population_size);
+        for front in fronts {
+            assign_crowding(&mut population, &front);
+            if selected.len() + front.len() <= population_size {
+                selected.extend(front);
+            } else {
+                let mut front = front;
+                front.sort_by(|&a, &b| population[b].crowding.total_cmp(&population[a].crowding));
+                selected.extend(front.into_iter().take(population_size - selected.len()));
+            }
+            if selected.len() == population_size {
+                break;
+            }
+        }
+        let mut slots: Vec<Option<Individual<T, M>>> = population.into_iter().map(Some).collect();
+        population = selected
+            .into_iter()
+            .map(|index| slots[index].take().unwrap())
+            .collect();
+        if generation % 10 == 0 {
+            let front_size = population.iter().filter(|x| x.front == 0).count();
+            let best = population
+                .iter()
+                .min_by(|a, b| a.rank.1.total_cmp(&b.rank.1))
+                .unwrap(); // 种群不为空
+            interface.report_progress(
+                generation,
+                format!("帕累托前沿中有 {} 个方案，其中加权目标函数最小的方案：\n{}", front_size, best.rank.0),
+            );
+        }
+    }
+
+    // 输出第一前沿，目标值完全相同的方案只保留一个
+    let mut front: Vec<Individual<T, M>> = vec![];
+    for individual in population.into_iter().filter(|x| x.front == 0) {
+        if front.iter().all(|x| x.objectives != individual.objectives) {
+            front.push(individual);
+        }
+    }
+    front.sort_by(|a, b| a.objectives[0].total_cmp(&b.objectives[0]));
+    let solutions: Vec<(T, (M, f64))> = front
+        .iter()
+        .map(|x| (problem.clone_candidate(&x.candidate), x.rank.clone()))
+        .collect();
+    problem.save_front(&solutions, &objectives, interface);
+    let best = front
+        .into_iter()
+        .min_by(|a, b| a.rank.1.total_cmp(&b.rank.1))
+        .unwrap(); // 第一前沿不为空
+    best.candidate
+}
@@ -0,0 +1,121 @@
+//! 多链并行退火
+//!
+//! 在多个线程上同时运行若干条独立的退火链，每条链有自己的随机数生成器，按照同一条降温曲线降温。每隔一段步数，所有的链暂停一次，把全局最好的方案复制到当前最差的链上，让较差的链从更有希望的区域继续搜索。与多次重启相比，链之间的交流使得算力更多地集中在好的方案附近。
+
+use super::{simulated_annealing, Metaheuristics};
+use crate::interface::Interface;
+use rand::Rng;
+use std::fmt::Display;
+use std::thread;
+use web_time::Instant;
+
+/// 每隔这么多步在链之间迁移一次最好方案
+const MIGRATION_INTERVAL: usize = 10000;
+
+/// 一条退火链的状态
+struct Chain<T, M> {
+    problem: Box<dyn Metaheuristics<T, M> + Send>,
+    candidate: T,
+    rank: (M, f64),
+    best_candidate: T,
+    best_rank: (M, f64),
+}
+
+impl<T: Clone, M: Clone> Chain<T, M> {
+    /// 从 from 步运行到 to 步，温度由全局的进度决定
+    fn run(&mut self, parameters: &simulated_annealing::Parameters, from: usize, to: usize) {
+        let schedule = parameters.schedule.unwrap_or_default();
+        let scaling = parameters.step_scaling.unwrap_or(0.0).max(-0.9);
+        for step in from..to {
+            let progress = step as f64 / parameters.steps as f64;
+            let stage = simulated_annealing::stage_progress(progress, scaling);
+            let temperature = schedule.temperature(parameters.t_max, parameters.t_min, stage);
+            let next_candidate = self.problem.tweak_candidate(&self.candidate);
+            let next_rank = self.problem.rank_candidate(&next_candidate);
+            let improvement = next_rank.1 - self.rank.1;
+            self.problem.report_improvement(improvement);
+            if improvement < 0.0
+                || (self.problem.rng().gen::<f64>() < (-improvement / temperature).exp())
+            {
+                self.candidate = next_candidate;
+                self.rank = next_rank;
+            }
+            if self.rank.1 < self.best_rank.1 {
+                self.best_rank = self.rank.clone();
+                self.best_candidate = self.problem.clone_candidate(&self.candidate);
+            }
+        }
+    }
+}
+
+/// 多链并行退火的主函数。Lam 自适应降温依赖单条链的接受率，在这里按照指数降温处理
+pub fn solve<T: Clone + Send, M: Clone + Display + Send>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    parameters: simulated_annealing::Parameters,
+    threads: usize,
+    report_after: Option<f64>,
+    deadline: Option<Instant>,
+    interface: &dyn Interface,
+) -> T {
+    let steps = parameters.steps;
+    let schedule = parameters.schedule.unwrap_or_default();
+    let initial = problem.generate_candidate();
+    let rank = problem.rank_candidate(&initial);
+    let mut chains: Vec<Chain<T, M>> = (0..threads.max(1))
+        .map(|_| Chain {
+            problem: problem.clone_problem(),
+            candidate: problem.clone_candidate(&initial),
+            rank: rank.clone(),
+            best_candidate: problem.clone_candidate(&initial),
+            best_rank: rank.clone(),
+        })
+        .collect();
+    let mut best_candidate = initial;
+    let mut best_rank = rank;
+    let mut step = 0;
+    let mut temperature = parameters.t_max;
+
+    while step < steps {
+        let next = (step + MIGRATION_INTERVAL).min(steps);
+        thread::scope(|scope| {
+            for chain in chains.iter_mut() {
+                let parameters = &parameters;
+                scope.spawn(move || chain.run(parameters, step, next));
+            }
+        });
+        step = next;
+        let progress = step as f64 / steps as f64;
+        // 汇总各条链找到的最好方案
+        let best = (0..chains.len())
+            .min_by(|&a, &b| chains[a].best_rank.1.total_cmp(&chains[b].best_rank.1))
+            .unwrap(); // 至少有一条链
+        if chains[best].best_rank.1 < best_rank.1 {
+            best_rank = chains[best].best_rank.clone();
+            best_candidate = problem.clone_candidate(&chains[best].best_candidate);
+            problem.save_candidate(
+                &best_candidate,
+                &best_rank,
+                progress > report_after.unwrap_or(0.9),
+                interface,
+            );
+        }
+        // 把全局最好方案复制到当前方案最差的链上
+        let worst = (0..chains.len())
+            .max_by(|&a, &b| chains[a].rank.1.total_cmp(&chains[b].rank.1))
+            .unwrap(); // 至少有一条链
+        chains[worst].candidate = problem.clone_candidate(&best_candidate);
+        chains[worst].rank = best_rank.clone();
+        let scaling = parameters.step_scaling.unwrap_or(0.0).max(-0.9);
+        let stage = simulated_annealing::stage_progress(progress, scaling);
+        temperature = schedule.temperature(parameters.t_max, parameters.t_min, stage);
+        interface.report_schedule(step, temperature, schedule, format!("{}", best_rank.0));
+        interface.report_status(step, steps, temperature, best_rank.1);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+    }
+    // 到达截止时间时也以实际执行的步数作为总步数，表示优化已经结束
+    interface.report_status(step, step, temperature, best_rank.1);
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
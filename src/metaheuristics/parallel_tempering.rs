@@ -0,0 +1,129 @@
+//! 并行回火（副本交换）算法
+//!
+//! 在不同的温度上同时运行若干条退火链，每条链在一个单独的线程上以固定的温度进行 Metropolis 抽样。每隔一定的步数，相邻温度的链之间按照一定的概率交换它们的解，使得低温链能够借助高温链跳出局部最优。
+
+use super::{simulated_annealing, Metaheuristics};
+use crate::interface::Interface;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::thread;
+
+/// 并行回火的参数，包括副本数量和交换间隔；温度范围和总步数沿用退火算法的参数，降温方式不起作用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub replicas: usize,
+    pub exchange_interval: usize,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            replicas: 4,
+            exchange_interval: 1000,
+        }
+    }
+}
+
+struct Replica<T, M> {
+    problem: Box<dyn Metaheuristics<T, M> + Send>,
+    temperature: f64,
+    candidate: T,
+    rank: (M, f64),
+    best_candidate: T,
+    best_rank: (M, f64),
+}
+
+impl<T: Clone, M: Clone> Replica<T, M> {
+    /// 在固定的温度下运行若干步 Metropolis 抽样
+    fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            let next_candidate = self.problem.tweak_candidate(&self.candidate);
+            let next_rank = self.problem.rank_candidate(&next_candidate);
+            let improvement = next_rank.1 - self.rank.1;
+            self.problem.report_improvement(improvement);
+            if improvement < 0.0 || (self.problem.rng().gen::<f64>() < (-improvement / self.temperature).exp()) {
+                self.candidate = next_candidate;
+                self.rank = next_rank;
+            }
+            if self.rank.1 < self.best_rank.1 {
+                self.best_rank = self.rank.clone();
+                self.best_candidate = self.problem.clone_candidate(&self.candidate);
+            }
+        }
+    }
+}
+
+/// 并行回火求解的主函数
+pub fn solve<T: Clone + Send, M: Clone + Display + Send>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    annealing: simulated_annealing::Parameters,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let simulated_annealing::Parameters {
+        t_max,
+        t_min,
+        steps,
+        ..
+    } = annealing;
+    let Parameters {
+        replicas,
+        exchange_interval,
+    } = parameters;
+    let replicas = replicas.max(2);
+    let exchange_interval = exchange_interval.max(1);
+    let mut best_candidate = problem.generate_candidate();
+    let mut best_rank = problem.rank_candidate(&best_candidate);
+    // 温度按几何级数分布在最低温和最高温之间，下标越小温度越低
+    let ratio = (t_max / t_min).powf(1.0 / (replicas - 1) as f64);
+    let mut chains: Vec<Replica<T, M>> = (0..replicas)
+        .map(|index| Replica {
+            problem: problem.clone_problem(),
+            temperature: t_min * ratio.powi(index as i32),
+            candidate: problem.clone_candidate(&best_candidate),
+            rank: best_rank.clone(),
+            best_candidate: problem.clone_candidate(&best_candidate),
+            best_rank: best_rank.clone(),
+        })
+        .collect();
+
+    let mut step = 0;
+    while step < steps {
+        let batch = exchange_interval.min(steps - step);
+        thread::scope(|scope| {
+            for chain in chains.iter_mut() {
+                scope.spawn(move || chain.run(batch));
+            }
+        });
+        step += batch;
+        let progress = step as f64 / steps as f64;
+        for chain in chains.iter() {
+            if chain.best_rank.1 < best_rank.1 {
+                best_rank = chain.best_rank.clone();
+                best_candidate = problem.clone_candidate(&chain.best_candidate);
+                problem.save_candidate(
+                    &best_candidate,
+                    &best_rank,
+                    progress > report_after.unwrap_or(0.9),
+                    interface,
+                );
+            }
+        }
+        // 相邻温度的副本之间尝试交换
+        for index in 0..(replicas - 1) {
+            let (cold, hot) = (&chains[index], &chains[index + 1]);
+            let delta = (cold.rank.1 - hot.rank.1) * (1.0 / cold.temperature - 1.0 / hot.temperature);
+            if delta > 0.0 || problem.rng().gen::<f64>() < delta.exp() {
+                let (left, right) = chains.split_at_mut(index + 1);
+                let (cold, hot) = (&mut left[index], &mut right[0]);
+                std::mem::swap(&mut cold.candidate, &mut hot.candidate);
+                std::mem::swap(&mut cold.rank, &mut hot.rank);
+            }
+        }
+        interface.report_progress(step, format!("{}", chains[0].rank.0));
+    }
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
@@ -1,56 +1,283 @@
 //! 退火算法
 
-use super::Metaheuristics;
+use super::{Metaheuristic, Metaheuristics};
 use crate::interface::Interface;
-use rand::random;
+use log::{debug, trace};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
 use std::fmt::Display;
 use web_time::{Duration, Instant};
 
-/// 退火算法的参数，包括最高温、最低温、步数
+/// 退火算法的参数，包括最高温、最低温、步数、降温方式、各温度阶段步数的分配、接受准则、提前停止之前允许最好方案连续没有改进的步数，以及搜索停滞时的回温策略
+#[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameters {
     pub t_max: f64,
     pub t_min: f64,
     pub steps: usize,
+    pub schedule: Option<Schedule>,
+    pub step_scaling: Option<f64>,
+    pub acceptance: Option<Acceptance>,
+    pub patience: Option<usize>,
+    pub reheat: Option<Reheat>,
 }
 
-/// 退火算法求解的主函数
-pub fn solve<T: Clone, M: Clone + Display>(
+/// 把步数的进度换算为降温的进度。若每个温度阶段的步数正比于该阶段降温进度的 scaling 次方，那么走完前 u 部分的降温需要 u 的 (scaling + 1) 次方部分的步数，反过来就是这里的换算。scaling 为 0 时每个阶段的步数相同，大于 0 时在低温阶段停留更久，小于 0 时在高温阶段停留更久
+pub fn stage_progress(progress: f64, scaling: f64) -> f64 {
+    progress.powf(1.0 / (scaling + 1.0))
+}
+
+/// 接受准则，温度在不同的准则中有不同的含义
+/// Metropolis：以 exp(-Δ / T) 的概率接受变差的方案，这是默认的准则
+/// 门槛接受：变差的幅度小于 T 就接受
+/// 大洪水：新方案的目标函数低于水位就接受，水位只降不升，每一步降到当前方案的目标函数加上 T
+/// 记录到记录：新方案的目标函数比历史最好方案高出不到 T 就接受
+/// 后三种准则不需要计算指数函数，也不消耗随机数
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acceptance {
+    #[default]
+    Metropolis,
+    Threshold,
+    GreatDeluge,
+    RecordToRecord,
+}
+
+/// 回温策略：当近期的接受率低于阈值，并且最好方案连续若干步没有改进时，把温度乘以一个系数，帮助搜索跳出当前的局部最优。回温的效果随后逐渐消退，温度回到降温曲线上
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reheat {
+    pub accept_rate: f64,
+    pub stagnation: usize,
+    pub factor: f64,
+}
+
+/// 回温效果每一步消退的比例
+const REHEAT_DECAY: f64 = 0.999;
+
+/// 降温方式
+/// 指数降温：温度的对数随步数线性下降，这是默认的方式
+/// 线性降温：温度随步数线性下降
+/// 对数降温：温度与步数的对数成反比，前期降温快、后期降温慢
+/// Lam 自适应降温：根据近期的接受率动态调节温度，使接受率跟踪一条预设的曲线
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    #[default]
+    Exponential,
+    Linear,
+    Logarithmic,
+    Lam,
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Schedule::Exponential => "指数降温",
+            Schedule::Linear => "线性降温",
+            Schedule::Logarithmic => "对数降温",
+            Schedule::Lam => "Lam 自适应降温",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Schedule {
+    /// 对于非自适应的降温方式，根据进度计算温度
+    pub fn temperature(&self, t_max: f64, t_min: f64, progress: f64) -> f64 {
+        match self {
+            Schedule::Linear => t_max - (t_max - t_min) * progress,
+            Schedule::Logarithmic => {
+                // 选取系数使得进度为 1 时恰好降到最低温
+                let alpha = (t_max / t_min - 1.0) / 2.0_f64.ln();
+                t_max / (1.0 + alpha * (1.0 + progress).ln())
+            }
+            _ => t_max * (t_min / t_max).powf(progress),
+        }
+    }
+
+    /// Lam 自适应降温的目标接受率曲线（Swartz 改进版）
+    fn target_accept_rate(progress: f64) -> f64 {
+        if progress < 0.15 {
+            0.44 + 0.56 * 560.0_f64.powf(-progress / 0.15)
+        } else if progress < 0.65 {
+            0.44
+        } else {
+            0.44 * 440.0_f64.powf(-(progress - 0.65) / 0.35)
+        }
+    }
+}
+
+/// 退火过程的检查点，包含了从中断处继续优化所需要的全部状态
+///
+/// 检查点以二进制格式保存，所以这里不能直接嵌入带有可省略字段的 `Parameters`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint<T> {
+    pub t_max: f64,
+    pub t_min: f64,
+    pub steps: usize,
+    pub schedule: Schedule,
+    pub step_scaling: f64,
+    pub acceptance: Acceptance,
+    pub patience: Option<usize>,
+    pub reheat: Option<Reheat>,
+    pub step: usize,
+    pub last_improvement: usize,
+    pub last_reheat: usize,
+    pub boost: f64,
+    pub level: f64,
+    pub temperature: f64,
+    pub accept_rate: f64,
+    pub candidate: T,
+    pub best_candidate: T,
+}
+
+/// 每隔这么多步保存一次检查点
+const CHECKPOINT_INTERVAL: usize = 10000;
+
+/// 每隔这么多步汇报一次当前的步数、温度和最好方案的目标函数值，用于显示进度条
+const STATUS_INTERVAL: usize = 100;
+
+/// 退火算法求解的主函数。如果提供了截止时间，会根据实际的运行速度缩减总步数，保证在截止时间之前完成
+pub fn solve<T: Clone + Serialize, M: Clone + Display>(
     problem: &mut dyn Metaheuristics<T, M>,
     parameters: Parameters,
     report_after: Option<f64>,
+    deadline: Option<Instant>,
     interface: &dyn Interface,
 ) -> T {
-    let mut best_candidate = problem.generate_candidate();
-    let mut best_rank = problem.rank_candidate(&best_candidate);
-    let mut annealing_candidate = problem.clone_candidate(&best_candidate);
-    let mut annealing_rank = best_rank.clone();
-    let Parameters {
+    let candidate = problem.generate_candidate();
+    let (_, loss) = problem.rank_candidate(&candidate);
+    let checkpoint = Checkpoint {
+        t_max: parameters.t_max,
+        t_min: parameters.t_min,
+        steps: parameters.steps,
+        schedule: parameters.schedule.unwrap_or_default(),
+        step_scaling: parameters.step_scaling.unwrap_or(0.0).max(-0.9),
+        acceptance: parameters.acceptance.unwrap_or_default(),
+        patience: parameters.patience,
+        reheat: parameters.reheat,
+        step: 0,
+        last_improvement: 0,
+        last_reheat: 0,
+        boost: 1.0,
+        level: loss + parameters.t_max,
+        temperature: parameters.t_max,
+        accept_rate: 0.5,
+        best_candidate: problem.clone_candidate(&candidate),
+        candidate,
+    };
+    resume(problem, checkpoint, report_after, deadline, interface)
+}
+
+/// 从一个检查点开始继续退火
+pub fn resume<T: Clone + Serialize, M: Clone + Display>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    checkpoint: Checkpoint<T>,
+    report_after: Option<f64>,
+    deadline: Option<Instant>,
+    interface: &dyn Interface,
+) -> T {
+    let Checkpoint {
         t_max,
         t_min,
-        steps,
-    } = parameters;
-    let log_space = t_max.ln() - t_min.ln();
+        mut steps,
+        schedule,
+        step_scaling,
+        acceptance,
+        patience,
+        reheat,
+        step: initial_step,
+        mut last_improvement,
+        mut last_reheat,
+        mut boost,
+        mut level,
+        mut temperature,
+        mut accept_rate,
+        candidate: mut annealing_candidate,
+        mut best_candidate,
+    } = checkpoint;
+    let mut best_rank = problem.rank_candidate(&best_candidate);
+    let mut annealing_rank = problem.rank_candidate(&annealing_candidate);
     let start = Instant::now();
+    let mut reported_steps = steps;
+    let mut step = initial_step;
 
-    for step in 0..steps {
+    while step < steps {
+        // 最好方案连续若干步没有改进，说明已经收敛，提前停止
+        if patience.is_some_and(|patience| step - last_improvement >= patience) {
+            interface.report_early_stop(step, step - last_improvement);
+            break;
+        }
+        // 每隔一千步根据实际的运行速度估计截止时间之前还能运行多少步，如果不够就缩减总步数，降温过程也随之加快
+        if let Some(deadline) = deadline {
+            if step > initial_step && (step - initial_step) % 1000 == 0 {
+                let per_step = start.elapsed().as_secs_f64() / (step - initial_step) as f64;
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let affordable = step + (remaining.as_secs_f64() / per_step) as usize;
+                if affordable < steps {
+                    steps = affordable.max(step + 1);
+                    // 只在总步数变化较大时汇报，避免刷屏
+                    if (steps as f64) < 0.95 * reported_steps as f64 {
+                        reported_steps = steps;
+                        interface.report_parameters(t_max, t_min, steps);
+                    }
+                }
+            }
+        }
         let progress = step as f64 / steps as f64;
-        let temperature = t_max / (log_space * progress).exp();
+        // Lam 自适应降温根据当前温度和近期的接受率来调节温度，其他方式直接由进度算出温度
+        if let Schedule::Lam = schedule {
+            let ratio = if accept_rate > Schedule::target_accept_rate(progress) {
+                0.999
+            } else {
+                1.0 / 0.999
+            };
+            temperature = (temperature * ratio).clamp(t_min, t_max);
+        } else {
+            temperature = schedule.temperature(t_max, t_min, stage_progress(progress, step_scaling));
+        }
+        // 最近一次改进或者回温之后又停滞了足够多步，并且接受率已经很低，就回温
+        if let Some(reheat) = &reheat {
+            if accept_rate < reheat.accept_rate
+                && step - last_improvement.max(last_reheat) >= reheat.stagnation
+            {
+                boost *= reheat.factor;
+                last_reheat = step;
+                interface.report_reheat(step, (temperature * boost).min(t_max));
+            }
+            boost = 1.0 + (boost - 1.0) * REHEAT_DECAY;
+        }
+        let current = (temperature * boost).min(t_max);
         let next_candidate = problem.tweak_candidate(&annealing_candidate);
         let next_rank = problem.rank_candidate(&next_candidate);
-        if step == 1000 {
+        if step == initial_step + 1000 {
             let elapsed = start.elapsed().as_micros() / 1000;
             interface.report_elapsed(elapsed);
         }
         let improvement = next_rank.1 - annealing_rank.1;
-        if improvement < 0.0 || (random::<f64>() < (-improvement / temperature).exp()) {
+        problem.report_improvement(improvement);
+        let accepted = improvement < 0.0
+            || match acceptance {
+                Acceptance::Metropolis => problem.rng().gen::<f64>() < (-improvement / current).exp(),
+                Acceptance::Threshold => improvement < current,
+                Acceptance::GreatDeluge => next_rank.1 < level,
+                Acceptance::RecordToRecord => next_rank.1 < best_rank.1 + current,
+            };
+        if accepted {
+            debug!("第 {step} 步接受了新方案，目标函数变化 {improvement:+.6}，温度 {current:.2e}");
+            problem.save_move(step, &annealing_candidate, &next_candidate, improvement, interface);
             annealing_candidate = next_candidate;
             annealing_rank = next_rank;
+        } else {
+            trace!("第 {step} 步拒绝了新方案，目标函数变化 {improvement:+.6}，温度 {current:.2e}");
         }
+        accept_rate = 0.998 * accept_rate + if accepted { 0.002 } else { 0.0 };
+        level = level.min(annealing_rank.1 + current);
         if annealing_rank.1 < best_rank.1 {
             best_rank = annealing_rank.clone();
             best_candidate = problem.clone_candidate(&annealing_candidate);
+            last_improvement = step;
             problem.save_candidate(
                 &best_candidate,
                 &best_rank,
@@ -58,11 +285,43 @@ pub fn solve<T: Clone, M: Clone + Display>(
                 interface,
             );
         }
+        if step % STATUS_INTERVAL == 0 {
+            interface.report_status(step, steps, current, best_rank.1);
+        }
         if step % 1000 == 0 {
-            interface.report_schedule(step, temperature, format!("{}", annealing_rank.0));
+            let metric = format!("{}", annealing_rank.0);
+            interface.report_schedule(step, current, schedule, metric);
+        }
+        if (step + 1) % CHECKPOINT_INTERVAL == 0 {
+            let checkpoint = Checkpoint {
+                t_max,
+                t_min,
+                steps,
+                schedule,
+                step_scaling,
+                acceptance,
+                patience,
+                reheat: reheat.clone(),
+                step: step + 1,
+                last_improvement,
+                last_reheat,
+                boost,
+                level,
+                temperature,
+                accept_rate,
+                candidate: problem.clone_candidate(&annealing_candidate),
+                best_candidate: problem.clone_candidate(&best_candidate),
+            };
+            let state = bincode::serialize(&checkpoint).unwrap();
+            problem.save_checkpoint(step, state, interface);
         }
+        step += 1;
     }
-    interface.report_schedule(steps, t_min, format!("{}", annealing_rank.0));
+    // 提前停止时也以实际执行的步数作为总步数，表示优化已经结束；温度与循环中一样包括回温的效果
+    let current = (temperature * boost).min(t_max);
+    interface.report_status(step, step, current, best_rank.1);
+    let metric = format!("{}", annealing_rank.0);
+    interface.report_schedule(step, current, schedule, metric);
     problem.save_candidate(&best_candidate, &best_rank, true, interface);
     best_candidate
 }
@@ -81,7 +340,7 @@ fn trial_run<T: Clone, M: Clone>(
         let next_candidate = problem.tweak_candidate(&candidate);
         let (_, next_energy) = problem.rank_candidate(&next_candidate);
         let energy_delta = next_energy - energy;
-        if energy_delta < 0.0 || (-energy_delta / temperature).exp() > random::<f64>() {
+        if energy_delta < 0.0 || (-energy_delta / temperature).exp() > problem.rng().gen::<f64>() {
             accepts += 1;
             if energy_delta < 0.0 {
                 improves += 1;
@@ -96,14 +355,14 @@ fn trial_run<T: Clone, M: Clone>(
 }
 
 // 不提供参数，而是提供预期运行时间，通过试验来获得一组参数的办法
-pub fn autosolve<T: Clone, M: Clone + Display>(
+pub fn find_parameters<T: Clone, M: Clone>(
     problem: &mut dyn Metaheuristics<T, M>,
     runtime: u64,
-    report_after: Option<f64>,
     interface: &dyn Interface,
-) -> T {
+) -> Parameters {
     let batch = 1000;
     interface.init_autosolve();
+    let start = Instant::now();
     let mut candidate = problem.generate_candidate();
     let (_, energy) = problem.rank_candidate(&candidate);
     let mut sum_delta = 0.0;
@@ -114,22 +373,17 @@ pub fn autosolve<T: Clone, M: Clone + Display>(
     }
     let initial_guess = sum_delta / batch as f64;
     let mut temperature = initial_guess;
-    let mut total_steps = 0_usize;
-    let start = Instant::now();
     let mut accept_rate;
     let mut improve_rate;
     (candidate, accept_rate, improve_rate) = trial_run(problem, candidate, temperature, batch);
-    total_steps += batch;
     while accept_rate > 0.98 {
         temperature /= 2.0;
         (candidate, accept_rate, improve_rate) = trial_run(problem, candidate, temperature, batch);
-        total_steps += batch;
         interface.report_trial_t_max(temperature, accept_rate);
     }
     while accept_rate < 0.98 {
         temperature *= 2.0;
         (candidate, accept_rate, improve_rate) = trial_run(problem, candidate, temperature, batch);
-        total_steps += batch;
         interface.report_trial_t_max(temperature, accept_rate);
     }
     interface.report_t_max(temperature);
@@ -139,19 +393,71 @@ pub fn autosolve<T: Clone, M: Clone + Display>(
     while improve_rate > 0.01 {
         temperature /= 4.0;
         (candidate, _, improve_rate) = trial_run(problem, candidate, temperature, batch);
-        total_steps += batch;
         interface.report_trial_t_min(temperature, improve_rate);
     }
     interface.report_t_min(temperature);
     let t_min = temperature;
-    let elapsed = start.elapsed();
-    let duration = Duration::new(runtime * 60, 0);
-    let steps = total_steps * duration.as_millis() as usize / elapsed.as_millis() as usize;
+    // 在最高温和最低温的几何平均处实测每步的耗时：温度会影响接受率，从而影响复制方案的开销，中间的温度更有代表性
+    let calibration = Instant::now();
+    trial_run(problem, candidate, (t_max * t_min).sqrt(), batch);
+    let per_step = calibration.elapsed().as_secs_f64() / batch as f64;
+    // 寻找参数所用的时间也计入运行时间
+    let budget = Duration::new(runtime * 60, 0).saturating_sub(start.elapsed());
+    let steps = ((budget.as_secs_f64() / per_step) as usize).max(batch);
+    interface.report_step_estimate(per_step * 1e6, budget.as_secs(), steps);
     interface.report_parameters(t_max, t_min, steps);
-    let parameters = Parameters {
+    Parameters {
         t_max,
         t_min,
         steps,
-    };
-    solve(problem, parameters, report_after, interface)
+        schedule: None,
+        step_scaling: None,
+        acceptance: None,
+        patience: None,
+        reheat: None,
+    }
+}
+
+pub fn autosolve<T: Clone + Serialize, M: Clone + Display>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    runtime: u64,
+    report_after: Option<f64>,
+    deadline: Option<Instant>,
+    interface: &dyn Interface,
+) -> T {
+    let parameters = find_parameters(problem, runtime, interface);
+    solve(problem, parameters, report_after, deadline, interface)
+}
+
+/// 退火算法。如果不提供参数，就在求解之前按照运行时间自动寻找参数
+pub struct SimulatedAnnealing {
+    pub parameters: Option<Parameters>,
+    pub runtime: u64,
+    pub report_after: Option<f64>,
+    pub deadline: Option<Instant>,
+}
+
+impl SimulatedAnnealing {
+    pub fn new(
+        parameters: Option<Parameters>,
+        runtime: u64,
+        report_after: Option<f64>,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            parameters,
+            runtime,
+            report_after,
+            deadline,
+        }
+    }
+}
+
+impl<T: Clone + Serialize, M: Clone + Display> Metaheuristic<T, M> for SimulatedAnnealing {
+    fn solve(&mut self, problem: &mut dyn Metaheuristics<T, M>, interface: &dyn Interface) -> T {
+        match self.parameters.clone() {
+            Some(parameters) => solve(problem, parameters, self.report_after, self.deadline, interface),
+            None => autosolve(problem, self.runtime, self.report_after, self.deadline, interface),
+        }
+    }
 }
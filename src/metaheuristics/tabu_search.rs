@@ -0,0 +1,102 @@
+//! 禁忌搜索
+
+use super::{Change, Metaheuristics};
+use crate::interface::Interface;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+/// 禁忌搜索的参数，包括步数、每步考察的邻域大小和禁忌期限
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameters {
+    pub steps: usize,
+    pub neighborhood_size: Option<usize>,
+    pub tenure: Option<usize>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            steps: 10000,
+            neighborhood_size: Some(20),
+            tenure: Some(50),
+        }
+    }
+}
+
+/// 禁忌搜索求解的主函数
+///
+/// 每一步在当前解的邻域中采样若干个解，选择其中最好的一个非禁忌解作为下一步的解，即使它比当前解更差。
+/// 元素离开一个按键之后，在禁忌期限内不允许再回到这个按键上，除非这样能得到一个历史最好的解（特赦准则）。
+pub fn solve<T: Clone, M: Clone + Display>(
+    problem: &mut dyn Metaheuristics<T, M>,
+    parameters: Parameters,
+    report_after: Option<f64>,
+    interface: &dyn Interface,
+) -> T {
+    let Parameters {
+        steps,
+        neighborhood_size,
+        tenure,
+    } = parameters;
+    let neighborhood_size = neighborhood_size.unwrap_or(20).max(1);
+    let tenure = tenure.unwrap_or(50);
+    let mut current_candidate = problem.generate_candidate();
+    let mut current_rank = problem.rank_candidate(&current_candidate);
+    let mut best_candidate = problem.clone_candidate(&current_candidate);
+    let mut best_rank = current_rank.clone();
+    let mut tabu_list: VecDeque<(usize, usize)> = VecDeque::with_capacity(tenure + 1);
+
+    for step in 0..steps {
+        let progress = step as f64 / steps as f64;
+        let mut best_neighbor: Option<(T, (M, f64), Vec<Change>)> = None;
+        for _ in 0..neighborhood_size {
+            let neighbor = problem.tweak_candidate(&current_candidate);
+            let changes = problem.compare_candidates(&current_candidate, &neighbor);
+            if changes.is_empty() {
+                continue;
+            }
+            let rank = problem.rank_candidate(&neighbor);
+            let is_tabu = changes
+                .iter()
+                .any(|change| tabu_list.contains(&(change.element, change.to)));
+            if is_tabu && rank.1 >= best_rank.1 {
+                continue;
+            }
+            let is_better = best_neighbor
+                .as_ref()
+                .is_none_or(|(_, best, _)| rank.1 < best.1);
+            if is_better {
+                best_neighbor = Some((neighbor, rank, changes));
+            }
+        }
+        if let Some((neighbor, rank, changes)) = best_neighbor {
+            current_candidate = neighbor;
+            current_rank = rank;
+            for change in changes {
+                tabu_list.push_back((change.element, change.from));
+                if tabu_list.len() > tenure {
+                    tabu_list.pop_front();
+                }
+            }
+        }
+        if current_rank.1 < best_rank.1 {
+            best_rank = current_rank.clone();
+            best_candidate = problem.clone_candidate(&current_candidate);
+            problem.save_candidate(
+                &best_candidate,
+                &best_rank,
+                progress > report_after.unwrap_or(0.9),
+                interface,
+            );
+        }
+        if step % 100 == 0 {
+            interface.report_progress(step, format!("{}", current_rank.0));
+        }
+    }
+    interface.report_progress(steps, format!("{}", current_rank.0));
+    problem.save_candidate(&best_candidate, &best_rank, true, interface);
+    best_candidate
+}
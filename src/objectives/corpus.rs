@@ -0,0 +1,70 @@
+//! 语料模拟
+//!
+//! 把语料切分成字和词的序列，然后按照实际输入的顺序逐项累计击键数、组合当量和选重，相邻两项之间的击键也计入组合当量。这样得到的指标比把字表和词表当作两个互不相关的集合更接近真实的输入体验。
+//!
+
+use std::collections::HashMap;
+
+/// 切分后语料中的一项
+#[derive(Debug, Clone, Copy)]
+pub enum CorpusItem {
+    /// 字表中的第 index 个字
+    Character(usize),
+    /// 词表中的第 index 个词，由 length 个字组成
+    Word { index: usize, length: usize },
+    /// 定义了编码的第 index 个标点符号
+    Symbol(usize),
+    /// 既不在字表也不在词表中、也没有定义编码的字符，例如换行，输入在这里中断
+    Break,
+}
+
+/// 用正向最大匹配切分语料：在每个位置上尽量匹配最长的词，匹配不到词时按单字或者标点处理
+pub fn segment(
+    text: &str,
+    characters: &[char],
+    words: &[String],
+    symbols: &[char],
+) -> Vec<CorpusItem> {
+    let character_index: HashMap<char, usize> =
+        characters.iter().enumerate().map(|(i, x)| (*x, i)).collect();
+    let symbol_index: HashMap<char, usize> =
+        symbols.iter().enumerate().map(|(i, x)| (*x, i)).collect();
+    let word_index: HashMap<&str, usize> =
+        words.iter().enumerate().map(|(i, x)| (x.as_str(), i)).collect();
+    let max_length = words.iter().map(|x| x.chars().count()).max().unwrap_or(1);
+    let chars: Vec<char> = text.chars().collect();
+    let mut items = vec![];
+    let mut position = 0;
+    while position < chars.len() {
+        let mut matched = None;
+        let longest = max_length.min(chars.len() - position);
+        for length in (2..=longest).rev() {
+            let candidate: String = chars[position..position + length].iter().collect();
+            if let Some(index) = word_index.get(candidate.as_str()) {
+                matched = Some(CorpusItem::Word {
+                    index: *index,
+                    length,
+                });
+                break;
+            }
+        }
+        let item = matched.unwrap_or_else(|| {
+            let char = chars[position];
+            match (character_index.get(&char), symbol_index.get(&char)) {
+                (Some(index), _) => CorpusItem::Character(*index),
+                (None, Some(index)) => CorpusItem::Symbol(*index),
+                (None, None) => CorpusItem::Break,
+            }
+        });
+        position += match item {
+            CorpusItem::Word { length, .. } => length,
+            _ => 1,
+        };
+        // 连续的中断只需要保留一个
+        if matches!(item, CorpusItem::Break) && matches!(items.last(), Some(CorpusItem::Break)) {
+            continue;
+        }
+        items.push(item);
+    }
+    items
+}
@@ -0,0 +1,92 @@
+//! 用户自定义的目标函数分量
+//!
+//! 用户用 Rhai 脚本定义一个 `score(entries)` 函数，它接收某一部分的所有字词（按频率从高到低排列）并返回一个数值，这个数值乘以权重之后计入目标函数。这样一些小众的指标不需要修改本程序就能参与优化。
+//!
+
+use crate::config::CustomWeights;
+use crate::error::Error;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::iter::zip;
+use std::sync::{Arc, Mutex};
+
+/// 一个编译好的自定义分量。引擎和语法树都是只读的，在多个线程之间共享；作用域每个副本各有一份。
+/// 传给脚本的字词数组只构造一次作为模板，每次评测在它的拷贝上填入编码，这样脚本修改了数组也不会影响下一次评测
+pub struct CustomComponent {
+    pub name: String,
+    pub partial: String,
+    pub weight: f64,
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+    scope: Mutex<Scope<'static>>,
+    entries: Array,
+}
+
+impl Clone for CustomComponent {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            partial: self.partial.clone(),
+            weight: self.weight,
+            engine: self.engine.clone(),
+            ast: self.ast.clone(),
+            scope: Mutex::new(Scope::new()),
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl CustomComponent {
+    pub fn new(config: &CustomWeights) -> Result<Self, Error> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile(&config.script)
+            .map_err(|e| format!("自定义指标 {} 的脚本无法编译：{}", config.name, e))?;
+        let partial = config
+            .partial
+            .clone()
+            .unwrap_or("characters_full".to_string());
+        Ok(Self {
+            name: config.name.clone(),
+            partial,
+            weight: config.weight,
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+            scope: Mutex::new(Scope::new()),
+            entries: Array::new(),
+        })
+    }
+
+    /// 构造传给脚本的字词数组的模板。字词和频率与布局无关，所以只在构造目标函数时调用一次
+    pub fn prepare(&mut self, items: Vec<String>, frequencies: &[f64]) {
+        self.entries = zip(items, frequencies)
+            .map(|(item, frequency)| {
+                let mut map = Map::new();
+                map.insert("item".into(), item.into());
+                map.insert("code".into(), "".into());
+                map.insert("duplicated".into(), false.into());
+                map.insert("frequency".into(), (*frequency).into());
+                map.into()
+            })
+            .collect();
+    }
+
+    /// 把每个字词的编码和是否重码写入字词数组的拷贝，然后调用脚本中的 score 函数，整数和浮点数的返回值都可以接受
+    pub fn score(&self, codes: impl Iterator<Item = (String, bool)>) -> Result<f64, Error> {
+        let mut entries = self.entries.clone();
+        for (entry, (code, duplicated)) in zip(entries.iter_mut(), codes) {
+            if let Some(mut map) = entry.write_lock::<Map>() {
+                map.insert("code".into(), code.into());
+                map.insert("duplicated".into(), duplicated.into());
+            }
+        }
+        let mut scope = self.scope.lock().unwrap(); // 作用域只在这里使用，不会中毒
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut scope, &self.ast, "score", (entries,))
+            .map_err(|e| format!("自定义指标 {} 的脚本运行出错：{}", self.name, e))?;
+        result
+            .as_float()
+            .or_else(|_| result.as_int().map(|x| x as f64))
+            .map_err(|t| format!("自定义指标 {} 的返回值类型 {} 不是数值", self.name, t).into())
+    }
+}
@@ -3,11 +3,50 @@
 //! 参考法月的《科学形码测评系统》，基于定义来推导出各种差指法组合都有哪些，然后封装成一个结构体便于主程序使用。
 //! 
 
-use std::collections::HashSet;
+use crate::config::{Hand, KeyboardConfig};
+use std::collections::{HashMap, HashSet};
 
 type FingeringSet = HashSet<(char, char)>;
 type Layout = [Vec<char>; 4];
 
+/// 同指：相邻两次击键用同一个手指击打不同的键
+pub const SAME_FINGER: usize = 0;
+/// 同指大跨排：同指且跨越两排或以上
+pub const SAME_FINGER_LARGE_JUMP: usize = 1;
+/// 同指小跨排：同指且跨越一排
+pub const SAME_FINGER_SMALL_JUMP: usize = 2;
+/// 左右互击：相邻两次击键分别由左右手击打
+pub const HAND_ALTERNATION: usize = 3;
+/// 跨排：同一只手相邻两次击键跨越两排或以上，例如从上排到下排
+pub const ROW_JUMP: usize = 4;
+/// 指法类型的数量
+pub const FINGERING_TYPES: usize = 5;
+
+/// 每个手指的基准键，从左手小指到右手小指，拇指没有基准键
+const HOME_KEYS: [Option<char>; 10] = [
+    Some('a'),
+    Some('s'),
+    Some('d'),
+    Some('f'),
+    None,
+    None,
+    Some('j'),
+    Some('k'),
+    Some('l'),
+    Some(';'),
+];
+
+/// 美式键盘上需要按住上档键才能输入的符号，与 BASE_SYMBOLS 中同一位置的基础按键一一对应。
+/// 下划线在本程序中表示空格，所以不作为上档字符
+const SHIFTED_SYMBOLS: &str = "~!@#$%^&*()+{}|:\"<>?";
+const BASE_SYMBOLS: &str = "`1234567890=[]\\;',./";
+
+/// 键盘几何：每个按键的坐标，单位通常是键宽
+pub type KeyboardGeometry = HashMap<char, (f64, f64)>;
+
+/// 一个编码中各种指法类型出现的次数，以 FINGERING_TYPES 之前的常量为下标
+pub type FingeringCounts = [u8; FINGERING_TYPES];
+
 #[derive(Debug)]
 pub struct FingeringTypes {
     same_hand: FingeringSet,
@@ -67,21 +106,27 @@ pub fn get_partial_fingering_types(layout: &Layout) -> FingeringTypes {
     }
 }
 
-pub fn get_fingering_types() -> FingeringTypes {
-    let left_layout: Layout = [
+fn left_layout() -> Layout {
+    [
         vec!['5', '4', '3', '2', '1'],
         vec!['t', 'r', 'e', 'w', 'q'],
         vec!['g', 'f', 'd', 's', 'a'],
         vec!['b', 'v', 'c', 'x', 'z'],
-    ];
-    let right_layout: Layout = [
+    ]
+}
+
+fn right_layout() -> Layout {
+    [
         vec!['6', '7', '8', '9', '0', '-', '='],
         vec!['y', 'u', 'i', 'o', 'p', '[', ']'],
         vec!['h', 'j', 'k', 'l', ';', '\''],
         vec!['n', 'm', ',', '.', '/'],
-    ];
-    let mut left_types = get_partial_fingering_types(&left_layout);
-    let right_types = get_partial_fingering_types(&right_layout);
+    ]
+}
+
+pub fn get_fingering_types() -> FingeringTypes {
+    let mut left_types = get_partial_fingering_types(&left_layout());
+    let right_types = get_partial_fingering_types(&right_layout());
     left_types.same_hand.extend(right_types.same_hand);
     left_types
         .same_finger_large_jump
@@ -97,3 +142,237 @@ pub fn get_fingering_types() -> FingeringTypes {
         .extend(right_types.awkward_upside_down);
     left_types
 }
+
+/// 键盘模型：每个按键由哪只手的哪个手指击打、位于哪一排哪一列
+///
+/// 手指从左到右编号为 0 至 9，即左手小指、无名指、中指、食指、拇指，右手拇指、食指、中指、无名指、小指；排从上到下编号，数字排为 0；列从左到右编号，左手小指的主列为 0。
+/// 默认按照标准指法给出主键盘区的手指和排，用户可以在配置中覆盖其中一部分按键。左右手默认由手指决定，也可以单独指定
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    fingers: HashMap<char, usize>,
+    hands: HashMap<char, Hand>,
+    rows: HashMap<char, usize>,
+    columns: HashMap<char, usize>,
+    shifted: HashMap<char, char>,
+}
+
+impl Keyboard {
+    pub fn new(config: Option<&KeyboardConfig>) -> Self {
+        let map_char_index_to_finger: [usize; 7] = [2, 2, 3, 4, 5, 5, 5];
+        let mut fingers = HashMap::new();
+        let mut rows = HashMap::new();
+        let mut columns = HashMap::new();
+        for (row, content) in left_layout().iter().enumerate() {
+            for (column, char) in content.iter().enumerate() {
+                fingers.insert(*char, 5 - map_char_index_to_finger[column]);
+                rows.insert(*char, row);
+                columns.insert(*char, 4 - column);
+            }
+        }
+        for (row, content) in right_layout().iter().enumerate() {
+            for (column, char) in content.iter().enumerate() {
+                fingers.insert(*char, 4 + map_char_index_to_finger[column]);
+                rows.insert(*char, row);
+                columns.insert(*char, 5 + column);
+            }
+        }
+        // 上档字符沿用基础按键的手指和排
+        let mut shifted = HashMap::new();
+        if let Some(shift) = config.and_then(|x| x.shift.as_ref()) {
+            shifted.extend(('A'..='Z').map(|x| (x, x.to_ascii_lowercase())));
+            shifted.extend(SHIFTED_SYMBOLS.chars().zip(BASE_SYMBOLS.chars()));
+            if let Some(custom) = &shift.keys {
+                shifted.extend(custom.iter().map(|(k, v)| (*k, *v)));
+            }
+            for (key, base) in shifted.iter() {
+                if let Some(finger) = fingers.get(base).copied() {
+                    fingers.insert(*key, finger);
+                }
+                if let Some(row) = rows.get(base).copied() {
+                    rows.insert(*key, row);
+                }
+                if let Some(column) = columns.get(base).copied() {
+                    columns.insert(*key, column);
+                }
+            }
+        }
+        if let Some(custom) = config.and_then(|x| x.fingers.as_ref()) {
+            fingers.extend(custom.iter().map(|(k, v)| (*k, *v)));
+        }
+        if let Some(custom) = config.and_then(|x| x.rows.as_ref()) {
+            rows.extend(custom.iter().map(|(k, v)| (*k, *v)));
+        }
+        if let Some(custom) = config.and_then(|x| x.columns.as_ref()) {
+            columns.extend(custom.iter().map(|(k, v)| (*k, *v)));
+        }
+        let mut hands: HashMap<char, Hand> = fingers
+            .iter()
+            .map(|(k, v)| (*k, if *v < 5 { Hand::Left } else { Hand::Right }))
+            .collect();
+        if let Some(custom) = config.and_then(|x| x.hands.as_ref()) {
+            hands.extend(custom.iter().map(|(k, v)| (*k, *v)));
+        }
+        Self {
+            fingers,
+            hands,
+            rows,
+            columns,
+            shifted,
+        }
+    }
+
+    /// 键盘区域包含的所有按键，例如「左手」「右手食指区」「上排」「左手下排」「第 3 列」
+    ///
+    /// 区域名称依次由左右手、手指、排和列组合而成，每一部分都可以省略，手指后面可以加「区」字，列写作「第 N 列」，从左到右从 1 开始编号。返回同时属于这几部分的按键，无法识别的名称返回 None
+    pub fn zone(&self, name: &str) -> Option<Vec<char>> {
+        let mut rest = name;
+        let mut strip = |candidates: &[&str]| {
+            let index = candidates.iter().position(|x| rest.starts_with(x))?;
+            rest = &rest[candidates[index].len()..];
+            Some(index)
+        };
+        let hand = strip(&["左手", "右手"]).map(|x| [Hand::Left, Hand::Right][x]);
+        let finger = strip(&["小指", "无名指", "中指", "食指", "拇指"]);
+        if finger.is_some() {
+            strip(&["区"]);
+        }
+        // 基准排就是中排
+        let row = strip(&["数字排", "上排", "中排", "下排", "基准排"]).map(|x| x.min(2));
+        let mut column = None;
+        if let Some(number) = rest.strip_prefix("第").and_then(|x| x.strip_suffix("列")) {
+            column = Some(number.trim().parse::<usize>().ok()?.checked_sub(1)?);
+            rest = "";
+        }
+        let parts = [hand.is_some(), finger.is_some(), row.is_some(), column.is_some()];
+        if !rest.is_empty() || !parts.contains(&true) {
+            return None;
+        }
+        let mut keys: Vec<char> = self
+            .fingers
+            .iter()
+            .filter(|(key, value)| {
+                // 手指按照左手的编号给出，右手的手指与之对称
+                let finger_matches = finger.is_none_or(|x| **value == x || **value == 9 - x);
+                let hand_matches = hand.is_none_or(|x| self.hands.get(key) == Some(&x));
+                let row_matches = row.is_none_or(|x| self.rows.get(key) == Some(&x));
+                let column_matches = column.is_none_or(|x| self.columns.get(key) == Some(&x));
+                finger_matches && hand_matches && row_matches && column_matches
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort();
+        Some(keys)
+    }
+
+    /// 上档字符对应的基础按键；不是上档字符或者没有启用上档键模型时返回 None
+    pub fn unshift(&self, key: char) -> Option<char> {
+        self.shifted.get(&key).copied()
+    }
+
+    /// 按键本身，或者上档字符对应的基础按键
+    fn base(&self, key: char) -> char {
+        self.unshift(key).unwrap_or(key)
+    }
+
+    /// 为用指分布数据补上上档字符，上档字符没有单独给出时沿用基础按键的数据
+    pub fn expand_keys(&self, table: &HashMap<char, f64>) -> HashMap<char, f64> {
+        let mut result = table.clone();
+        for (key, base) in self.shifted.iter() {
+            if let Some(value) = table.get(base) {
+                result.entry(*key).or_insert(*value);
+            }
+        }
+        result
+    }
+
+    /// 为速度当量表补上含有上档字符的组合，没有单独给出时沿用对应基础按键组合的当量
+    pub fn expand_pairs(&self, table: &HashMap<String, f64>) -> HashMap<String, f64> {
+        let mut result = table.clone();
+        if self.shifted.is_empty() {
+            return result;
+        }
+        let variants = |key: char| {
+            let mut keys = vec![key];
+            keys.extend(self.shifted.iter().filter(|x| *x.1 == key).map(|x| *x.0));
+            keys
+        };
+        for (pair, value) in table {
+            let chars: Vec<char> = pair.chars().collect();
+            if chars.len() != 2 {
+                continue;
+            }
+            for first in variants(chars[0]) {
+                for second in variants(chars[1]) {
+                    let pair: String = [first, second].iter().collect();
+                    if !table.contains_key(&pair) {
+                        result.insert(pair, *value);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// 击打某个按键的手指
+    pub fn finger(&self, key: char) -> Option<usize> {
+        self.fingers.get(&key).copied()
+    }
+
+    /// 击打某个按键的手
+    pub fn hand(&self, key: char) -> Option<Hand> {
+        self.hands.get(&key).copied()
+    }
+
+    /// 某个按键所在的排
+    pub fn row(&self, key: char) -> Option<usize> {
+        self.rows.get(&key).copied()
+    }
+
+    /// 依次击打一串按键时手指移动的总距离。每个手指从自己的基准键出发，击键后停留在所击的键上；
+    /// 不在键盘模型或键盘几何中的按键（例如选择键）不计距离
+    pub fn travel(&self, geometry: &KeyboardGeometry, keys: &[char]) -> f64 {
+        let mut positions = HOME_KEYS.map(|x| x.and_then(|key| geometry.get(&key).copied()));
+        let mut distance = 0.0;
+        for key in keys {
+            let position = geometry.get(key).or_else(|| geometry.get(&self.base(*key)));
+            if let (Some(finger), Some((x, y))) = (self.finger(*key), position) {
+                if let Some(Some((x0, y0))) = positions.get(finger) {
+                    distance += ((x - x0).powi(2) + (y - y0).powi(2)).sqrt();
+                }
+                if finger < positions.len() {
+                    positions[finger] = Some((*x, *y));
+                }
+            }
+        }
+        distance
+    }
+
+    /// 判断两个相邻击键属于哪些指法类型。不在键盘模型中的按键（例如选择键）不属于任何类型
+    pub fn classify(&self, first: char, second: char) -> FingeringCounts {
+        let mut counts = [0; FINGERING_TYPES];
+        if let (Some(hand1), Some(hand2)) = (self.hands.get(&first), self.hands.get(&second)) {
+            if hand1 != hand2 {
+                counts[HAND_ALTERNATION] = 1;
+            } else if let (Some(row1), Some(row2)) = (self.rows.get(&first), self.rows.get(&second)) {
+                if row1.abs_diff(*row2) >= 2 {
+                    counts[ROW_JUMP] = 1;
+                }
+            }
+        }
+        let (finger1, finger2) = match (self.fingers.get(&first), self.fingers.get(&second)) {
+            (Some(finger1), Some(finger2)) => (finger1, finger2),
+            _ => return counts,
+        };
+        if self.base(first) != self.base(second) && finger1 == finger2 {
+            counts[SAME_FINGER] = 1;
+            if let (Some(row1), Some(row2)) = (self.rows.get(&first), self.rows.get(&second)) {
+                match row1.abs_diff(*row2) {
+                    0 => {}
+                    1 => counts[SAME_FINGER_SMALL_JUMP] = 1,
+                    _ => counts[SAME_FINGER_LARGE_JUMP] = 1,
+                }
+            }
+        }
+        counts
+    }
+}
@@ -1,37 +1,75 @@
 // 递归定义各种度量的数据结构以及它们输出到命令行的方式
 
+use super::normalization::Term;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
 use std::fmt::Display;
 
-#[derive(Debug, Clone)]
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LevelMetric1 {
     pub length: usize,
     pub frequency: usize,
 }
 
-#[derive(Debug, Clone)]
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LevelMetric2 {
     pub length: usize,
     pub frequency: f64,
+    pub pair_equivalence: Option<f64>,
+}
+
+/// 简码与另一个字的全码相同的字的个数和频率
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictMetric {
+    pub count: usize,
+    pub frequency: f64,
 }
 
-#[derive(Debug, Clone)]
+/// 某一码长的码位利用情况。可用码位取理论码位数和字词数中的较小者
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtilizationMetric {
+    pub length: usize,
+    pub used: usize,
+    pub capacity: usize,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TierMetric {
+    pub from: Option<usize>,
     pub top: Option<usize>,
     pub duplication: Option<usize>,
+    pub coverage: Option<f64>,
+    pub length: Option<f64>,
+    pub pair_equivalence: Option<f64>,
     pub levels: Option<Vec<LevelMetric1>>,
 }
 
 impl Display for TierMetric {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let hanzi_numbers: Vec<char> = "一二三四五六七八九十".chars().collect();
-        let specifier = if let Some(top) = self.top {
-            format!("{} ", top)
-        } else {
-            String::from("全部")
+        let specifier = match (self.from, self.top) {
+            (Some(from), Some(top)) => format!("{}-{} ", from + 1, top),
+            (Some(from), None) => format!("{} 之后", from),
+            (None, Some(top)) => format!("{} ", top),
+            (None, None) => String::from("全部"),
         };
         if let Some(duplication) = self.duplication {
             f.write_str(&format!("{}选重：{}；", specifier, duplication))?;
         }
+        if let Some(coverage) = self.coverage {
+            f.write_str(&format!("{}覆盖率：{:.2}%；", specifier, coverage * 100.0))?;
+        }
+        if let Some(length) = self.length {
+            f.write_str(&format!("{}码长：{:.4}；", specifier, length))?;
+        }
+        if let Some(pair_equivalence) = self.pair_equivalence {
+            f.write_str(&format!("{}组合当量：{:.4}；", specifier, pair_equivalence))?;
+        }
         if let Some(levels) = &self.levels {
             for LevelMetric1 { length, frequency } in levels {
                 f.write_str(&format!(
@@ -46,8 +84,12 @@ impl Display for TierMetric {
     }
 }
 
-#[derive(Debug, Clone)]
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FingeringMetric {
+    pub same_finger: Option<f64>,
+    pub hand_alternation: Option<f64>,
+    pub row_jump: Option<f64>,
     pub same_hand: Option<f64>,
     pub same_finger_large_jump: Option<f64>,
     pub same_finger_small_jump: Option<f64>,
@@ -55,16 +97,70 @@ pub struct FingeringMetric {
     pub awkward_upside_down: Option<f64>,
 }
 
-#[derive(Debug, Clone)]
+impl Display for FingeringMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(same_finger) = self.same_finger {
+            f.write_str(&format!("同指：{:.2}%；", same_finger * 100.0))?;
+        }
+        if let Some(alternation) = self.hand_alternation {
+            f.write_str(&format!("左右互击：{:.2}%；", alternation * 100.0))?;
+        }
+        if let Some(row_jump) = self.row_jump {
+            f.write_str(&format!("跨排：{:.2}%；", row_jump * 100.0))?;
+        }
+        if let Some(large_jump) = self.same_finger_large_jump {
+            f.write_str(&format!("同指大跨排：{:.2}%；", large_jump * 100.0))?;
+        }
+        if let Some(small_jump) = self.same_finger_small_jump {
+            f.write_str(&format!("同指小跨排：{:.2}%；", small_jump * 100.0))?;
+        }
+        Ok(())
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeakFingerMetric {
+    pub pinky: Option<f64>,
+    pub ring: Option<f64>,
+    pub bottom_pinky: Option<f64>,
+}
+
+impl Display for WeakFingerMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(pinky) = self.pinky {
+            f.write_str(&format!("小指：{:.2}%；", pinky * 100.0))?;
+        }
+        if let Some(ring) = self.ring {
+            f.write_str(&format!("无名指：{:.2}%；", ring * 100.0))?;
+        }
+        if let Some(bottom_pinky) = self.bottom_pinky {
+            f.write_str(&format!("下排小指：{:.2}%；", bottom_pinky * 100.0))?;
+        }
+        Ok(())
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartialMetric {
     pub tiers: Option<Vec<TierMetric>>,
     pub duplication: Option<f64>,
+    pub ranked_duplication: Option<f64>,
     pub key_distribution: Option<f64>,
     pub new_key_equivalence: Option<f64>,
     pub new_key_equivalence_modified: Option<f64>,
     pub pair_equivalence: Option<f64>,
+    pub bad_pairs: Option<f64>,
+    pub triple_equivalence: Option<f64>,
     pub new_pair_equivalence: Option<f64>,
     pub fingering: Option<FingeringMetric>,
+    pub finger_load: Option<f64>,
+    pub travel_distance: Option<f64>,
+    pub weak_fingers: Option<WeakFingerMetric>,
+    pub utilization: Option<Vec<UtilizationMetric>>,
+    pub short_full_conflict: Option<ConflictMetric>,
+    pub dynamic_duplication: Option<f64>,
     pub levels: Option<Vec<LevelMetric2>>,
 }
 
@@ -75,9 +171,46 @@ impl Display for PartialMetric {
         if let Some(duplication) = self.duplication {
             f.write_str(&format!("选重率：{:.4}%；", duplication * 100.0))?;
         }
+        if let Some(ranked_duplication) = self.ranked_duplication {
+            f.write_str(&format!("选重代价：{:.4}；", ranked_duplication))?;
+        }
         if let Some(key_distribution) = self.key_distribution {
             f.write_str(&format!("用指分布偏差：{:.2}%；", key_distribution * 100.0))?;
         }
+        if let Some(finger_load) = self.finger_load {
+            f.write_str(&format!("手指负荷偏差：{:.4}；", finger_load))?;
+        }
+        if let Some(travel_distance) = self.travel_distance {
+            f.write_str(&format!("移动距离：{:.4}；", travel_distance))?;
+        }
+        if let Some(weak_fingers) = &self.weak_fingers {
+            f.write_str(&format!("{}", weak_fingers))?;
+        }
+        if let Some(dynamic_duplication) = self.dynamic_duplication {
+            f.write_str(&format!("动态选重率：{:.4}%；", dynamic_duplication * 100.0))?;
+        }
+        if let Some(conflict) = &self.short_full_conflict {
+            f.write_str(&format!(
+                "简全冲突：{} 字（{:.2}%）；",
+                conflict.count,
+                conflict.frequency * 100.0
+            ))?;
+        }
+        if let Some(utilization) = &self.utilization {
+            for UtilizationMetric {
+                length,
+                used,
+                capacity,
+            } in utilization
+            {
+                f.write_str(&format!(
+                    "{}键码位：{}/{}；",
+                    hanzi_numbers[length - 1],
+                    used,
+                    capacity
+                ))?;
+            }
+        }
         if let Some(equivalence) = self.new_key_equivalence {
             f.write_str(&format!("杏码式用指当量：{:.4}；", equivalence))?;
         }
@@ -87,16 +220,34 @@ impl Display for PartialMetric {
         if let Some(equivalence) = self.pair_equivalence {
             f.write_str(&format!("组合当量：{:.4}；", equivalence))?;
         }
+        if let Some(bad_pairs) = self.bad_pairs {
+            f.write_str(&format!("差键位：{:.4}；", bad_pairs))?;
+        }
+        if let Some(equivalence) = self.triple_equivalence {
+            f.write_str(&format!("三键当量：{:.4}；", equivalence))?;
+        }
         if let Some(equivalence) = self.new_pair_equivalence {
             f.write_str(&format!("杏码式组合当量：{:.4}；", equivalence))?;
         }
         if let Some(levels) = &self.levels {
-            for LevelMetric2 { length, frequency } in levels {
+            for LevelMetric2 {
+                length,
+                frequency,
+                pair_equivalence,
+            } in levels
+            {
                 f.write_str(&format!(
                     "{}键：{:.2}%；",
                     hanzi_numbers[length - 1],
                     frequency * 100.0
                 ))?;
+                if let Some(equivalence) = pair_equivalence {
+                    f.write_str(&format!(
+                        "{}键组合当量：{:.4}；",
+                        hanzi_numbers[length - 1],
+                        equivalence
+                    ))?;
+                }
             }
         }
         if let Some(tiers) = &self.tiers {
@@ -108,27 +259,282 @@ impl Display for PartialMetric {
     }
 }
 
-#[derive(Debug, Clone)]
+impl PartialMetric {
+    /// 按名称取出一个标量指标
+    pub fn component(&self, name: &str) -> Option<f64> {
+        match name {
+            "duplication" => self.duplication,
+            "ranked_duplication" => self.ranked_duplication,
+            "key_distribution" => self.key_distribution,
+            "finger_load" => self.finger_load,
+            "travel_distance" => self.travel_distance,
+            "pinky" => self.weak_fingers.as_ref()?.pinky,
+            "ring" => self.weak_fingers.as_ref()?.ring,
+            "bottom_pinky" => self.weak_fingers.as_ref()?.bottom_pinky,
+            "dynamic_duplication" => self.dynamic_duplication,
+            "short_full_conflict" => Some(self.short_full_conflict.as_ref()?.frequency),
+            "new_key_equivalence" => self.new_key_equivalence,
+            "new_key_equivalence_modified" => self.new_key_equivalence_modified,
+            "pair_equivalence" => self.pair_equivalence,
+            "bad_pairs" => self.bad_pairs,
+            "triple_equivalence" => self.triple_equivalence,
+            "new_pair_equivalence" => self.new_pair_equivalence,
+            "same_finger" => self.fingering.as_ref()?.same_finger,
+            "hand_alternation" => self.fingering.as_ref()?.hand_alternation,
+            "row_jump" => self.fingering.as_ref()?.row_jump,
+            "same_finger_large_jump" => self.fingering.as_ref()?.same_finger_large_jump,
+            "same_finger_small_jump" => self.fingering.as_ref()?.same_finger_small_jump,
+            _ => None,
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LearningMetric {
+    pub max_elements: Option<usize>,
+    pub variance: Option<f64>,
+    pub unrelated: Option<usize>,
+    pub overload: Option<usize>,
+}
+
+impl Display for LearningMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(max_elements) = self.max_elements {
+            f.write_str(&format!("单键最多元素：{}；", max_elements))?;
+        }
+        if let Some(variance) = self.variance {
+            f.write_str(&format!("元素数方差：{:.2}；", variance))?;
+        }
+        if let Some(unrelated) = self.unrelated {
+            f.write_str(&format!("混杂按键：{}；", unrelated))?;
+        }
+        if let Some(overload) = self.overload {
+            f.write_str(&format!("超限元素：{}；", overload))?;
+        }
+        Ok(())
+    }
+}
+
+impl LearningMetric {
+    pub fn component(&self, name: &str) -> Option<f64> {
+        match name {
+            "max_elements" => self.max_elements.map(|x| x as f64),
+            "variance" => self.variance,
+            "unrelated" => self.unrelated.map(|x| x as f64),
+            "overload" => self.overload.map(|x| x as f64),
+            _ => None,
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusMetric {
+    pub keys: Option<f64>,
+    pub pair_equivalence: Option<f64>,
+    pub duplication: Option<f64>,
+    pub same_hand_runs: Option<f64>,
+    /// 各种长度的同手连击所占的比例，下标为长度减一，最后一项包括所有更长的连击
+    pub same_hand_run_distribution: Option<Vec<f64>>,
+}
+
+impl Display for CorpusMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(keys) = self.keys {
+            f.write_str(&format!("字均键数：{:.4}；", keys))?;
+        }
+        if let Some(pair_equivalence) = self.pair_equivalence {
+            f.write_str(&format!("组合当量：{:.4}；", pair_equivalence))?;
+        }
+        if let Some(duplication) = self.duplication {
+            f.write_str(&format!("选重率：{:.4}%；", duplication * 100.0))?;
+        }
+        if let Some(same_hand_runs) = self.same_hand_runs {
+            f.write_str(&format!("同手连击：{:.4}；", same_hand_runs))?;
+        }
+        if let Some(distribution) = &self.same_hand_run_distribution {
+            let last = distribution.len();
+            let parts: Vec<String> = (1..=last)
+                .zip(distribution)
+                .map(|(length, x)| {
+                    let plus = if length == last { "+" } else { "" };
+                    format!("{}{}：{:.2}%", length, plus, x * 100.0)
+                })
+                .collect();
+            f.write_str(&format!("连击分布：{}；", parts.join("，")))?;
+        }
+        Ok(())
+    }
+}
+
+impl CorpusMetric {
+    pub fn component(&self, name: &str) -> Option<f64> {
+        match name {
+            "keys" => self.keys,
+            "pair_equivalence" => self.pair_equivalence,
+            "duplication" => self.duplication,
+            "same_hand_runs" => self.same_hand_runs,
+            _ => None,
+        }
+    }
+}
+
+/// 与参考布局的差异：所在按键与参考布局不同的元素数，以及这些元素的频率占全部元素频率的比例
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceMetric {
+    pub moved: usize,
+    pub frequency: f64,
+}
+
+impl Display for ReferenceMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!(
+            "变动元素：{}；变动频率：{:.2}%；",
+            self.moved,
+            self.frequency * 100.0
+        ))
+    }
+}
+
+impl ReferenceMetric {
+    pub fn component(&self, name: &str) -> Option<f64> {
+        match name {
+            "moved" => Some(self.moved as f64),
+            "frequency" => Some(self.frequency),
+            _ => None,
+        }
+    }
+}
+
+/// 软约束：被违反的软约束条数，以及按权重累计的惩罚
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstraintMetric {
+    pub violated: usize,
+    pub penalty: f64,
+}
+
+impl Display for ConstraintMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!(
+            "违反条数：{}；惩罚：{:.4}；",
+            self.violated, self.penalty
+        ))
+    }
+}
+
+impl ConstraintMetric {
+    pub fn component(&self, name: &str) -> Option<f64> {
+        match name {
+            "violated" => Some(self.violated as f64),
+            "penalty" => Some(self.penalty),
+            _ => None,
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metric {
+    #[serde(rename = "characters_full")]
     pub characters: Option<PartialMetric>,
+    #[serde(rename = "words_full")]
     pub words: Option<PartialMetric>,
+    #[serde(rename = "characters_short")]
     pub characters_reduced: Option<PartialMetric>,
+    #[serde(rename = "words_short")]
     pub words_reduced: Option<PartialMetric>,
+    pub punctuation: Option<PartialMetric>,
+    pub traditional: Option<PartialMetric>,
+    pub corpus: Option<CorpusMetric>,
+    pub learning: Option<LearningMetric>,
+    pub reference: Option<ReferenceMetric>,
+    pub constraints: Option<ConstraintMetric>,
+    pub custom: Option<Vec<(String, f64)>>,
+    pub normalized: Option<Vec<Term>>,
+}
+
+impl Metric {
+    /// 把全部指标序列化为 JSON，字段名与目标函数配置中的一致，没有计算的指标不出现
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap() // 指标中只有数字和字符串，序列化不会失败
+    }
+
+    /// 按「部分.指标」的形式取出一个标量指标，例如 `characters_full.duplication`，部分的名称与目标函数配置中的一致
+    pub fn component(&self, name: &str) -> Option<f64> {
+        let (partial, component) = name.split_once('.')?;
+        let partial = match partial {
+            "characters_full" => &self.characters,
+            "words_full" => &self.words,
+            "characters_short" => &self.characters_reduced,
+            "words_short" => &self.words_reduced,
+            "punctuation" => &self.punctuation,
+            "traditional" => &self.traditional,
+            "corpus" => return self.corpus.as_ref()?.component(component),
+            "learning" => return self.learning.as_ref()?.component(component),
+            "reference" => return self.reference.as_ref()?.component(component),
+            "constraints" => return self.constraints.as_ref()?.component(component),
+            "custom" => {
+                let custom = self.custom.as_ref()?;
+                return custom.iter().find(|x| x.0 == component).map(|x| x.1);
+            }
+            _ => return None,
+        };
+        partial.as_ref()?.component(component)
+    }
 }
 
 impl Display for Metric {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(characters) = &self.characters {
-            f.write_str(&format!("单字全码［{}］\n", characters))?;
+        let partials = [
+            ("单字全码", &self.characters),
+            ("词语全码", &self.words),
+            ("单字简码", &self.characters_reduced),
+            ("词语简码", &self.words_reduced),
+            ("标点符号", &self.punctuation),
+            ("繁体单字全码", &self.traditional),
+        ];
+        for (name, partial) in partials {
+            if let Some(partial) = partial {
+                f.write_str(&format!("{}［{}］\n", name, partial))?;
+                // 指法指标单独成行
+                if let Some(fingering) = &partial.fingering {
+                    f.write_str(&format!("{}指法［{}］\n", name, fingering))?;
+                }
+            }
+        }
+        if let Some(corpus) = &self.corpus {
+            f.write_str(&format!("语料［{}］\n", corpus))?;
+        }
+        if let Some(learning) = &self.learning {
+            f.write_str(&format!("学习成本［{}］\n", learning))?;
         }
-        if let Some(words) = &self.words {
-            f.write_str(&format!("词语全码［{}］\n", words))?;
+        if let Some(reference) = &self.reference {
+            f.write_str(&format!("参考布局［{}］\n", reference))?;
         }
-        if let Some(characters_reduced) = &self.characters_reduced {
-            f.write_str(&format!("单字简码［{}］\n", characters_reduced))?;
+        if let Some(constraints) = &self.constraints {
+            f.write_str(&format!("软约束［{}］\n", constraints))?;
         }
-        if let Some(words_reduced) = &self.words_reduced {
-            f.write_str(&format!("词语简码［{}］\n", words_reduced))?;
+        if let Some(custom) = &self.custom {
+            f.write_str("自定义［")?;
+            for (name, value) in custom {
+                f.write_str(&format!("{}：{:.4}；", name, value))?;
+            }
+            f.write_str("］\n")?;
+        }
+        if let Some(normalized) = &self.normalized {
+            f.write_str("归一化［")?;
+            for Term {
+                label,
+                raw,
+                normalized,
+            } in normalized
+            {
+                f.write_str(&format!("{}：{:.4} → {:.4}；", label, raw, normalized))?;
+            }
+            f.write_str("］\n")?;
         }
         Ok(())
     }
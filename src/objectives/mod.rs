@@ -2,42 +2,198 @@
 //!
 //!
 
+pub mod corpus;
+pub mod custom;
 pub mod fingering;
+pub mod normalization;
 pub mod metric;
 
 use crate::config::ObjectiveConfig;
+use crate::config::DistributionLoss;
+use crate::config::FullCodePolicy;
+use crate::config::CorpusWeights;
+use crate::config::Hand;
+use crate::config::LearningWeights;
+use crate::config::TierWeights;
+use crate::config::WeakFingerTerm;
+use corpus::CorpusItem;
+use custom::CustomComponent;
+use normalization::Loss;
+use normalization::Normalization;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use rand::Rng;
+use rand::SeedableRng;
 use crate::config::PartialWeights;
+use crate::constraints::Constraints;
+use crate::constraints::SoftConstraint;
+use crate::encoder::DefaultEncoder;
 use crate::encoder::Encoder;
 use crate::error::Error;
 use crate::representation::Assets;
 use crate::representation::Buffer;
+use crate::representation::Code;
 use crate::representation::Codes;
 use crate::representation::KeyMap;
 use crate::representation::Occupation;
 use crate::representation::Representation;
+use crate::representation::Sequence;
+use fingering::FingeringCounts;
+use fingering::Keyboard;
+use fingering::FINGERING_TYPES;
+use fingering::HAND_ALTERNATION;
+use fingering::ROW_JUMP;
+use fingering::SAME_FINGER;
+use fingering::SAME_FINGER_LARGE_JUMP;
+use fingering::SAME_FINGER_SMALL_JUMP;
+use metric::ConflictMetric;
+use metric::ConstraintMetric;
+use metric::CorpusMetric;
+use metric::FingeringMetric;
+use metric::LearningMetric;
 use metric::LevelMetric1;
 use metric::LevelMetric2;
 use metric::Metric;
 use metric::PartialMetric;
+use metric::ReferenceMetric;
 use metric::TierMetric;
+use metric::UtilizationMetric;
+use metric::WeakFingerMetric;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::iter::zip;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Objective {
     config: ObjectiveConfig,
-    encoder: Encoder,
+    encoder: DefaultEncoder,
+    // 自定义的编码器，设置之后用它来计算单字全码
+    custom_encoder: Option<Arc<dyn Encoder>>,
     character_frequencies: Frequencies,
     word_frequencies: Option<Frequencies>,
     ideal_distribution: Vec<f64>,
+    key_caps: Vec<f64>,
     pair_equivalence: Vec<f64>,
     new_pair_equivalence: Vec<f64>,
+    triple_equivalence: Vec<f64>,
+    bad_pairs: Vec<f64>,
+    corpus: Vec<usize>,
+    corpus_items: Vec<CorpusItem>,
+    punctuation_codes: Vec<Code>,
+    punctuation_frequencies: Frequencies,
+    // 繁体单字的拆分序列、频率和元素到字的倒排索引，只在评测繁体时使用
+    traditional_sequences: Vec<Sequence>,
+    traditional_frequencies: Frequencies,
+    traditional_entries: Vec<Vec<usize>>,
+    fingering: Vec<FingeringCounts>,
+    travel: Vec<f64>,
+    key_fingers: Vec<Option<usize>>,
+    key_rows: Vec<Option<usize>>,
+    key_hands: Vec<Option<Hand>>,
+    finger_targets: Vec<f64>,
+    element_groups: Vec<Option<usize>>,
+    reference: Vec<Option<usize>>,
+    reference_frequencies: Vec<f64>,
+    soft_constraints: Vec<SoftConstraint>,
+    custom: Vec<CustomComponent>,
+    repr_key: Vec<char>,
+    scales: Vec<(f64, f64)>,
 }
 
 pub type Frequencies = Vec<f64>;
 
+/// 标准分归一化时采样的随机布局数
+const CALIBRATION_SAMPLES: usize = 100;
+
+/// 同手连击分布中单独列出的最大长度，更长的连击都归入最后一项
+const MAX_RUN_LENGTH: usize = 6;
+
+/// 并行累计指标时每一块的字词数
+const PARALLEL_CHUNK: usize = 8192;
+
+/// 计算一部分编码的指标时逐项累计的量。并行累计时每一块各有一份，最后按顺序合并
+struct PartialTotals {
+    duplication: f64,
+    pairs: f64,
+    new_keys: f64,
+    new_keys_equivalence: f64,
+    pair_equivalence: f64,
+    bad_pairs: f64,
+    triple_equivalence: f64,
+    triples: f64,
+    new_pair_equivalence: f64,
+    fingering: [f64; FINGERING_TYPES],
+    keys: f64,
+    travel: f64,
+    ranked_duplication: f64,
+    first_keys: Vec<f64>,
+    last_keys: Vec<f64>,
+    levels: Vec<f64>,
+    levels_pair_equivalence: Vec<f64>,
+    tiers_duplication: Vec<usize>,
+    tiers_coverage: Vec<usize>,
+    tiers_frequency: Vec<f64>,
+    tiers_length: Vec<f64>,
+    tiers_pairs: Vec<f64>,
+    tiers_pair_equivalence: Vec<f64>,
+    tiers_levels: Vec<Vec<usize>>,
+    distribution: Vec<f64>,
+    used_codes: HashSet<Code>,
+    chuma: Vec<f64>,
+    moma: Vec<f64>,
+}
+
+impl PartialTotals {
+    fn add<T: Copy + std::ops::AddAssign>(x: &mut [T], y: &[T]) {
+        for (a, b) in x.iter_mut().zip(y) {
+            *a += *b;
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.duplication += other.duplication;
+        self.pairs += other.pairs;
+        self.new_keys += other.new_keys;
+        self.new_keys_equivalence += other.new_keys_equivalence;
+        self.pair_equivalence += other.pair_equivalence;
+        self.bad_pairs += other.bad_pairs;
+        self.triple_equivalence += other.triple_equivalence;
+        self.triples += other.triples;
+        self.new_pair_equivalence += other.new_pair_equivalence;
+        Self::add(&mut self.fingering, &other.fingering);
+        self.keys += other.keys;
+        self.travel += other.travel;
+        self.ranked_duplication += other.ranked_duplication;
+        Self::add(&mut self.first_keys, &other.first_keys);
+        Self::add(&mut self.last_keys, &other.last_keys);
+        Self::add(&mut self.levels, &other.levels);
+        Self::add(&mut self.levels_pair_equivalence, &other.levels_pair_equivalence);
+        Self::add(&mut self.tiers_duplication, &other.tiers_duplication);
+        Self::add(&mut self.tiers_coverage, &other.tiers_coverage);
+        Self::add(&mut self.tiers_frequency, &other.tiers_frequency);
+        Self::add(&mut self.tiers_length, &other.tiers_length);
+        Self::add(&mut self.tiers_pairs, &other.tiers_pairs);
+        Self::add(&mut self.tiers_pair_equivalence, &other.tiers_pair_equivalence);
+        for (x, y) in self.tiers_levels.iter_mut().zip(&other.tiers_levels) {
+            Self::add(x, y);
+        }
+        Self::add(&mut self.distribution, &other.distribution);
+        self.used_codes.extend(other.used_codes);
+        Self::add(&mut self.chuma, &other.chuma);
+        Self::add(&mut self.moma, &other.moma);
+        self
+    }
+}
+
 /// 目标函数
 impl Objective {
     /// 通过传入配置表示、编码器和共用资源来构造一个目标函数
-    pub fn new(representation: &Representation, encoder: Encoder, assets: Assets) -> Self {
+    pub fn new(
+        representation: &Representation,
+        encoder: DefaultEncoder,
+        assets: Assets,
+    ) -> Result<Self, Error> {
         let character_frequencies: Vec<_> = encoder
             .characters
             .iter()
@@ -48,23 +204,400 @@ impl Objective {
                 .map(|word| *assets.word_frequency.get(word).unwrap_or(&0))
                 .collect()
         });
-        let ideal_distribution =
-            representation.generate_ideal_distribution(&assets.key_distribution);
-        let pair_equivalence = representation.transform_pair_equivalence(&assets.pair_equivalence);
-        let new_pair_equivalence =
-            representation.transform_new_pair_equivalence(&assets.pair_equivalence);
-        Self {
+        let config = &representation.config.optimization.objective;
+        let keyboard = Keyboard::new(config.keyboard.as_ref());
+        // 上档字符没有单独的用指分布和速度当量数据时，沿用对应基础按键的数据
+        let key_distribution = keyboard.expand_keys(&assets.key_distribution);
+        let pair_table = keyboard.expand_pairs(&assets.pair_equivalence);
+        let pair_table = representation.complete_pair_equivalence(&pair_table);
+        let ideal_distribution = representation.generate_ideal_distribution(&key_distribution);
+        let mut pair_equivalence = representation.transform_pair_equivalence(&pair_table);
+        if let Some(shift) = config.keyboard.as_ref().and_then(|x| x.shift.as_ref()) {
+            let shift = representation.transform_shift(&keyboard, shift, &pair_table);
+            for (value, extra) in pair_equivalence.iter_mut().zip(shift) {
+                *value += extra;
+            }
+        }
+        let new_pair_equivalence = representation.transform_new_pair_equivalence(&pair_table);
+        let partials = [
+            &config.characters_full,
+            &config.words_full,
+            &config.characters_short,
+            &config.words_short,
+            &config.punctuation,
+            &config.traditional,
+        ];
+        for tier in partials.iter().filter_map(|x| x.as_ref()?.tiers.as_ref()).flatten() {
+            if let (Some(from), Some(top)) = (tier.from, tier.top) {
+                if from >= top {
+                    return Err(format!("层级的起点 {from} 必须小于终点 {top}").into());
+                }
+            }
+        }
+        // 三键当量表和速度当量表一样大，只在用到的时候才计算
+        let triple_equivalence = if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.triple_equivalence.is_some()))
+        {
+            if assets.triple_equivalence.is_empty() {
+                return Err("使用三键当量需要提供三键当量表 triple_equivalence.txt".into());
+            }
+            representation.transform_triple_equivalence(&assets.triple_equivalence)
+        } else {
+            vec![]
+        };
+        // 差键位表的计算方式与速度当量表相同，只是每个按键组合的值换成了用户指定的惩罚
+        let bad_pairs = if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.bad_pairs.is_some()))
+        {
+            let pairs = config
+                .bad_pairs
+                .as_ref()
+                .ok_or("使用差键位指标需要在 objective.bad_pairs 中列出差键位组合")?;
+            representation.transform_pair_equivalence(pairs)
+        } else {
+            vec![]
+        };
+        if [&config.words_full, &config.words_short]
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.dynamic_duplication.is_some()))
+        {
+            return Err("动态选重率只适用于单字全码和单字简码".into());
+        }
+        // 语料中的字转换成字在编码器中的序号，不在字表中的字直接略去
+        let corpus = if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.dynamic_duplication.is_some()))
+        {
+            if assets.corpus.is_empty() {
+                return Err("使用动态选重率需要提供语料 corpus.txt".into());
+            }
+            let index: HashMap<char, usize> =
+                zip(encoder.characters.iter().copied(), 0..).collect();
+            assets.corpus.chars().filter_map(|x| index.get(&x).copied()).collect()
+        } else {
+            vec![]
+        };
+        let corpus_items = if config.corpus.is_some() {
+            if assets.corpus.is_empty() {
+                return Err("使用语料模拟需要提供语料 corpus.txt".into());
+            }
+            let words = encoder.words.as_deref().unwrap_or_default();
+            let symbols: Vec<char> = encoder.punctuation.iter().map(|x| x.0).collect();
+            corpus::segment(&assets.corpus, &encoder.characters, words, &symbols)
+        } else {
+            vec![]
+        };
+        // 标点符号的编码是固定的，按频率从高到低排列，以便确定重码中的首选
+        let mut punctuation = vec![];
+        if config.punctuation.is_some() {
+            if encoder.punctuation.is_empty() {
+                return Err("使用标点符号的指标需要在 encoder.punctuation 或 form.mapping 中定义标点的编码".into());
+            }
+            for (symbol, code) in &encoder.punctuation {
+                let frequency = *assets.punctuation_frequency.get(symbol).unwrap_or(&0);
+                punctuation.push((*code, frequency));
+            }
+            punctuation.sort_by_key(|x| std::cmp::Reverse(x.1));
+        }
+        let punctuation_frequencies: Vec<u64> = punctuation.iter().map(|x| x.1).collect();
+        let (traditional_sequences, traditional_frequencies) = if config.traditional.is_some() {
+            if assets.traditional_frequency.is_empty() {
+                return Err("评测繁体需要提供繁体字频表 traditional_frequency.txt".into());
+            }
+            Self::traditional_sequences(&encoder, &assets)
+        } else {
+            (vec![], vec![])
+        };
+        let elements = representation.initial.len();
+        let traditional_entries = DefaultEncoder::entries_index(&traditional_sequences, elements);
+        // 指法表和速度当量表一样大，只在用到的时候才计算
+        let fingering = if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.fingering.is_some()))
+        {
+            representation.transform_fingering(&keyboard)
+        } else {
+            vec![]
+        };
+        // 移动距离表也和速度当量表一样大
+        let travel = if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.travel_distance.is_some()))
+        {
+            if assets.keyboard_geometry.is_empty() {
+                return Err("使用手指移动距离需要提供键盘几何 keyboard_geometry.txt".into());
+            }
+            representation.transform_travel(&keyboard, &assets.keyboard_geometry)
+        } else {
+            vec![]
+        };
+        let key_fingers: Vec<Option<usize>> = (0..representation.alphabet_radix)
+            .map(|x| {
+                representation
+                    .repr_key
+                    .get(&x)
+                    .and_then(|c| keyboard.finger(*c))
+            })
+            .collect();
+        // 每个按键的负荷上限：单独指定的上限、统一的上限，或者理想分布
+        let keyboard_config = config.keyboard.as_ref();
+        let key_caps: Vec<f64> = (0..representation.alphabet_radix)
+            .map(|x| {
+                let key = representation.repr_key.get(&x);
+                let cap = key
+                    .and_then(|c| keyboard_config?.caps.as_ref()?.get(c))
+                    .or(keyboard_config.and_then(|k| k.max_load.as_ref()));
+                cap.map_or(ideal_distribution[x], |cap| cap / 100.0)
+            })
+            .collect();
+        let key_rows: Vec<Option<usize>> = (0..representation.alphabet_radix)
+            .map(|x| representation.repr_key.get(&x).and_then(|c| keyboard.row(*c)))
+            .collect();
+        let key_hands: Vec<Option<Hand>> = (0..representation.alphabet_radix)
+            .map(|x| representation.repr_key.get(&x).and_then(|c| keyboard.hand(*c)))
+            .collect();
+        // 理想的手指负荷：用户指定的百分比，或者把理想的按键分布按手指汇总
+        let mut finger_targets = vec![0.0; 10];
+        match config.keyboard.as_ref().and_then(|x| x.finger_load.as_ref()) {
+            Some(load) => {
+                for (target, value) in finger_targets.iter_mut().zip(load) {
+                    *target = *value;
+                }
+            }
+            None => {
+                for (finger, frequency) in zip(&key_fingers, &ideal_distribution) {
+                    if let Some(target) = finger.and_then(|x| finger_targets.get_mut(x)) {
+                        *target += frequency;
+                    }
+                }
+            }
+        }
+        let total: f64 = finger_targets.iter().sum();
+        if total > 0.0 {
+            for target in finger_targets.iter_mut() {
+                *target /= total;
+            }
+        } else if partials
+            .iter()
+            .any(|x| x.as_ref().is_some_and(|x| x.finger_load.is_some()))
+        {
+            let path = "optimization.objective.keyboard.finger_load";
+            return Err(format!("手指负荷偏差需要理想负荷之和大于零，请检查 {path} 或键盘模型中的手指").into());
+        }
+        // 每个元素所属的组，不在任何组中的元素不参与混杂按键的统计
+        let mut element_groups = vec![None; representation.initial.len()];
+        if let Some(groups) = config.learning.as_ref().and_then(|x| x.groups.as_ref()) {
+            for (igroup, group) in groups.iter().enumerate() {
+                for name in group {
+                    if let Some(element) = representation.element_repr.get(name) {
+                        element_groups[*element] = Some(igroup);
+                    }
+                }
+            }
+        }
+        let mut custom = Vec::new();
+        for weights in config.custom.iter().flatten() {
+            let component = CustomComponent::new(weights)?;
+            let configured = match component.partial.as_str() {
+                "characters_full" => Self::enabled(&config.characters_full).is_some(),
+                "characters_short" => Self::enabled(&config.characters_short).is_some(),
+                "words_full" => Self::enabled(&config.words_full).is_some(),
+                _ => false,
+            };
+            if !configured {
+                return Err(format!(
+                    "自定义指标 {} 所评测的部分 {} 不存在或者没有在目标函数中配置",
+                    component.name, component.partial
+                )
+                .into());
+            }
+            custom.push(component);
+        }
+        let reference = match &config.reference {
+            Some(reference) => representation.read_reference(&reference.mapping)?,
+            None => vec![],
+        };
+        // 把编码还原成字符串时用的按键表，下标 0 表示空码
+        let repr_key: Vec<char> = (0..representation.radix)
+            .map(|x| *representation.repr_key.get(&x).unwrap_or(&' '))
+            .collect();
+        let mut objective = Self {
             encoder,
+            custom_encoder: None,
             config: representation.config.optimization.objective.clone(),
             character_frequencies: Self::normalize_frequencies(&character_frequencies),
-            word_frequencies: word_frequencies.as_ref().map(Self::normalize_frequencies),
+            word_frequencies: word_frequencies.as_deref().map(Self::normalize_frequencies),
             ideal_distribution,
+            key_caps,
             pair_equivalence,
             new_pair_equivalence,
+            triple_equivalence,
+            bad_pairs,
+            corpus,
+            corpus_items,
+            punctuation_codes: punctuation.iter().map(|x| x.0).collect(),
+            punctuation_frequencies: Self::normalize_frequencies(&punctuation_frequencies),
+            traditional_sequences,
+            traditional_frequencies: Self::normalize_frequencies(&traditional_frequencies),
+            traditional_entries,
+            fingering,
+            travel,
+            key_fingers,
+            key_rows,
+            key_hands,
+            finger_targets,
+            element_groups,
+            reference,
+            reference_frequencies: vec![],
+            soft_constraints: Constraints::soft(representation)?,
+            custom,
+            repr_key,
+            scales: vec![],
+        };
+        // 元素的频率与布局无关，所以参考布局用到的元素频率只需计算一次
+        if !objective.reference.is_empty() {
+            objective.reference_frequencies =
+                objective.element_frequencies(representation.initial.len());
+        }
+        // 自定义脚本的输入中，字词和频率也与布局无关，只需构造一次
+        let mut custom = std::mem::take(&mut objective.custom);
+        for component in custom.iter_mut() {
+            if component.partial == "words_full" {
+                let words = objective.encoder.words.clone().unwrap_or_default();
+                let frequencies = objective.word_frequencies.as_deref().unwrap_or_default();
+                component.prepare(words, frequencies);
+            } else {
+                let characters = objective.encoder.characters.iter().map(|x| x.to_string());
+                component.prepare(characters.collect(), &objective.character_frequencies);
+            }
+        }
+        objective.custom = custom;
+        if let Some(normalization) = config.normalization {
+            objective.calibrate(normalization, &representation.initial)?;
         }
+        Ok(objective)
     }
 
-    fn normalize_frequencies(occurrences: &Vec<u64>) -> Frequencies {
+    /// 用自定义的编码器代替默认编码器计算单字全码。字表、词语全码、简码和各项指标仍然沿用默认编码器，
+    /// 标准分归一化的基准也是用默认编码器得到的
+    pub fn with_encoder(mut self, encoder: Arc<dyn Encoder>) -> Self {
+        self.custom_encoder = Some(encoder);
+        self
+    }
+
+    /// 把编码转换为按键组成的字符串，作为自定义脚本的输入
+    fn custom_code(&self, code: Code) -> String {
+        let mut chars = String::new();
+        let mut remainder = code;
+        while remainder > 0 {
+            let key = remainder % self.encoder.radix;
+            if key > 0 {
+                chars.push(self.repr_key[key]);
+            }
+            remainder /= self.encoder.radix;
+        }
+        chars
+    }
+
+    /// 计算布局的学习成本
+    fn evaluate_learning(
+        &self,
+        candidate: &KeyMap,
+        weights: &LearningWeights,
+        loss: &mut Loss,
+    ) -> LearningMetric {
+        let radix = self.encoder.alphabet_radix;
+        let mut counts = vec![0_usize; radix];
+        let mut groups: Vec<Vec<usize>> = vec![vec![]; radix];
+        for (element, key) in candidate.iter().enumerate() {
+            if *key >= radix {
+                continue;
+            }
+            counts[*key] += 1;
+            if let Some(group) = self.element_groups[element] {
+                if !groups[*key].contains(&group) {
+                    groups[*key].push(group);
+                }
+            }
+        }
+        // 只统计字母表中的按键，下标 0 不对应任何按键
+        let counts = &counts[1..];
+        let mut metric = LearningMetric {
+            max_elements: None,
+            variance: None,
+            unrelated: None,
+            overload: None,
+        };
+        if let Some(weight) = weights.max_elements {
+            let max_elements = counts.iter().copied().max().unwrap_or(0);
+            metric.max_elements = Some(max_elements);
+            loss.add("单键最多元素", max_elements as f64, weight);
+        }
+        if let Some(weight) = weights.variance {
+            let mean = counts.iter().sum::<usize>() as f64 / counts.len() as f64;
+            let variance = counts
+                .iter()
+                .map(|x| (*x as f64 - mean).powi(2))
+                .sum::<f64>()
+                / counts.len() as f64;
+            metric.variance = Some(variance);
+            loss.add("元素数方差", variance, weight);
+        }
+        if let Some(weight) = weights.unrelated {
+            let unrelated = groups.iter().filter(|x| x.len() > 1).count();
+            metric.unrelated = Some(unrelated);
+            loss.add("混杂按键", unrelated as f64, weight);
+        }
+        if let Some(overload) = &weights.overload {
+            let excess: usize = counts.iter().map(|x| x.saturating_sub(overload.limit)).sum();
+            metric.overload = Some(excess);
+            loss.add("超限元素", excess as f64, overload.weight);
+        }
+        metric
+    }
+
+    /// 配置了并且没有被关闭的部分
+    fn enabled(weights: &Option<PartialWeights>) -> Option<&PartialWeights> {
+        weights.as_ref().filter(|x| x.enabled.unwrap_or(true))
+    }
+
+    /// 每个元素的频率，即所有用到这个元素的字的频率之和
+    pub fn element_frequencies(&self, elements: usize) -> Vec<f64> {
+        self.encoder
+            .element_frequencies(&self.character_frequencies, elements)
+    }
+
+    /// 繁体字的拆分序列和频率，按繁体字频从高到低排列。拆分表中没有的繁体字借用对应简体字的拆分，
+    /// 两者都没有的字无法输入，不参与评测
+    fn traditional_sequences(
+        encoder: &DefaultEncoder,
+        assets: &Assets,
+    ) -> (Vec<Sequence>, Vec<u64>) {
+        let sequences: HashMap<char, &Sequence> =
+            zip(&encoder.characters, encoder.characters_sequence()).map(|(x, y)| (*x, y)).collect();
+        let mut simplified: HashMap<char, char> = HashMap::new();
+        for (character, variants) in &assets.variants {
+            for variant in variants {
+                simplified.entry(*variant).or_insert(*character);
+            }
+        }
+        let mut characters: Vec<(char, u64)> =
+            assets.traditional_frequency.iter().map(|(x, y)| (*x, *y)).collect();
+        characters.sort_by_key(|x| (std::cmp::Reverse(x.1), x.0));
+        characters
+            .into_iter()
+            .filter_map(|(character, frequency)| {
+                let sequence = sequences
+                    .get(&character)
+                    .or_else(|| sequences.get(simplified.get(&character)?))?;
+                Some(((*sequence).clone(), frequency))
+            })
+            .unzip()
+    }
+
+    fn normalize_frequencies(occurrences: &[u64]) -> Frequencies {
         let total_occurrences: u64 = occurrences.iter().sum();
         occurrences
             .iter()
@@ -72,96 +605,407 @@ impl Objective {
             .collect()
     }
 
-    fn get_distribution_distance(
+    fn get_distribution_distance(&self, distribution: &[f64], loss: DistributionLoss) -> f64 {
+        let pairs = zip(distribution, &self.ideal_distribution);
+        match loss {
+            DistributionLoss::Excess => pairs.map(|(x, y)| (x - y).max(0.0)).sum(),
+            DistributionLoss::KlDivergence => pairs
+                .filter(|(x, y)| **x > 0.0 && **y > 0.0)
+                .map(|(x, y)| x * (x / y).ln())
+                .sum(),
+            DistributionLoss::Squared => pairs.map(|(x, y)| (x - y).powi(2)).sum(),
+            DistributionLoss::Hinge => zip(distribution, &self.key_caps)
+                .map(|(x, cap)| (x - cap).max(0.0))
+                .sum(),
+        }
+    }
+
+    /// 弱指惩罚：使用率不超过阈值时就是使用率本身，超过之后按幂次增长，在阈值处连续
+    fn weak_finger_penalty(usage: f64, term: &WeakFingerTerm, exponent: f64) -> f64 {
+        match term.threshold.map(|x| x / 100.0) {
+            Some(threshold) if usage > threshold && threshold > 0.0 => {
+                threshold * (usage / threshold).powf(exponent)
+            }
+            _ => usage,
+        }
+    }
+
+    /// 第 rank 个候选（首选为 0）实际的编码。全码中统一添加的是首选键，把它替换成第 rank 个选择键，
+    /// 超出选择键数量的候选都按最后一个选择键计算；自动上屏的编码没有选择键，选重时要在末尾补上。
+    /// 补上之后超出编码空间的，返回原编码以及末码与选择键组成的二键编码，由调用者单独计算
+    fn select_code(&self, code: Code, rank: usize) -> (Code, Option<Code>) {
+        let select_keys = &self.encoder.select_keys;
+        let radix = self.encoder.radix;
+        let key = select_keys.get(rank).or(select_keys.last()).unwrap(); // 选择键不能为空
+        let length = code.ilog(radix);
+        let weight = radix.pow(length);
+        if code / weight == select_keys[0] {
+            return (code - select_keys[0] * weight + key * weight, None);
+        }
+        if length + 1 < self.encoder.get_space().ilog(radix) {
+            (code + key * weight * radix, None)
+        } else {
+            (code, Some(code / weight + key * radix))
+        }
+    }
+
+    /// 编码中编码键的个数，不计选择键
+    fn count_alphabet_keys(&self, code: Code) -> usize {
+        let mut count = 0;
+        let mut remainder = code;
+        while remainder > 0 {
+            let key = remainder % self.encoder.radix;
+            if key > 0 && key < self.encoder.alphabet_radix {
+                count += 1;
+            }
+            remainder /= self.encoder.radix;
+        }
+        count
+    }
+
+    /// 语料模拟：按切分好的语料逐项输入，累计击键数、组合当量和选重。相邻两项之间前一项的末码和后一项的首码
+    /// 也构成一个按键组合，计入组合当量；遇到中断时不计
+    fn evaluate_corpus(
         &self,
-        distribution: &Vec<f64>,
-        ideal_distribution: &Vec<f64>,
-    ) -> f64 {
-        let mut distance = 0.0;
-        for (frequency, ideal_frequency) in zip(distribution, ideal_distribution) {
-            if frequency > ideal_frequency {
-                distance += frequency - ideal_frequency;
+        buffer: &Buffer,
+        weights: &CorpusWeights,
+        loss: &mut Loss,
+    ) -> CorpusMetric {
+        let characters = buffer.characters_short.as_ref().unwrap_or(&buffer.characters_full);
+        let radix = self.encoder.radix;
+        let mut total_characters = 0;
+        let mut total_items = 0;
+        let mut total_keys = 0;
+        let mut total_pairs = 0;
+        let mut total_duplication = 0;
+        let mut total_pair_equivalence = 0.0;
+        let mut last_key: Option<usize> = None;
+        // 同手连击：当前连击的手和长度，以及各种长度的连击出现的次数
+        let mut run_hand: Option<Hand> = None;
+        let mut run_length = 0;
+        let mut runs: Vec<usize> = vec![];
+        for item in &self.corpus_items {
+            let ((code, duplicated), length) = match item {
+                CorpusItem::Character(index) => (characters[*index], 1),
+                CorpusItem::Symbol(index) => ((self.encoder.punctuation[*index].1, false), 1),
+                CorpusItem::Word { index, length } => match &buffer.words_full {
+                    Some(words) => (words[*index], *length),
+                    None => continue,
+                },
+                CorpusItem::Break => {
+                    last_key = None;
+                    Self::finish_run(&mut runs, &mut run_length);
+                    run_hand = None;
+                    continue;
+                }
+            };
+            let keys = code.ilog(radix) as usize + 1;
+            total_characters += length;
+            total_items += 1;
+            total_keys += keys;
+            total_pairs += keys - 1;
+            total_pair_equivalence += self.pair_equivalence[code];
+            if let Some(last) = last_key {
+                total_pairs += 1;
+                total_pair_equivalence += self.pair_equivalence[last + (code % radix) * radix];
+            }
+            if duplicated {
+                total_duplication += 1;
             }
+            last_key = Some(code / radix.pow(keys as u32 - 1));
+            if weights.same_hand_runs.is_some() {
+                let mut rest = code;
+                while rest > 0 {
+                    let hand = self.key_hands.get(rest % radix).copied().flatten();
+                    rest /= radix;
+                    if hand.is_some() && hand == run_hand {
+                        run_length += 1;
+                    } else {
+                        // 不在键盘模型中的按键（例如选择键）也会打断连击
+                        Self::finish_run(&mut runs, &mut run_length);
+                        run_hand = hand;
+                        run_length = usize::from(hand.is_some());
+                    }
+                }
+            }
+        }
+        Self::finish_run(&mut runs, &mut run_length);
+        let mut metric = CorpusMetric {
+            keys: None,
+            pair_equivalence: None,
+            duplication: None,
+            same_hand_runs: None,
+            same_hand_run_distribution: None,
+        };
+        if let Some(weight) = weights.keys {
+            let keys = total_keys as f64 / total_characters.max(1) as f64;
+            metric.keys = Some(keys);
+            loss.add("字均键数", keys, weight);
+        }
+        if let Some(weight) = weights.pair_equivalence {
+            let equivalence = total_pair_equivalence / total_pairs.max(1) as f64;
+            metric.pair_equivalence = Some(equivalence);
+            loss.add("组合当量", equivalence, weight);
         }
-        distance
+        if let Some(weight) = weights.duplication {
+            let duplication = total_duplication as f64 / total_items.max(1) as f64;
+            metric.duplication = Some(duplication);
+            loss.add("选重率", duplication, weight);
+        }
+        if let Some(same_hand_runs) = &weights.same_hand_runs {
+            // 超过阈值的部分按击键数计入惩罚，再除以总击键数
+            let threshold = same_hand_runs.threshold.unwrap_or(2);
+            let excess: usize = runs
+                .iter()
+                .enumerate()
+                .map(|(length, count)| length.saturating_sub(threshold) * count)
+                .sum();
+            let penalty = excess as f64 / total_keys.max(1) as f64;
+            let total_runs = runs.iter().sum::<usize>().max(1) as f64;
+            let mut distribution = vec![0.0; MAX_RUN_LENGTH];
+            for (length, count) in runs.iter().enumerate().skip(1) {
+                distribution[length.min(MAX_RUN_LENGTH) - 1] += *count as f64 / total_runs;
+            }
+            metric.same_hand_runs = Some(penalty);
+            metric.same_hand_run_distribution = Some(distribution);
+            loss.add("同手连击", penalty, same_hand_runs.weight);
+        }
+        metric
     }
 
-    /// 计算一部分编码的指标，这里的部分可以是单字全码、单字简码、词语全码或词语简码
-    pub fn evaluate_partial(
+    /// 结束当前的同手连击，把它的长度记入连击次数表
+    fn finish_run(runs: &mut Vec<usize>, run_length: &mut usize) {
+        if *run_length > 0 {
+            if runs.len() <= *run_length {
+                runs.resize(*run_length + 1, 0);
+            }
+            runs[*run_length] += 1;
+        }
+        *run_length = 0;
+    }
+
+    /// 动态选重率：按语料逐字输入，模拟输入法把最近用过的候选调到首位。重码的候选一开始按照字频排序，
+    /// 每打一个字就把它移到所在候选列表的最前面，返回语料中不在首位、需要选重的字所占的比例
+    fn dynamic_duplication(&self, codes: &Codes) -> f64 {
+        let mut group_index: HashMap<Code, usize> = HashMap::new();
+        let mut groups: Vec<Vec<usize>> = vec![];
+        for (index, (code, _)) in codes.iter().enumerate() {
+            let group = *group_index.entry(*code).or_insert_with(|| {
+                groups.push(vec![]);
+                groups.len() - 1
+            });
+            groups[group].push(index);
+        }
+        let mut selections = 0;
+        for index in &self.corpus {
+            let candidates = &mut groups[group_index[&codes[*index].0]];
+            if candidates[0] != *index {
+                selections += 1;
+                let position = candidates.iter().position(|x| x == index).unwrap();
+                candidates[..=position].rotate_right(1);
+            }
+        }
+        selections as f64 / self.corpus.len().max(1) as f64
+    }
+
+    /// 出简不出全冲突：简码不同于自身全码，却与另一个字的全码相同的字。全码的占用情况已经记录在
+    /// occupation 中，而简码不同于自身全码时，占用这个全码的只能是别的字
+    fn short_full_conflict(
         &self,
-        codes: &Codes,
-        frequencies: &Frequencies,
+        full_codes: &Codes,
+        short_codes: &Codes,
+        occupation: &Occupation,
+    ) -> ConflictMetric {
+        let mut conflict = ConflictMetric {
+            count: 0,
+            frequency: 0.0,
+        };
+        let entries = zip(zip(full_codes, short_codes), &self.character_frequencies);
+        for (((full, _), (short, _)), frequency) in entries {
+            if short != full && occupation.get(*short) == Some(&true) {
+                conflict.count += 1;
+                conflict.frequency += frequency;
+            }
+        }
+        conflict
+    }
+
+    /// 按频率排序的第 index 个字词是否在某个层级的统计范围内
+    fn in_tier(tier: &TierWeights, index: usize) -> bool {
+        index >= tier.from.unwrap_or(0) && index < tier.top.unwrap_or(usize::MAX)
+    }
+
+    /// 重复编码在候选中的位置：没有重码标记的是首选（记为 0），重码依次为 1、2……
+    fn duplicate_ranks(codes: &[(Code, bool)]) -> Vec<usize> {
+        let mut ranks: HashMap<Code, usize> = HashMap::new();
+        codes
+            .iter()
+            .map(|(code, duplicated)| {
+                if !*duplicated {
+                    return 0;
+                }
+                let rank = ranks.entry(*code).or_insert(0);
+                *rank += 1;
+                *rank
+            })
+            .collect()
+    }
+
+    /// 逐项累计从 start 开始的一段字词的各项指标，ranks 为所有字词在候选中的位置
+    fn accumulate_partial(
+        &self,
+        codes: &[(Code, bool)],
+        start: usize,
+        frequencies: &[f64],
+        ranks: Option<&[usize]>,
         weights: &PartialWeights,
-    ) -> (PartialMetric, f64) {
-        // 初始化整体指标的变量
-        let mut total_duplication = 0.0;
-        let mut total_pairs = 0.0;
-        // 新当量以键数为单位
-        let mut total_new_keys = 0.0;
-        let mut total_new_keys_equivalence = 0.0;
-        let mut total_new_keys_equivalence_modified = 0.0;
-        let mut total_pair_equivalence = 0.0;
-        let mut total_new_pair_equivalence = 0.0;
-        let mut total_levels = vec![0.0; weights.levels.as_ref().unwrap_or(&vec![]).len()];
-        // 初始化分级指标的变量
+    ) -> PartialTotals {
+        // 左右互击还要统计相邻两个字词之间的击键，为此记录首码和末码的频率
+        let alternation = weights
+            .fingering
+            .as_ref()
+            .is_some_and(|x| x.hand_alternation.is_some());
+        let radix = self.encoder.radix;
         let ntier = weights.tiers.as_ref().map_or(0, |v| v.len());
-        let mut tiers_duplication = vec![0; ntier];
-        let mut tiers_levels: Vec<Vec<usize>> = vec![];
-        if let Some(tiers) = &weights.tiers {
-            for tier in tiers {
-                let vec = vec![0_usize; tier.levels.as_ref().map_or(0, |v| v.len())];
-                tiers_levels.push(vec);
-            }
-        }
-        let mut distribution = vec![0.0; self.encoder.alphabet_radix];
-        // 标记初始字符、结束字符的频率
-        let mut chuma = vec![0 as f64; self.encoder.radix];
-        let mut moma = vec![0.0 as f64; self.encoder.radix];
-        for (index, ((code, duplicated), frequency)) in zip(codes, frequencies).enumerate() {
-            let length = code.ilog(self.encoder.radix) as usize + 1;
+        let levels = weights.levels.as_ref().map_or(0, |v| v.len());
+        let mut t = PartialTotals {
+            duplication: 0.0,
+            pairs: 0.0,
+            new_keys: 0.0,
+            new_keys_equivalence: 0.0,
+            pair_equivalence: 0.0,
+            bad_pairs: 0.0,
+            triple_equivalence: 0.0,
+            triples: 0.0,
+            new_pair_equivalence: 0.0,
+            fingering: [0.0; FINGERING_TYPES],
+            keys: 0.0,
+            travel: 0.0,
+            ranked_duplication: 0.0,
+            first_keys: vec![0.0; if alternation { radix } else { 0 }],
+            last_keys: vec![0.0; if alternation { radix } else { 0 }],
+            levels: vec![0.0; levels],
+            levels_pair_equivalence: vec![0.0; levels],
+            tiers_duplication: vec![0; ntier],
+            tiers_coverage: vec![0; ntier],
+            tiers_frequency: vec![0.0; ntier],
+            tiers_length: vec![0.0; ntier],
+            tiers_pairs: vec![0.0; ntier],
+            tiers_pair_equivalence: vec![0.0; ntier],
+            tiers_levels: weights.tiers.as_ref().map_or(vec![], |tiers| {
+                let levels = |x: &TierWeights| x.levels.as_ref().map_or(0, |v| v.len());
+                tiers.iter().map(|x| vec![0; levels(x)]).collect()
+            }),
+            distribution: vec![0.0; self.encoder.alphabet_radix],
+            used_codes: HashSet::new(),
+            chuma: vec![0.0; radix],
+            moma: vec![0.0; radix],
+        };
+        // 理想码长：按频率顺序把字词依次填入最短的编码，第 index 个字词能得到的码长
+        let keys = self.encoder.alphabet_radix - 1;
+        let mut ideal_length = 1;
+        let mut ideal_capacity = keys;
+        for (offset, ((code, duplicated), frequency)) in zip(codes, frequencies).enumerate() {
+            let index = start + offset;
+            // 重码在候选中的位置，首选为 0
+            let rank = match ranks {
+                Some(ranks) if *duplicated => ranks[index],
+                _ => 0,
+            };
+            // 按照实际所用的选择键计算当量和指法
+            let (code, extra) = if rank > 0 && weights.select_keys == Some(true) {
+                self.select_code(*code, rank)
+            } else {
+                (*code, None)
+            };
+            let code = &code;
+            let length = code.ilog(self.encoder.radix) as usize + 1 + usize::from(extra.is_some());
+            let pair_equivalence =
+                self.pair_equivalence[*code] + extra.map_or(0.0, |x| self.pair_equivalence[x]);
+            if weights.utilization.is_some() {
+                t.used_codes.insert(*code);
+            }
             // 按键分布
-            if weights.key_distribution.is_some() {
+            if weights.key_distribution.is_some()
+                || weights.finger_load.is_some()
+                || weights.weak_fingers.is_some()
+            {
                 let mut current = *code;
                 while current > 0 {
                     let key = current % self.encoder.radix;
-                    if key < distribution.len() {
-                        distribution[key] += *frequency;
+                    if key < t.distribution.len() {
+                        t.distribution[key] += *frequency;
                     }
                     current /= self.encoder.radix;
                 }
             }
             // 杏码式用指当量，只统计最初的1码
-            if let Some(_) = weights.new_key_equivalence {
-                total_new_keys_equivalence +=
+            if weights.new_key_equivalence.is_some() {
+                t.new_keys_equivalence +=
                     *frequency / self.ideal_distribution[*code % self.encoder.radix];
             }
             // 杏码式用指当量改
-            if let Some(_) = weights.new_key_equivalence_modified {
+            if weights.new_key_equivalence_modified.is_some() {
                 //取得首末码
                 let codefirst = *code % self.encoder.radix;
                 let mut codelast = *code;
                 while codelast > self.encoder.radix {
                     codelast /= self.encoder.radix;
                 }
-                chuma[codefirst] = chuma[codefirst] + *frequency;
-                moma[codelast] = moma[codelast] + *frequency;
+                t.chuma[codefirst] += *frequency;
+                t.moma[codelast] += *frequency;
+            }
+            t.pairs += (length - 1) as f64 * frequency;
+            t.keys += length as f64 * frequency;
+            if weights.travel_distance.is_some() {
+                t.travel += self.travel[*code] * *frequency;
+            }
+            if weights.pair_equivalence.is_some() {
+                t.pair_equivalence += pair_equivalence * *frequency;
+            }
+            if weights.bad_pairs.is_some() {
+                let extra_bad_pairs = extra.map_or(0.0, |x| self.bad_pairs[x]);
+                t.bad_pairs += (self.bad_pairs[*code] + extra_bad_pairs) * *frequency;
             }
-            if let Some(_) = weights.pair_equivalence {
-                total_pair_equivalence += self.pair_equivalence[*code] * *frequency;
-                total_pairs += (length - 1) as f64 * frequency;
+            if weights.triple_equivalence.is_some() && length >= 3 {
+                t.triple_equivalence += self.triple_equivalence[*code] * *frequency;
+                t.triples += (length - 2) as f64 * frequency;
+            }
+            // 指法
+            if weights.fingering.is_some() {
+                for (total, count) in t.fingering.iter_mut().zip(self.fingering[*code]) {
+                    *total += count as f64 * frequency;
+                }
+                if let Some(extra) = extra {
+                    for (total, count) in t.fingering.iter_mut().zip(self.fingering[extra]) {
+                        *total += count as f64 * frequency;
+                    }
+                }
+                if alternation {
+                    let mut last = *code;
+                    while last >= self.encoder.radix {
+                        last /= self.encoder.radix;
+                    }
+                    t.first_keys[*code % self.encoder.radix] += *frequency;
+                    t.last_keys[last] += *frequency;
+                }
             }
-            if let Some(_) = weights.new_pair_equivalence {
-                total_new_pair_equivalence += self.new_pair_equivalence[*code] * *frequency;
-                total_new_keys += length as f64 * frequency;
+            if weights.new_pair_equivalence.is_some() {
+                t.new_pair_equivalence += self.new_pair_equivalence[*code] * *frequency;
+                t.new_keys += length as f64 * frequency;
             }
             // 重码
             if *duplicated {
-                total_duplication += frequency;
+                t.duplication += frequency;
+                if let Some(ranked) = &weights.ranked_duplication {
+                    // 第一个重码是第二候选，对应代价列表的第 0 项
+                    let cost = ranked.costs.get(rank - 1).or(ranked.costs.last());
+                    t.ranked_duplication += cost.unwrap_or(&0.0) * frequency;
+                }
                 if let Some(tiers) = &weights.tiers {
                     for (itier, tier) in tiers.iter().enumerate() {
-                        let top = tier.top.unwrap_or(std::usize::MAX);
-                        if index < top {
-                            tiers_duplication[itier] += 1;
+                        if Self::in_tier(tier, index) {
+                            t.tiers_duplication[itier] += 1;
                         }
                     }
                 }
@@ -170,32 +1014,116 @@ impl Objective {
             if let Some(levels) = &weights.levels {
                 for (ilevel, level) in levels.iter().enumerate() {
                     if level.length == length {
-                        total_levels[ilevel] += frequency;
+                        t.levels[ilevel] += frequency;
+                        t.levels_pair_equivalence[ilevel] +=
+                            pair_equivalence * *frequency;
                     }
                 }
             }
             // 分级指标
             if let Some(tiers) = &weights.tiers {
+                while index >= ideal_capacity && keys > 1 {
+                    ideal_length += 1;
+                    ideal_capacity =
+                        ideal_capacity.saturating_add(keys.saturating_pow(ideal_length));
+                }
+                // 覆盖率只看编码键，不计选择键
+                let covered =
+                    !*duplicated && self.count_alphabet_keys(*code) <= ideal_length as usize;
                 for (itier, tier) in tiers.iter().enumerate() {
-                    let top = tier.top.unwrap_or(std::usize::MAX);
-                    if index < top {
-                        if let Some(levels) = &tier.levels {
-                            for (ilevel, level) in levels.iter().enumerate() {
-                                if level.length == length {
-                                    tiers_levels[itier][ilevel] += 1;
-                                }
+                    if !Self::in_tier(tier, index) {
+                        continue;
+                    }
+                    if covered {
+                        t.tiers_coverage[itier] += 1;
+                    }
+                    t.tiers_frequency[itier] += frequency;
+                    t.tiers_length[itier] += length as f64 * frequency;
+                    if tier.pair_equivalence.is_some() {
+                        t.tiers_pairs[itier] += (length - 1) as f64 * frequency;
+                        t.tiers_pair_equivalence[itier] += pair_equivalence * *frequency;
+                    }
+                    if let Some(levels) = &tier.levels {
+                        for (ilevel, level) in levels.iter().enumerate() {
+                            if level.length == length {
+                                t.tiers_levels[itier][ilevel] += 1;
                             }
                         }
                     }
                 }
             }
         }
-        if let Some(_) = weights.new_key_equivalence_modified {
+        t
+    }
+
+    /// 计算一部分编码的指标，这里的部分可以是单字全码、单字简码、词语全码或词语简码
+    pub fn evaluate_partial(
+        &self,
+        codes: &Codes,
+        frequencies: &Frequencies,
+        weights: &PartialWeights,
+        loss: &mut Loss,
+    ) -> PartialMetric {
+        // 重码在候选中的位置取决于排在前面的字词，所以先按顺序求出来，之后各项可以独立地累计
+        let track_ranks =
+            weights.ranked_duplication.is_some() || weights.select_keys == Some(true);
+        let ranks = track_ranks.then(|| Self::duplicate_ranks(codes));
+        let ranks = ranks.as_deref();
+        // 设置了编码线程数时分块并行累计，然后按顺序合并各块的结果，保证同样的输入得到同样的结果
+        let totals = match self.encoder.pool() {
+            Some(pool) => pool.install(|| {
+                let chunks: Vec<PartialTotals> = codes
+                    .par_chunks(PARALLEL_CHUNK)
+                    .enumerate()
+                    .map(|(chunk, codes)| {
+                        let start = chunk * PARALLEL_CHUNK;
+                        let frequencies = &frequencies[start..];
+                        self.accumulate_partial(codes, start, frequencies, ranks, weights)
+                    })
+                    .collect();
+                let empty = || self.accumulate_partial(&[], 0, &[], None, weights);
+                chunks.into_iter().reduce(PartialTotals::merge).unwrap_or_else(empty)
+            }),
+            None => self.accumulate_partial(codes, 0, frequencies, ranks, weights),
+        };
+        let PartialTotals {
+            duplication: total_duplication,
+            pairs: total_pairs,
+            new_keys: total_new_keys,
+            new_keys_equivalence: total_new_keys_equivalence,
+            pair_equivalence: total_pair_equivalence,
+            bad_pairs: total_bad_pairs,
+            triple_equivalence: total_triple_equivalence,
+            triples: total_triples,
+            new_pair_equivalence: total_new_pair_equivalence,
+            fingering: total_fingering,
+            keys: total_keys,
+            travel: total_travel,
+            ranked_duplication: total_ranked_duplication,
+            first_keys,
+            last_keys,
+            levels: total_levels,
+            levels_pair_equivalence: total_levels_pair_equivalence,
+            tiers_duplication,
+            tiers_coverage,
+            tiers_frequency,
+            tiers_length,
+            tiers_pairs,
+            tiers_pair_equivalence,
+            tiers_levels,
+            mut distribution,
+            used_codes,
+            chuma,
+            moma,
+        } = totals;
+        let mut total_new_keys_equivalence_modified = 0.0;
+        let keys = self.encoder.alphabet_radix - 1;
+        if weights.new_key_equivalence_modified.is_some() {
             //将首末码与全局的首末码频率拼起来
-            for i in 0..self.encoder.radix {
-                for j in 0..self.encoder.radix {
+            for (i, first) in chuma.iter().enumerate() {
+                for (j, last) in moma.iter().enumerate() {
                     total_new_keys_equivalence_modified +=
-                        self.pair_equivalence[j + i * self.encoder.radix] * chuma[i] * moma[j];
+                        self.pair_equivalence[j + i * self.encoder.radix] * first * last;
                 }
             }
         }
@@ -205,55 +1133,247 @@ impl Objective {
             new_key_equivalence: None,
             new_key_equivalence_modified: None,
             pair_equivalence: None,
+            bad_pairs: None,
+            triple_equivalence: None,
             new_pair_equivalence: None,
             fingering: None,
+            finger_load: None,
+            travel_distance: None,
+            weak_fingers: None,
+            utilization: None,
+            short_full_conflict: None,
+            dynamic_duplication: None,
             duplication: None,
+            ranked_duplication: None,
             levels: None,
         };
 
-        let mut loss = 0.0;
+        if let Some(finger_load_weight) = weights.finger_load {
+            // 把按键分布按手指汇总，然后计算与理想负荷的平方偏差之和
+            let mut load = vec![0.0; self.finger_targets.len()];
+            for (finger, frequency) in zip(&self.key_fingers, &distribution) {
+                if let Some(finger) = finger {
+                    if *finger < load.len() {
+                        load[*finger] += frequency;
+                    }
+                }
+            }
+            let total: f64 = load.iter().sum();
+            let deviation: f64 = zip(&load, &self.finger_targets)
+                .map(|(x, y)| (x / total - y).powi(2))
+                .sum();
+            partial_metric.finger_load = Some(deviation);
+            loss.add("手指负荷偏差", deviation, finger_load_weight);
+        }
+        if let Some(travel_weight) = weights.travel_distance {
+            let travel = total_travel / total_keys;
+            partial_metric.travel_distance = Some(travel);
+            loss.add("移动距离", travel, travel_weight);
+        }
+        if let Some(weak_weights) = &weights.weak_fingers {
+            // 小指为 0 和 9 号手指，无名指为 1 和 8 号手指，下排为 3 号排。没有任何按键时各项比例都是 0
+            let total: f64 = distribution.iter().sum::<f64>().max(f64::EPSILON);
+            let (mut pinky, mut ring, mut bottom_pinky) = (0.0, 0.0, 0.0);
+            let keys = zip(&self.key_fingers, &self.key_rows);
+            for ((finger, row), frequency) in zip(keys, &distribution) {
+                match finger {
+                    Some(0) | Some(9) => {
+                        pinky += frequency / total;
+                        if *row == Some(3) {
+                            bottom_pinky += frequency / total;
+                        }
+                    }
+                    Some(1) | Some(8) => ring += frequency / total,
+                    _ => {}
+                }
+            }
+            let exponent = weak_weights.exponent.unwrap_or(2.0);
+            let mut metric = WeakFingerMetric {
+                pinky: None,
+                ring: None,
+                bottom_pinky: None,
+            };
+            let components = [
+                ("小指", &weak_weights.pinky, pinky, &mut metric.pinky),
+                ("无名指", &weak_weights.ring, ring, &mut metric.ring),
+                (
+                    "下排小指",
+                    &weak_weights.bottom_pinky,
+                    bottom_pinky,
+                    &mut metric.bottom_pinky,
+                ),
+            ];
+            for (label, term, usage, value) in components {
+                if let Some(term) = term {
+                    *value = Some(usage);
+                    loss.add(label, Self::weak_finger_penalty(usage, term, exponent), term.weight);
+                }
+            }
+            partial_metric.weak_fingers = Some(metric);
+        }
+        if let Some(utilization_weight) = weights.utilization {
+            // 按码长统计用过的码位，可用码位不超过字词总数，否则长码的码位永远无法用满
+            let mut used = vec![];
+            for code in &used_codes {
+                let length = code.ilog(self.encoder.radix) as usize + 1;
+                if used.len() < length {
+                    used.resize(length, 0);
+                }
+                used[length - 1] += 1;
+            }
+            let mut utilization = vec![];
+            let mut waste = 0.0;
+            for (index, used) in used.into_iter().enumerate() {
+                let length = index + 1;
+                let capacity = keys.saturating_pow(length as u32).min(codes.len());
+                waste += 1.0 - used as f64 / capacity as f64;
+                utilization.push(UtilizationMetric {
+                    length,
+                    used,
+                    capacity,
+                });
+            }
+            if !utilization.is_empty() {
+                waste /= utilization.len() as f64;
+            }
+            partial_metric.utilization = Some(utilization);
+            loss.add("码位浪费", waste, utilization_weight);
+        }
         if let Some(key_distribution_weight) = weights.key_distribution {
             // 首先归一化
             let total: f64 = distribution.iter().sum();
             for i in distribution.iter_mut() {
                 *i /= total;
             }
-            let distance = self.get_distribution_distance(&distribution, &self.ideal_distribution);
+            let distance = self.get_distribution_distance(
+                &distribution,
+                weights.key_distribution_loss.unwrap_or_default(),
+            );
             partial_metric.key_distribution = Some(distance);
-            loss += distance * key_distribution_weight;
+            loss.add("用指分布偏差", distance, key_distribution_weight);
         }
         if let Some(equivalence_weight) = weights.new_key_equivalence {
             let equivalence = total_new_keys_equivalence / total_new_keys;
             partial_metric.new_key_equivalence = Some(equivalence);
-            loss += equivalence * equivalence_weight;
+            loss.add("杏码式用指当量", equivalence, equivalence_weight);
         }
         if let Some(equivalence_weight) = weights.new_key_equivalence_modified {
             let equivalence = total_new_keys_equivalence_modified / total_new_keys;
             partial_metric.new_key_equivalence_modified = Some(equivalence);
-            loss += equivalence * equivalence_weight;
+            loss.add("杏码式用指当量改", equivalence, equivalence_weight);
         }
         if let Some(equivalence_weight) = weights.pair_equivalence {
             let equivalence = total_pair_equivalence / total_pairs;
             partial_metric.pair_equivalence = Some(equivalence);
-            loss += equivalence * equivalence_weight;
+            loss.add("组合当量", equivalence, equivalence_weight);
+        }
+        if let Some(bad_pairs_weight) = weights.bad_pairs {
+            let bad_pairs = total_bad_pairs / total_pairs;
+            partial_metric.bad_pairs = Some(bad_pairs);
+            loss.add("差键位", bad_pairs, bad_pairs_weight);
+        }
+        if let Some(equivalence_weight) = weights.triple_equivalence {
+            let equivalence = total_triple_equivalence / total_triples;
+            partial_metric.triple_equivalence = Some(equivalence);
+            loss.add("三键当量", equivalence, equivalence_weight);
         }
         if let Some(equivalence_weight) = weights.new_pair_equivalence {
             let equivalence = total_new_pair_equivalence / total_new_keys;
             partial_metric.new_pair_equivalence = Some(equivalence);
-            loss += equivalence * equivalence_weight;
+            loss.add("杏码式组合当量", equivalence, equivalence_weight);
+        }
+        if let Some(fingering_weight) = &weights.fingering {
+            let mut fingering = FingeringMetric {
+                same_finger: None,
+                same_hand: None,
+                same_finger_large_jump: None,
+                same_finger_small_jump: None,
+                little_finger_inteference: None,
+                awkward_upside_down: None,
+                hand_alternation: None,
+                row_jump: None,
+            };
+            let components = [
+                (
+                    "同指",
+                    fingering_weight.same_finger,
+                    &mut fingering.same_finger,
+                    SAME_FINGER,
+                ),
+                (
+                    "同指大跨排",
+                    fingering_weight.same_finger_large_jump,
+                    &mut fingering.same_finger_large_jump,
+                    SAME_FINGER_LARGE_JUMP,
+                ),
+                (
+                    "同指小跨排",
+                    fingering_weight.same_finger_small_jump,
+                    &mut fingering.same_finger_small_jump,
+                    SAME_FINGER_SMALL_JUMP,
+                ),
+                (
+                    "跨排",
+                    fingering_weight.row_jump,
+                    &mut fingering.row_jump,
+                    ROW_JUMP,
+                ),
+            ];
+            for (label, weight, value, index) in components {
+                if let Some(weight) = weight {
+                    let rate = total_fingering[index] / total_pairs;
+                    *value = Some(rate);
+                    loss.add(label, rate, weight);
+                }
+            }
+            if let Some(weight) = fingering_weight.hand_alternation {
+                // 字词之间的击键：上一个字词的末码接下一个字词的首码，二者的编码为「末码 + 首码 * 进制」
+                let radix = self.encoder.radix;
+                let mut boundary_alternation = 0.0;
+                for (i, last) in last_keys.iter().enumerate() {
+                    for (j, first) in first_keys.iter().enumerate() {
+                        let count = self.fingering[i + j * radix][HAND_ALTERNATION];
+                        boundary_alternation += count as f64 * last * first;
+                    }
+                }
+                // 首码和末码的频率之和都等于字词边界的总数，所以上面的和要除以一次边界数，才是互击的边界数的期望
+                let boundaries: f64 = first_keys.iter().sum();
+                let boundary_alternation = boundary_alternation / boundaries.max(f64::EPSILON);
+                let rate = (total_fingering[HAND_ALTERNATION] + boundary_alternation)
+                    / (total_pairs + boundaries);
+                fingering.hand_alternation = Some(rate);
+                loss.add("左右互击", rate, weight);
+            }
+            partial_metric.fingering = Some(fingering);
         }
         if let Some(duplication_weight) = weights.duplication {
             partial_metric.duplication = Some(total_duplication);
-            loss += total_duplication * duplication_weight;
+            loss.add("选重率", total_duplication, duplication_weight);
+        }
+        if let Some(ranked) = &weights.ranked_duplication {
+            partial_metric.ranked_duplication = Some(total_ranked_duplication);
+            loss.add("选重代价", total_ranked_duplication, ranked.weight);
         }
         if let Some(levels_weight) = &weights.levels {
             let mut levels: Vec<LevelMetric2> = Vec::new();
             for (ilevel, level) in levels_weight.iter().enumerate() {
                 let value = total_levels[ilevel];
-                loss += value * level.frequency;
+                loss.add("简码频率", value, level.frequency);
+                // 这一码长的字词平均每个键对的组合当量
+                let pair_equivalence = level.pair_equivalence.map(|weight| {
+                    let pairs = value * (level.length - 1) as f64;
+                    let equivalence = if pairs > 0.0 {
+                        total_levels_pair_equivalence[ilevel] / pairs
+                    } else {
+                        0.0
+                    };
+                    loss.add("简码组合当量", equivalence, weight);
+                    equivalence
+                });
                 levels.push(LevelMetric2 {
                     length: level.length,
                     frequency: value,
+                    pair_equivalence,
                 });
             }
             partial_metric.levels = Some(levels);
@@ -262,21 +1382,43 @@ impl Objective {
             let mut tiers: Vec<TierMetric> = tiers_weight
                 .iter()
                 .map(|x| TierMetric {
+                    from: x.from,
                     top: x.top,
                     duplication: None,
+                    coverage: None,
+                    length: None,
+                    pair_equivalence: None,
                     levels: None,
                 })
                 .collect();
             for (itier, twights) in tiers_weight.iter().enumerate() {
-                let total = twights.top.unwrap_or(codes.len());
+                let from = twights.from.unwrap_or(0);
+                let total = twights.top.unwrap_or(codes.len()).saturating_sub(from);
                 if let Some(duplication_weight) = twights.duplication {
                     let duplication = tiers_duplication[itier];
-                    loss += duplication as f64 / total as f64 * duplication_weight;
+                    loss.add("分级选重", duplication as f64 / total as f64, duplication_weight);
                     tiers[itier].duplication = Some(duplication);
                 }
+                if let Some(coverage_weight) = twights.coverage {
+                    let size = total.min(codes.len().saturating_sub(from));
+                    let coverage = tiers_coverage[itier] as f64 / size as f64;
+                    loss.add("分级覆盖率", coverage, coverage_weight);
+                    tiers[itier].coverage = Some(coverage);
+                }
+                if let Some(length_weight) = twights.length {
+                    let length = tiers_length[itier] / tiers_frequency[itier];
+                    loss.add("分级码长", length, length_weight);
+                    tiers[itier].length = Some(length);
+                }
+                if let Some(equivalence_weight) = twights.pair_equivalence {
+                    let equivalence = tiers_pair_equivalence[itier] / tiers_pairs[itier];
+                    loss.add("分级组合当量", equivalence, equivalence_weight);
+                    tiers[itier].pair_equivalence = Some(equivalence);
+                }
                 if let Some(level_weight) = &twights.levels {
                     for (ilevel, level) in level_weight.iter().enumerate() {
-                        loss += tiers_levels[itier][ilevel] as f64 / total as f64 * level.frequency;
+                        let value = tiers_levels[itier][ilevel] as f64 / total as f64;
+                        loss.add("分级简码", value, level.frequency);
                     }
                     tiers[itier].levels = Some(
                         level_weight
@@ -292,7 +1434,7 @@ impl Objective {
             }
             partial_metric.tiers = Some(tiers);
         }
-        return (partial_metric, loss);
+        partial_metric
     }
 
     /// 计算各个部分编码的指标，然后将它们合并成一个指标输出
@@ -301,54 +1443,254 @@ impl Objective {
         candidate: &KeyMap,
         buffer: &mut Buffer,
     ) -> Result<(Metric, f64), Error> {
-        let mut loss = 0.0;
+        let mut loss = Loss::new(&self.scales, !self.scales.is_empty());
+        let mut metric = self.evaluate_terms(candidate, buffer, &mut loss)?;
+        if !self.scales.is_empty() {
+            metric.normalized = Some(loss.terms);
+        }
+        Ok((metric, loss.total))
+    }
+
+    /// 计算各项指标，并把它们逐项累加到目标函数中
+    fn evaluate_terms(
+        &self,
+        candidate: &KeyMap,
+        buffer: &mut Buffer,
+        loss: &mut Loss,
+    ) -> Result<Metric, Error> {
         let mut metric = Metric {
             characters: None,
             words: None,
             characters_reduced: None,
             words_reduced: None,
+            punctuation: None,
+            traditional: None,
+            corpus: None,
+            learning: None,
+            reference: None,
+            constraints: None,
+            custom: None,
+            normalized: None,
         };
-        if let Some(characters) = &self.config.characters_full {
-            let mut occupation: Occupation = vec![false; self.pair_equivalence.len()];
-            self.encoder.encode_character_full(
-                &candidate,
-                &mut buffer.characters_full,
-                &mut occupation,
-            );
-            let (partial, accum) = self.evaluate_partial(
-                &buffer.characters_full,
-                &self.character_frequencies,
-                characters,
-            );
-            loss += accum;
-            metric.characters = Some(partial);
-            if let Some(characters_short) = &self.config.characters_short {
-                let characters_short_buffer =
-                    buffer.characters_short.as_mut().ok_or("简码模式未定义")?;
-                self.encoder.encode_short(
-                    &buffer.characters_full,
-                    characters_short_buffer,
-                    &mut occupation,
+        let characters_full = Self::enabled(&self.config.characters_full);
+        let characters_short = Self::enabled(&self.config.characters_short);
+        let words_full = Self::enabled(&self.config.words_full);
+        let corpus = self.config.corpus.as_ref();
+        let punctuation = Self::enabled(&self.config.punctuation);
+        // 单字简码依赖于单字全码，所以只要评测其中之一就要先编码单字全码；语料模拟和标点符号也要用到字的编码
+        let characters = characters_full.is_some() || characters_short.is_some();
+        if characters || corpus.is_some() || punctuation.is_some() {
+            match &self.custom_encoder {
+                Some(custom) => self.encoder.encode_characters_with(
+                    custom.as_ref(),
+                    candidate,
+                    &mut buffer.characters_full,
+                    &mut buffer.characters_index,
+                ),
+                None => self.encoder.encode_characters_incremental(
+                    candidate,
+                    &mut buffer.characters_full,
+                    &mut buffer.characters_index,
+                ),
+            }
+            let occupation = &buffer.characters_index.occupation;
+            // 语料模拟时，如果定义了简码就按简码输入单字；不保留全码时也要先知道哪些字有简码
+            let adjust = self.encoder.full_code_policy() != FullCodePolicy::Keep;
+            if characters_short.is_some() || corpus.is_some() || adjust {
+                if let Some(characters_short_buffer) = buffer.characters_short.as_mut() {
+                    self.encoder.encode_short(
+                        &buffer.characters_full,
+                        characters_short_buffer,
+                        occupation,
+                    );
+                }
+            }
+            if let Some(characters) = characters_full {
+                loss.begin("单字全码", characters.weight.unwrap_or(1.0));
+                // 取得简码的字不保留全码时，单字全码的重码要重新判断
+                let adjusted = match (adjust, &buffer.characters_short) {
+                    (true, Some(short)) => {
+                        Some(self.encoder.adjust_full(&buffer.characters_full, short))
+                    }
+                    _ => None,
+                };
+                let full_codes = adjusted.as_ref().unwrap_or(&buffer.characters_full);
+                let mut partial_metric = self.evaluate_partial(
+                    full_codes,
+                    &self.character_frequencies,
+                    characters,
+                    loss,
                 );
-                let (partial, accum) = self.evaluate_partial(
+                if let Some(dynamic_weight) = characters.dynamic_duplication {
+                    let dynamic = self.dynamic_duplication(full_codes);
+                    loss.add("动态选重率", dynamic, dynamic_weight);
+                    partial_metric.dynamic_duplication = Some(dynamic);
+                }
+                metric.characters = Some(partial_metric);
+            }
+            if let Some(characters_short) = characters_short {
+                let characters_short_buffer =
+                    buffer.characters_short.as_ref().ok_or("简码模式未定义")?;
+                loss.begin("单字简码", characters_short.weight.unwrap_or(1.0));
+                let mut partial_metric = self.evaluate_partial(
                     characters_short_buffer,
                     &self.character_frequencies,
                     characters_short,
+                    loss,
                 );
-                loss += accum;
-                metric.characters_reduced = Some(partial);
+                if let Some(dynamic_weight) = characters_short.dynamic_duplication {
+                    let dynamic = self.dynamic_duplication(characters_short_buffer);
+                    loss.add("动态选重率", dynamic, dynamic_weight);
+                    partial_metric.dynamic_duplication = Some(dynamic);
+                }
+                if let Some(conflict_weight) = characters_short.short_full_conflict {
+                    let conflict = self.short_full_conflict(
+                        &buffer.characters_full,
+                        characters_short_buffer,
+                        occupation,
+                    );
+                    loss.add("简全冲突", conflict.frequency, conflict_weight);
+                    partial_metric.short_full_conflict = Some(conflict);
+                }
+                metric.characters_reduced = Some(partial_metric);
             }
         }
-        if let Some(words) = &self.config.words_full {
-            let mut occupation: Occupation = vec![false; self.encoder.get_space()];
+        if words_full.is_some() || (corpus.is_some() && buffer.words_full.is_some()) {
             let words_buffer = buffer.words_full.as_mut().ok_or("组词规则未定义")?;
-            self.encoder
-                .encode_words_full(&candidate, words_buffer, &mut occupation);
-            let (partial, accum) =
-                self.evaluate_partial(&words_buffer, self.word_frequencies.as_ref().unwrap(), words);
-            loss += accum;
-            metric.words = Some(partial);
-        }
-        Ok((metric, loss))
+            self.encoder.encode_words_incremental(
+                candidate,
+                words_buffer,
+                &mut buffer.words_index,
+            );
+            if let Some(words) = words_full {
+                loss.begin("词语全码", words.weight.unwrap_or(1.0));
+                metric.words = Some(self.evaluate_partial(
+                    words_buffer,
+                    self.word_frequencies.as_ref().unwrap(),
+                    words,
+                    loss,
+                ));
+            }
+        }
+        if let Some(punctuation) = punctuation {
+            // 标点符号的编码与字的全码相同，或者与频率更高的标点相同时，就是重码
+            let occupation = &buffer.characters_index.occupation;
+            let codes: Codes = self
+                .punctuation_codes
+                .iter()
+                .enumerate()
+                .map(|(index, code)| {
+                    let duplicated =
+                        occupation[*code] || self.punctuation_codes[..index].contains(code);
+                    (*code, duplicated)
+                })
+                .collect();
+            loss.begin("标点符号", punctuation.weight.unwrap_or(1.0));
+            metric.punctuation = Some(self.evaluate_partial(
+                &codes,
+                &self.punctuation_frequencies,
+                punctuation,
+                loss,
+            ));
+        }
+        if let Some(traditional) = Self::enabled(&self.config.traditional) {
+            // 繁体字按照繁体字频排列候选，只与繁体字之间比较重码
+            let sequences = &self.traditional_sequences;
+            let codes = buffer
+                .traditional_full
+                .get_or_insert_with(|| vec![(0, false); sequences.len()]);
+            self.encoder.encode_incremental(
+                candidate,
+                sequences,
+                &self.traditional_entries,
+                codes,
+                &mut buffer.traditional_index,
+            );
+            loss.begin("繁体单字全码", traditional.weight.unwrap_or(1.0));
+            metric.traditional = Some(self.evaluate_partial(
+                codes,
+                &self.traditional_frequencies,
+                traditional,
+                loss,
+            ));
+        }
+        if let Some(corpus) = corpus {
+            loss.begin("语料", 1.0);
+            metric.corpus = Some(self.evaluate_corpus(buffer, corpus, loss));
+        }
+        if let Some(learning) = &self.config.learning {
+            loss.begin("", 1.0);
+            metric.learning = Some(self.evaluate_learning(candidate, learning, loss));
+        }
+        if let Some(reference) = &self.config.reference {
+            loss.begin("", 1.0);
+            let frequencies = &self.reference_frequencies;
+            let mut metric_reference = ReferenceMetric {
+                moved: 0,
+                frequency: 0.0,
+            };
+            for ((key, original), frequency) in zip(zip(candidate, &self.reference), frequencies) {
+                if original.is_some_and(|x| x != *key) {
+                    metric_reference.moved += 1;
+                    metric_reference.frequency += frequency;
+                }
+            }
+            metric_reference.frequency /= frequencies.iter().sum::<f64>().max(f64::EPSILON);
+            loss.add("参考布局偏离", metric_reference.frequency, reference.weight);
+            metric.reference = Some(metric_reference);
+        }
+        if !self.soft_constraints.is_empty() {
+            loss.begin("", 1.0);
+            let mut metric_constraints = ConstraintMetric {
+                violated: 0,
+                penalty: 0.0,
+            };
+            for constraint in &self.soft_constraints {
+                if !constraint.keys.contains(&candidate[constraint.element]) {
+                    metric_constraints.violated += 1;
+                    metric_constraints.penalty += constraint.weight;
+                }
+            }
+            loss.add("软约束惩罚", metric_constraints.penalty, 1.0);
+            metric.constraints = Some(metric_constraints);
+        }
+        if !self.custom.is_empty() {
+            loss.begin("", 1.0);
+            let mut values = Vec::new();
+            for component in &self.custom {
+                let codes = match component.partial.as_str() {
+                    "characters_full" => &buffer.characters_full,
+                    "characters_short" => buffer.characters_short.as_ref().ok_or("简码模式未定义")?,
+                    _ => buffer.words_full.as_ref().ok_or("组词规则未定义")?,
+                };
+                let codes = codes.iter().map(|(x, duplicated)| (self.custom_code(*x), *duplicated));
+                let value = component.score(codes)?;
+                loss.add(&component.name, value, component.weight);
+                values.push((component.name.clone(), value));
+            }
+            metric.custom = Some(values);
+        }
+        Ok(metric)
+    }
+
+    /// 在若干个布局上计算各项指标的原始值，据此确定归一化的中心和尺度
+    fn calibrate(&mut self, normalization: Normalization, initial: &KeyMap) -> Result<(), Error> {
+        let mut buffer = self.encoder.init_buffer();
+        let mut layouts = vec![initial.clone()];
+        if let Normalization::ZScore = normalization {
+            let mut rng = StdRng::seed_from_u64(0);
+            let keys = 1..self.encoder.alphabet_radix;
+            layouts = (0..CALIBRATION_SAMPLES)
+                .map(|_| initial.iter().map(|_| rng.gen_range(keys.clone())).collect())
+                .collect();
+        }
+        let mut samples = Vec::new();
+        for layout in &layouts {
+            let mut loss = Loss::new(&[], true);
+            self.evaluate_terms(layout, &mut buffer, &mut loss)?;
+            samples.push(loss.terms.iter().map(|x| x.raw).collect());
+        }
+        self.scales = normalization::calibrate(normalization, &samples);
+        Ok(())
     }
 }
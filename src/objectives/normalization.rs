@@ -0,0 +1,94 @@
+//! 目标函数各项的归一化
+//!
+//! 目标函数是各项指标的加权和，而各项指标的量纲差别很大（选重率是百分之几，当量是一点几），权重因此难以解释。归一化之后，每一项先减去中心再除以尺度，然后才乘以权重：
+//!
+//! - 基准：中心为 0，尺度为方案文件中的布局在这一项上的取值，即归一化的值是相对于初始方案的倍数；
+//! - 标准分：中心和尺度分别为一批随机布局在这一项上的均值和标准差。
+//!
+//! 各项按照计算的顺序编号，对于同一个配置文件，顺序总是相同的。
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// 归一化的方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    Baseline,
+    ZScore,
+}
+
+/// 归一化前后的一项指标
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Term {
+    pub label: String,
+    pub raw: f64,
+    pub normalized: f64,
+}
+
+/// 目标函数的累加器
+pub struct Loss<'a> {
+    scales: &'a [(f64, f64)],
+    record: bool,
+    index: usize,
+    pub partial: &'static str,
+    pub scale: f64,
+    pub total: f64,
+    pub terms: Vec<Term>,
+}
+
+impl<'a> Loss<'a> {
+    /// scales 是每一项的中心和尺度，为空时不做归一化；record 为真时记录每一项的值
+    pub fn new(scales: &'a [(f64, f64)], record: bool) -> Self {
+        Self {
+            scales,
+            record,
+            index: 0,
+            partial: "",
+            scale: 1.0,
+            total: 0.0,
+            terms: Vec::new(),
+        }
+    }
+
+    /// 开始累加某一部分的指标，这一部分的所有指标都乘以同一个整体权重
+    pub fn begin(&mut self, partial: &'static str, scale: f64) {
+        self.partial = partial;
+        self.scale = scale;
+    }
+
+    /// 累加一项指标
+    pub fn add(&mut self, label: &str, value: f64, weight: f64) {
+        let (center, scale) = self.scales.get(self.index).copied().unwrap_or((0.0, 1.0));
+        let normalized = (value - center) / scale;
+        self.total += normalized * weight * self.scale;
+        self.index += 1;
+        if self.record {
+            self.terms.push(Term {
+                label: format!("{}{}", self.partial, label),
+                raw: value,
+                normalized,
+            });
+        }
+    }
+}
+
+/// 由若干个方案上各项的取值计算每一项的中心和尺度。尺度为零的项不做缩放
+pub fn calibrate(normalization: Normalization, samples: &[Vec<f64>]) -> Vec<(f64, f64)> {
+    let terms = samples.first().map_or(0, |x| x.len());
+    (0..terms)
+        .map(|i| {
+            let values: Vec<f64> = samples.iter().map(|x| x[i]).collect();
+            let (center, scale) = match normalization {
+                Normalization::Baseline => (0.0, values[0].abs()),
+                Normalization::ZScore => {
+                    let mean = values.iter().sum::<f64>() / values.len() as f64;
+                    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>()
+                        / values.len() as f64;
+                    (mean, variance.sqrt())
+                }
+            };
+            (center, if scale > 1e-12 { scale } else { 1.0 })
+        })
+        .collect()
+}
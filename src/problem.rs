@@ -3,23 +3,52 @@
 //! 目前只定义了最基础的元素布局问题，以后可能会定义更复杂的问题，如元素布局 + 元素选取等等。
 //! 
 
-use crate::config::{SolverConfig, SearchConfig};
+use crate::config::{Initialization, SolverConfig};
 use crate::constraints::Constraints;
-use crate::interface::Interface;
-use crate::metaheuristics::{simulated_annealing, Metaheuristics};
+use crate::error::Error;
+use crate::interface::{Interface, SilentInterface};
+use crate::metaheuristics::simulated_annealing::{Checkpoint, SimulatedAnnealing};
+use crate::metaheuristics::{
+    ant_colony, bandit::Bandit, beam_search, exhaustive, genetic_algorithm, hill_climbing, memetic, nsga2, parallel_annealing, parallel_tempering, simulated_annealing, tabu_search, Change,
+    Constructive, Evolutionary, Metaheuristic, Metaheuristics, MultiObjective,
+};
 use crate::objectives::Objective;
 use crate::objectives::metric::Metric;
-use crate::representation::{Buffer, KeyMap, Representation};
-use rand::random;
+use crate::representation::{Buffer, KeyMap, Representation, TrajectoryEntry};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::iter::zip;
+use std::thread;
+use web_time::{Duration, Instant};
 
 // 未来可能会有更加通用的解定义
 type Solution = KeyMap;
 
+/// 支持的算法名称，`SA` 是 `SimulatedAnnealing` 的简写
+const ALGORITHMS: [&str; 10] = [
+    "SimulatedAnnealing",
+    "SA",
+    "GeneticAlgorithm",
+    "TabuSearch",
+    "ParallelTempering",
+    "HillClimbing",
+    "Memetic",
+    "AntColony",
+    "Exhaustive",
+    "NSGA2",
+];
+
+#[derive(Clone)]
 pub struct ElementPlacementProblem {
     representation: Representation,
     constraints: Constraints,
     objective: Objective,
     buffer: Buffer,
+    rng: ChaCha8Rng,
+    initial: Solution,
+    bandit: Bandit,
+    last_operator: Option<usize>,
+    frequencies: Vec<f64>,
 }
 
 impl ElementPlacementProblem {
@@ -28,65 +57,1066 @@ impl ElementPlacementProblem {
         constraints: Constraints,
         objective: Objective,
         buffer: Buffer,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, Error> {
+        // 如果指定了随机数种子，优化的过程就是完全确定的
+        let rng = match representation.config.optimization.metaheuristic.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        // 初始方案中同组的元素可能不在同一个键上，先把它们放到一起
+        let mut initial = representation.initial.clone();
+        constraints.align(&mut initial);
+        let method = representation
+            .config
+            .optimization
+            .metaheuristic
+            .search_method
+            .clone()
+            .unwrap_or_default();
+        let bandit = Bandit::new(
+            method.selection.unwrap_or_default(),
+            vec![
+                method.random_move,
+                method.random_swap,
+                method.random_full_key_swap,
+                method.destroy_repair.unwrap_or(0.0),
+                method.rotation.unwrap_or(0.0),
+                method.feasible_swap.unwrap_or(0.0),
+            ],
+        );
+        let frequencies = objective.element_frequencies(initial.len());
+        let mut problem = Self {
             representation,
             constraints,
             objective,
             buffer,
+            rng,
+            initial,
+            bandit,
+            last_operator: None,
+            frequencies,
+        };
+        problem.check_solver()?;
+        Ok(problem)
+    }
+
+    /// 检查求解算法的配置，使配置错误在开始求解之前就能报告出来：
+    /// 多目标优化必须提供 nsga2 参数，其中的每个目标都必须是评测指标中存在的分量
+    fn check_solver(&mut self) -> Result<(), Error> {
+        let metaheuristic = &self.representation.config.optimization.metaheuristic;
+        if !ALGORITHMS.contains(&metaheuristic.algorithm.as_str()) {
+            let algorithm = &metaheuristic.algorithm;
+            let message = format!("optimization.metaheuristic.algorithm：未知的算法 {algorithm}");
+            return Err(message.into());
+        }
+        if metaheuristic.algorithm == "Exhaustive" {
+            let parameters = metaheuristic.exhaustive.clone().unwrap_or_default();
+            exhaustive::count_candidates(&self.construction_space(), &parameters)?;
+        }
+        if metaheuristic.algorithm == "NSGA2" {
+            let path = "optimization.metaheuristic.nsga2";
+            let parameters = metaheuristic
+                .nsga2
+                .as_ref()
+                .ok_or(format!("使用多目标优化时必须提供 {path} 参数"))?;
+            // 评测指标中有哪些分量只取决于目标函数的配置，与布局无关
+            let (metric, _) = self.objective.evaluate(&self.initial, &mut self.buffer)?;
+            for (index, name) in parameters.objectives.iter().enumerate() {
+                if metric.component(name).is_none() {
+                    let message = format!("{path}.objectives[{index}]：评测指标中没有 {name}");
+                    return Err(message.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 复制出一个独立的问题，它的随机数生成器由当前问题的随机数生成器派生
+    fn fork(&mut self) -> Self {
+        let mut problem = self.clone();
+        problem.rng = ChaCha8Rng::from_rng(&mut self.rng).unwrap(); // ChaCha8Rng 作为种子来源不会失败
+        problem
+    }
+
+    /// 破坏与修复：取出一组相关的元素，按照频率从高到低的顺序，依次把每个元素放到使目标函数最小的可行按键上。尚未处理的元素暂时留在原来的按键上，但不参与可行性的判断。
+    /// 如果某个元素没有可行的按键，就放弃这次修复，保持原方案不变
+    fn destroy_and_repair(&mut self, candidate: &Solution) -> Solution {
+        let elements = self.constraints.related_elements(candidate, &mut self.rng);
+        let mut next = candidate.clone();
+        let mut placed = vec![true; next.len()];
+        for element in &elements {
+            for member in self.constraints.members(*element) {
+                placed[*member] = false;
+            }
+        }
+        if self.greedy_assign(&mut next, &mut placed, elements) {
+            next
+        } else {
+            candidate.clone()
         }
     }
+
+    /// 按照频率从高到低的顺序，依次把每个元素放到使目标函数最小的可行按键上。placed 标记已经放好的元素，只有放好的元素参与可行性的判断。
+    /// 返回是否所有的元素都找到了可行的按键
+    fn greedy_assign(
+        &mut self,
+        next: &mut Solution,
+        placed: &mut [bool],
+        mut elements: Vec<usize>,
+    ) -> bool {
+        elements.sort_by(|a, b| self.frequencies[*b].total_cmp(&self.frequencies[*a]));
+        for element in elements {
+            // 同键的元素已经随着组里的其他元素放好了
+            if placed[element] {
+                continue;
+            }
+            let destinations = self
+                .constraints
+                .narrowed
+                .get(&element)
+                .unwrap_or(&self.constraints.alphabet)
+                .clone();
+            let mut best: Option<(usize, f64)> = None;
+            for key in destinations {
+                if !self.constraints.admits(next, placed, element, key) {
+                    continue;
+                }
+                self.constraints.place(next, element, key);
+                let (_, loss) = self.objective.evaluate(next, &mut self.buffer).unwrap();
+                if best.is_none_or(|(_, best_loss)| loss < best_loss) {
+                    best = Some((key, loss));
+                }
+            }
+            let Some((key, _)) = best else {
+                return false;
+            };
+            self.constraints.place(next, element, key);
+            for member in self.constraints.members(element) {
+                placed[*member] = true;
+            }
+        }
+        true
+    }
+
+    /// 不使用方案文件中的布局，贪心地构造一个初始方案：先把所有可移动的元素都放在各自的第一个可行按键上，然后按照频率从高到低的顺序依次为每个元素选择使目标函数最小的可行按键。
+    /// 先放的元素可能使后放的元素没有可行的按键，这时构造失败，返回错误
+    fn greedy_candidate(&mut self) -> Result<Solution, Error> {
+        let mut candidate = self.representation.initial.clone();
+        let mut elements = Vec::new();
+        for (element, key) in candidate.iter_mut().enumerate() {
+            if self.constraints.fixed.contains(&element) {
+                continue;
+            }
+            let destinations = self
+                .constraints
+                .narrowed
+                .get(&element)
+                .unwrap_or(&self.constraints.alphabet);
+            *key = destinations[0];
+            elements.push(element);
+        }
+        let mut placed: Vec<bool> = (0..candidate.len())
+            .map(|x| self.constraints.fixed.contains(&x))
+            .collect();
+        let assigned = self.greedy_assign(&mut candidate, &mut placed, elements);
+        let violations = self.constraints.violations(&candidate);
+        if !assigned || violations > 0 {
+            let path = "optimization.metaheuristic.initialization";
+            let message = format!("贪心构造的初始方案无法满足所有的约束，请把 {path} 改为 config");
+            return Err(message.into());
+        }
+        Ok(candidate)
+    }
 }
 
 impl Metaheuristics<Solution, Metric> for ElementPlacementProblem {
     fn clone_candidate(&mut self, candidate: &Solution) -> Solution {
-        return candidate.clone();
+        candidate.clone()
     }
 
     fn generate_candidate(&mut self) -> Solution {
-        return self.representation.initial.clone();
+        self.initial.clone()
     }
 
     fn rank_candidate(&mut self, candidate: &Solution) -> (Metric, f64) {
         let (metric, loss) = self.objective.evaluate(candidate, &mut self.buffer).unwrap();
-        return (metric, loss);
+        (metric, loss)
     }
 
     fn tweak_candidate(&mut self, candidate: &Solution) -> Solution {
-        let method = self.representation.config.optimization.metaheuristic.search_method.as_ref().unwrap_or(&SearchConfig { random_move: 0.9, random_swap: 0.09, random_full_key_swap: 0.01 });
-        let ratio1 = method.random_move / (method.random_move + method.random_swap + method.random_full_key_swap);
-        let ratio2 = (method.random_move + method.random_swap) / (method.random_move + method.random_swap + method.random_full_key_swap);
-        let randomnumber = random::<f64>();
-        if randomnumber < ratio1 {
-            self.constraints.constrained_random_move(candidate)
-        } else if randomnumber < ratio2 {
-            self.constraints.constrained_random_swap(candidate)
-        } else {
-            self.constraints.constrained_full_key_swap(candidate)
+        let operator = self.bandit.select(&mut self.rng);
+        self.last_operator = Some(operator);
+        match operator {
+            0 => self.constraints.constrained_random_move(candidate, &mut self.rng),
+            1 => self.constraints.constrained_random_swap(candidate, &mut self.rng),
+            2 => self.constraints.constrained_full_key_swap(candidate, &mut self.rng),
+            3 => self.destroy_and_repair(candidate),
+            4 => self.constraints.constrained_rotation(candidate, &mut self.rng),
+            _ => self.constraints.constrained_feasible_swap(candidate, &mut self.rng),
+        }
+    }
+
+    fn report_improvement(&mut self, improvement: f64) {
+        if let Some(operator) = self.last_operator.take() {
+            self.bandit.update(operator, improvement < 0.0);
         }
     }
 
+    fn compare_candidates(&self, old_candidate: &Solution, new_candidate: &Solution) -> Vec<Change> {
+        zip(old_candidate, new_candidate)
+            .enumerate()
+            .filter(|(_, (from, to))| from != to)
+            .map(|(element, (from, to))| Change {
+                element,
+                from: *from,
+                to: *to,
+            })
+            .collect()
+    }
+
+    fn clone_problem(&mut self) -> Box<dyn Metaheuristics<Solution, Metric> + Send> {
+        Box::new(self.fork())
+    }
+
+    fn rng(&mut self) -> &mut dyn RngCore {
+        &mut self.rng
+    }
+
+    fn save_checkpoint(&mut self, step: usize, state: Vec<u8>, interface: &dyn Interface) {
+        let data = bincode::serialize(&(state, &self.rng, &self.bandit)).unwrap();
+        interface.report_checkpoint(step, data);
+    }
+
+    fn save_move(
+        &self,
+        step: usize,
+        old_candidate: &Solution,
+        new_candidate: &Solution,
+        delta: f64,
+        interface: &dyn Interface,
+    ) {
+        if self.representation.config.optimization.metaheuristic.record != Some(true) {
+            return;
+        }
+        let changes = self.representation.describe_moves(old_candidate, new_candidate);
+        interface.report_move(TrajectoryEntry { step, changes, delta });
+    }
+
     fn save_candidate(&self, candidate: &Solution, rank: &(Metric, f64), write_to_file: bool, interface: &dyn Interface) {
-        let new_config = self.representation.update_config(&candidate);
+        let new_config = self.representation.update_config(candidate);
         let metric = format!("{}", rank.0);
         interface.report_solution(new_config, metric, write_to_file);
     }
 }
 
+impl Evolutionary<Solution, Metric> for ElementPlacementProblem {
+    fn crossover_candidates(&mut self, candidate1: &Solution, candidate2: &Solution) -> Solution {
+        self.constraints.constrained_crossover(candidate1, candidate2, &mut self.rng)
+    }
+}
+
+impl MultiObjective<Solution, Metric> for ElementPlacementProblem {
+    fn split_metric(&self, metric: &Metric, components: &[String]) -> Vec<f64> {
+        components
+            .iter()
+            .map(|name| {
+                metric.component(name).unwrap() // 在构造问题时已经检查过所有的分量都存在
+            })
+            .collect()
+    }
+
+    fn save_front(
+        &self,
+        front: &[(Solution, (Metric, f64))],
+        components: &[String],
+        interface: &dyn Interface,
+    ) {
+        let front = front
+            .iter()
+            .map(|(candidate, (metric, _))| {
+                let config = self.representation.update_config(candidate);
+                (config, self.split_metric(metric, components), format!("{}", metric))
+            })
+            .collect();
+        interface.report_pareto_front(components, front);
+    }
+}
+
+impl Constructive<Solution, Metric> for ElementPlacementProblem {
+    fn construction_space(&self) -> Vec<(Vec<usize>, f64)> {
+        let initial = &self.initial;
+        let frequencies = self.objective.element_frequencies(initial.len());
+        let Constraints {
+            alphabet,
+            fixed,
+            narrowed,
+            ..
+        } = &self.constraints;
+        // 同键的元素只由组里的第一个元素做选择，其余的在组装方案时跟随它
+        (0..initial.len())
+            .map(|element| {
+                let follower = self.constraints.members(element)[0] != element;
+                let choices = if fixed.contains(&element) || follower {
+                    vec![initial[element]]
+                } else {
+                    narrowed.get(&element).unwrap_or(alphabet).clone()
+                };
+                (choices, frequencies[element])
+            })
+            .collect()
+    }
+
+    fn assemble_candidate(&self, values: &[usize]) -> Solution {
+        let mut candidate = values.to_vec();
+        for members in self.constraints.groups.iter().filter(|x| x.len() > 1) {
+            self.constraints.place(&mut candidate, members[0], values[members[0]]);
+        }
+        candidate
+    }
+
+    fn disassemble_candidate(&self, candidate: &Solution) -> Vec<usize> {
+        candidate.clone()
+    }
+
+    fn admissible(
+        &self,
+        values: &[usize],
+        decided: &[bool],
+        decision: usize,
+        value: usize,
+    ) -> bool {
+        // 同键的元素跟随组里的第一个元素，由它来判断
+        let leader = |x: usize| self.constraints.members(x)[0];
+        if leader(decision) != decision {
+            return true;
+        }
+        let position = |x: usize| decided[leader(x)].then_some(values[leader(x)]);
+        self.constraints.admits_with(position, decision, value)
+    }
+
+    fn feasible(&self, candidate: &Solution) -> bool {
+        self.constraints.violations(candidate) == 0
+    }
+}
+
 impl ElementPlacementProblem {
-    pub fn solve(&mut self, interface: &dyn Interface) -> Solution {
+    /// 求解问题。如果配置了多次重启，就在多个线程上独立地求解多次，最后只汇报和保存最好的方案
+    pub fn solve(&mut self, interface: &dyn Interface) -> Result<Solution, Error> {
         interface.prepare_output();
-        let SolverConfig { parameters, runtime, report_after, .. } = self
+        let (movable, size) = self.constraints.search_space();
+        interface.report_search_space(movable, size);
+        let metaheuristic = &self.representation.config.optimization.metaheuristic;
+        if let Some(Initialization::Greedy) = metaheuristic.initialization {
+            self.initial = self.greedy_candidate()?;
+            let rank = self.rank_candidate(&self.initial.clone());
+            self.save_candidate(&self.initial, &rank, false, interface);
+        }
+        // 先用集束搜索确定高频元素的按键，作为后续优化的初始方案
+        if let Some(parameters) = self
+            .representation
+            .config
+            .optimization
+            .metaheuristic
+            .beam_search
+            .clone()
+        {
+            self.initial = beam_search::search(self, parameters, interface);
+        }
+        let initial = self.initial.clone();
+        self.record_start(&initial, interface);
+        let restarts = self
+            .representation
+            .config
+            .optimization
+            .metaheuristic
+            .restarts
+            .unwrap_or(1);
+        if restarts <= 1 {
+            return Ok(self.solve_staged(interface));
+        }
+        let concurrency = thread::available_parallelism().map_or(1, |x| x.get());
+        let mut results: Vec<(Solution, (Metric, f64))> = Vec::new();
+        while results.len() < restarts {
+            let batch = concurrency.min(restarts - results.len());
+            let finished: Vec<_> = thread::scope(|scope| {
+                let handles: Vec<_> = (0..batch)
+                    .map(|_| {
+                        let mut problem = self.fork();
+                        scope.spawn(move || {
+                            let candidate = problem.solve_staged(&SilentInterface);
+                            let rank = problem.rank_candidate(&candidate);
+                            (candidate, rank)
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|x| x.join().unwrap()).collect()
+            });
+            results.extend(finished);
+        }
+        let losses: Vec<f64> = results.iter().map(|(_, rank)| rank.1).collect();
+        interface.report_restarts(&losses);
+        let (best_candidate, best_rank) = results
+            .into_iter()
+            .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+            .unwrap(); // 至少有两次重启
+        self.save_candidate(&best_candidate, &best_rank, true, interface);
+        Ok(best_candidate)
+    }
+
+    /// 从检查点继续求解。检查点中保存了退火的状态和随机数生成器的状态，但是不包含方案配置和拆分表，所以需要和中断前使用同样的输入文件
+    pub fn resume(&mut self, checkpoint: &[u8], interface: &dyn Interface) -> Result<Solution, Error> {
+        let parse = |e: bincode::Error| format!("检查点无法解析：{e}");
+        let (state, rng, bandit): (Vec<u8>, ChaCha8Rng, Bandit) =
+            bincode::deserialize(checkpoint).map_err(parse)?;
+        let checkpoint: Checkpoint<Solution> = bincode::deserialize(&state).map_err(parse)?;
+        if checkpoint.candidate.len() != self.representation.initial.len() {
+            return Err("检查点中的元素数量与当前方案不一致，请使用中断前的方案文件和拆分表".into());
+        }
+        self.rng = rng;
+        self.bandit = bandit;
+        interface.prepare_output();
+        self.record_start(&checkpoint.candidate, interface);
+        let SolverConfig {
+            report_after,
+            max_time,
+            ..
+        } = self.representation.config.optimization.metaheuristic;
+        let deadline = max_time.map(|x| Instant::now() + Duration::from_secs(x));
+        Ok(simulated_annealing::resume(
+            self,
+            checkpoint,
+            report_after,
+            deadline,
+            interface,
+        ))
+    }
+
+    /// 在优化轨迹的开头记录从方案文件中的布局到优化起点的变化，这样回放时只需要方案文件
+    fn record_start(&mut self, start: &Solution, interface: &dyn Interface) {
+        if self.representation.config.optimization.metaheuristic.record != Some(true) {
+            return;
+        }
+        let config = self.representation.initial.clone();
+        let delta = self.rank_candidate(start).1 - self.rank_candidate(&config).1;
+        self.save_move(0, &config, start, delta, interface);
+    }
+
+    /// 评测若干个候选的初始方案，把其中最好的一个作为优化的起点。每个候选都先对齐元素组，对齐之后仍然违反约束的候选不能作为起点，返回错误
+    pub fn warm_start(
+        &mut self,
+        mut candidates: Vec<(String, Solution)>,
+        interface: &dyn Interface,
+    ) -> Result<(), Error> {
+        let mut losses = Vec::with_capacity(candidates.len());
+        let mut best: Option<(usize, f64)> = None;
+        for (index, (name, candidate)) in candidates.iter_mut().enumerate() {
+            self.constraints.align(candidate);
+            let violations = self.constraints.violations(candidate);
+            if violations > 0 {
+                let message = format!("{name}：有 {violations} 处违反约束，不能作为优化的起点");
+                return Err(message.into());
+            }
+            let (_, loss) = self.rank_candidate(candidate);
+            if best.is_none_or(|(_, best_loss)| loss < best_loss) {
+                best = Some((index, loss));
+            }
+            losses.push((name.clone(), loss));
+        }
+        if let Some((index, _)) = best {
+            interface.report_warm_start(&losses, index);
+            self.initial = candidates[index].1.clone();
+        }
+        Ok(())
+    }
+
+    /// 用任意一个实现了 `Metaheuristic` 的算法求解问题
+    pub fn solve_with(
+        &mut self,
+        algorithm: &mut dyn Metaheuristic<Solution, Metric>,
+        interface: &dyn Interface,
+    ) -> Solution {
+        algorithm.solve(self, interface)
+    }
+
+    /// 分阶段冻结：每一阶段结束后，把尚未固定的元素中频率最高的若干个固定在当前的按键上，下一阶段只优化剩下的元素。没有配置分阶段冻结时只有一个阶段
+    fn solve_staged(&mut self, interface: &dyn Interface) -> Solution {
+        let freezing = self
+            .representation
+            .config
+            .optimization
+            .metaheuristic
+            .freezing
+            .clone()
+            .unwrap_or_default();
+        let constraints = self.constraints.clone();
+        let mut solution = self.solve_once(interface);
+        for (stage, count) in freezing.iter().enumerate() {
+            let mut elements = self.constraints.movable.clone();
+            elements.sort_by(|a, b| self.frequencies[*b].total_cmp(&self.frequencies[*a]));
+            elements.truncate(*count);
+            if elements.len() == self.constraints.movable.len() {
+                break;
+            }
+            self.constraints.freeze(&elements);
+            self.initial = solution;
+            interface.report_stage(stage + 2, freezing.len() + 1, self.constraints.fixed.len());
+            solution = self.solve_once(interface);
+        }
+        self.constraints = constraints;
+        solution
+    }
+
+    fn solve_once(&mut self, interface: &dyn Interface) -> Solution {
+        let SolverConfig {
+            algorithm,
+            parameters,
+            runtime,
+            report_after,
+            genetic_algorithm,
+            tabu_search,
+            parallel_tempering,
+            hill_climbing,
+            nsga2,
+            memetic,
+            ant_colony,
+            exhaustive,
+            max_time,
+            threads,
+            ..
+        } = self
             .representation
             .config
             .optimization
             .metaheuristic
             .clone();
-        if let Some(parameters) = parameters {
-            simulated_annealing::solve(self, parameters.clone(), report_after, interface)
-        } else {
-            let runtime = runtime.unwrap_or(10);
-            simulated_annealing::autosolve(self, runtime, report_after, interface)
+        // 截止时间从求解开始计算，包括自动寻找参数所用的时间
+        let deadline = max_time.map(|x| Instant::now() + Duration::from_secs(x));
+        let runtime = runtime.unwrap_or_else(|| max_time.map_or(10, |x| x.div_ceil(60)));
+        match algorithm.as_str() {
+            "GeneticAlgorithm" => {
+                let parameters = genetic_algorithm.unwrap_or_default();
+                genetic_algorithm::solve(self, parameters, report_after, interface)
+            }
+            "TabuSearch" => {
+                let parameters = tabu_search.unwrap_or_default();
+                tabu_search::solve(self, parameters, report_after, interface)
+            }
+            "ParallelTempering" => {
+                let annealing = parameters.unwrap_or_else(|| {
+                    simulated_annealing::find_parameters(self, runtime, interface)
+                });
+                let parameters = parallel_tempering.unwrap_or_default();
+                parallel_tempering::solve(self, annealing, parameters, report_after, interface)
+            }
+            "Memetic" => {
+                let annealing = parameters.unwrap_or_else(|| {
+                    simulated_annealing::find_parameters(self, runtime, interface)
+                });
+                let parameters = memetic.unwrap_or_default();
+                memetic::solve(self, annealing, parameters, report_after, interface)
+            }
+            "AntColony" => {
+                let parameters = ant_colony.unwrap_or_default();
+                ant_colony::solve(self, parameters, report_after, interface)
+            }
+            "Exhaustive" => {
+                let parameters = exhaustive.unwrap_or_default();
+                exhaustive::solve(self, parameters, interface)
+            }
+            "NSGA2" => {
+                let parameters = nsga2.unwrap(); // 在构造问题时已经检查过
+                nsga2::solve(self, parameters, interface)
+            }
+            "HillClimbing" => {
+                let parameters = hill_climbing.unwrap_or_default();
+                hill_climbing::solve(self, parameters, report_after, interface)
+            }
+            "SimulatedAnnealing" | "SA" if threads.is_some_and(|x| x > 1) => {
+                let parameters = parameters.unwrap_or_else(|| {
+                    simulated_annealing::find_parameters(self, runtime, interface)
+                });
+                let threads = threads.unwrap(); // 已经判断过
+                parallel_annealing::solve(self, parameters, threads, report_after, deadline, interface)
+            }
+            "SimulatedAnnealing" | "SA" => {
+                let mut algorithm =
+                    SimulatedAnnealing::new(parameters, runtime, report_after, deadline);
+                self.solve_with(&mut algorithm, interface)
+            }
+            _ => unreachable!("在构造问题时已经检查过算法的名称"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ElementPlacementProblem, Solution};
+    use crate::config::Config;
+    use crate::constraints::Constraints;
+    use crate::interface::{Interface, SilentInterface};
+    use crate::metaheuristics::simulated_annealing::{self, Schedule};
+    use crate::metaheuristics::{ant_colony, beam_search, exhaustive, nsga2, parallel_annealing};
+    use crate::metaheuristics::{memetic, parallel_tempering};
+    use crate::metaheuristics::Metaheuristics;
+    use crate::representation::TrajectoryEntry;
+    use crate::testing;
+    use std::sync::Mutex;
+
+    const METAHEURISTIC: &str = r#"
+  metaheuristic:
+    algorithm: SimulatedAnnealing
+    seed: 42
+"#;
+
+    const OBJECTIVE: &str = r#"
+  objective:
+    characters_full:
+      duplication: 1.0
+      pair_equivalence: 0.1
+    words_full:
+      duplication: 1.0
+"#;
+
+    /// 所有布局的目标函数都相同，贪心放置时总是选择第一个可行的按键，最容易把元素堆到一起
+    const INDIFFERENT: &str = r#"
+  objective:
+    characters_full:
+      duplication: 0.0
+"#;
+
+    /// 初始方案中「口」和「日」同键，违反了不同键的约束；构造式算法必须在满足所有约束的方案中做选择
+    const CONSTRAINED: &str = r#"
+    elements:
+      - { element: 丨 }
+      - { element: 木, keys: [b, c] }
+      - { element: 米, keys: [c, d] }
+    relations:
+      - { elements: [口, 日, 木], relation: different }
+    conditionals:
+      - { if: { element: 口, keys: [a] }, then: { element: 米, keys: [c] } }
+    max_elements_per_key: 2
+"#;
+
+    fn optimization(objective: &str, constraints: &str) -> String {
+        format!("{objective}  constraints:\n{constraints}{METAHEURISTIC}")
+    }
+
+    /// 退火参数，只有步数由各个测试决定
+    fn annealing(steps: usize) -> simulated_annealing::Parameters {
+        simulated_annealing::Parameters {
+            t_max: 1.0,
+            t_min: 1e-4,
+            steps,
+            schedule: None,
+            step_scaling: None,
+            acceptance: None,
+            patience: None,
+            reheat: None,
+        }
+    }
+
+    /// 只记录帕累托前沿中各个方案的目标值的接口
+    struct FrontRecorder(Mutex<Vec<Vec<f64>>>);
+
+    impl Interface for FrontRecorder {
+        fn prepare_output(&self) {}
+        fn init_autosolve(&self) {}
+        fn report_search_space(&self, _: usize, _: f64) {}
+        fn report_trial_t_max(&self, _: f64, _: f64) {}
+        fn report_t_max(&self, _: f64) {}
+        fn report_trial_t_min(&self, _: f64, _: f64) {}
+        fn report_t_min(&self, _: f64) {}
+        fn report_parameters(&self, _: f64, _: f64, _: usize) {}
+        fn report_elapsed(&self, _: u128) {}
+        fn report_step_estimate(&self, _: f64, _: u64, _: usize) {}
+        fn report_schedule(&self, _: usize, _: f64, _: Schedule, _: String) {}
+        fn report_progress(&self, _: usize, _: String) {}
+        fn report_status(&self, _: usize, _: usize, _: f64, _: f64) {}
+        fn report_early_stop(&self, _: usize, _: usize) {}
+        fn report_reheat(&self, _: usize, _: f64) {}
+        fn report_solution(&self, _: Config, _: String, _: bool) {}
+        fn report_restarts(&self, _: &[f64]) {}
+        fn report_stage(&self, _: usize, _: usize, _: usize) {}
+        fn report_warm_start(&self, _: &[(String, f64)], _: usize) {}
+        fn report_pareto_front(&self, _: &[String], front: Vec<(Config, Vec<f64>, String)>) {
+            *self.0.lock().unwrap() = front.into_iter().map(|(_, values, _)| values).collect();
+        }
+        fn report_checkpoint(&self, _: usize, _: Vec<u8>) {}
+        fn report_move(&self, _: TrajectoryEntry) {}
+    }
+
+    #[test]
+    fn destroy_and_repair_keeps_fixed_narrowed_and_groups() {
+        let constraints = r#"
+    elements:
+      - { element: 口 }
+      - { element: 木, keys: [b, c] }
+    groups:
+      - [日, 禾]
+"#;
+        let mut problem = testing::problem(&optimization(OBJECTIVE, constraints));
+        let element = |name: &str| problem.representation.element_repr[name];
+        let key = |name: char| problem.representation.key_repr[&name];
+        let (kou, mu, ri, he) = (element("口"), element("木"), element("日"), element("禾"));
+        let (a, b, c) = (key('a'), key('b'), key('c'));
+        let mut candidate = problem.initial.clone();
+        for _ in 0..200 {
+            candidate = problem.destroy_and_repair(&candidate);
+            assert_eq!(candidate[kou], a);
+            assert!(candidate[mu] == b || candidate[mu] == c);
+            assert_eq!(candidate[ri], candidate[he]);
+        }
+    }
+
+    #[test]
+    fn destroy_and_repair_keeps_different_elements_apart() {
+        let constraints = r#"
+    relations:
+      - { elements: [口, 木, 米], relation: different }
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        let elements: Vec<usize> = ["口", "木", "米"]
+            .iter()
+            .map(|x| problem.representation.element_repr[*x])
+            .collect();
+        let mut candidate = problem.initial.clone();
+        for _ in 0..200 {
+            candidate = problem.destroy_and_repair(&candidate);
+            for (i, first) in elements.iter().enumerate() {
+                for second in &elements[i + 1..] {
+                    assert_ne!(candidate[*first], candidate[*second]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_candidate_keeps_different_elements_apart() {
+        let constraints = r#"
+    relations:
+      - { elements: [口, 日], relation: different }
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        let (kou, ri) = (
+            problem.representation.element_repr["口"],
+            problem.representation.element_repr["日"],
+        );
+        let candidate = problem.greedy_candidate().unwrap();
+        assert_ne!(candidate[kou], candidate[ri]);
+    }
+
+    #[test]
+    fn greedy_candidate_respects_capacity() {
+        let constraints = r#"
+    max_elements_per_key: 2
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        let candidate = problem.greedy_candidate().unwrap();
+        assert_eq!(problem.constraints.overflow(&candidate), 0);
+        let mut candidate = problem.initial.clone();
+        for _ in 0..200 {
+            candidate = problem.destroy_and_repair(&candidate);
+            assert_eq!(problem.constraints.overflow(&candidate), 0);
+        }
+    }
+
+    #[test]
+    fn greedy_placement_respects_conditionals() {
+        let constraints = r#"
+    conditionals:
+      - { if: { element: 口, keys: [a] }, then: { element: 米, keys: [c] } }
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        let conditional = problem.constraints.conditionals[0].clone();
+        let candidate = problem.greedy_candidate().unwrap();
+        assert!(!conditional.violated(&candidate));
+        let mut candidate = problem.initial.clone();
+        for _ in 0..200 {
+            candidate = problem.destroy_and_repair(&candidate);
+            assert!(!conditional.violated(&candidate));
+        }
+    }
+
+    #[test]
+    fn greedy_candidate_satisfies_all_constraints() {
+        let constraints = r#"
+    elements:
+      - { element: 丨 }
+      - { element: 木, keys: [b, c] }
+    groups:
+      - [日, 禾]
+    relations:
+      - { elements: [口, 木], relation: different }
+    conditionals:
+      - { if: { element: 口, keys: [a] }, then: { element: 米, keys: [c] } }
+    max_elements_per_key: 3
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        let candidate = problem.greedy_candidate().unwrap();
+        assert_eq!(problem.constraints.violations(&candidate), 0);
+        problem.representation.initial = candidate;
+        assert!(Constraints::validate(&problem.representation).is_empty());
+    }
+
+    #[test]
+    fn greedy_candidate_reports_dead_end() {
+        // 口 先放到 a 上，之后米只能放在 d 或 e 上，不满足条件约束
+        let constraints = r#"
+    elements:
+      - { element: 米, keys: [d, e] }
+    conditionals:
+      - { if: { element: 口, keys: [a] }, then: { element: 米, keys: [c] } }
+"#;
+        let mut problem = testing::problem(&optimization(INDIFFERENT, constraints));
+        assert!(problem.greedy_candidate().is_err());
+    }
+
+    #[test]
+    fn multiobjective_configuration_is_checked_on_construction() {
+        let missing = format!("{OBJECTIVE}  metaheuristic:\n    algorithm: NSGA2\n");
+        assert!(testing::try_problem(&missing).is_err());
+        let nsga2 = r#"
+    nsga2:
+      population_size: 10
+      generations: 2
+      mutation_rate: 0.1
+      objectives: [characters_full.duplication, words_full.key_distribution]
+"#;
+        let unknown = format!("{missing}{nsga2}");
+        assert!(testing::try_problem(&unknown).is_err());
+        let known = unknown.replace("words_full.key_distribution", "words_full.duplication");
+        assert!(testing::try_problem(&known).is_ok());
+    }
+
+    #[test]
+    fn unknown_algorithm_is_rejected_on_construction() {
+        for algorithm in ["\"GA \"", "genetic", "simulated_annealing"] {
+            let metaheuristic = METAHEURISTIC.replace("SimulatedAnnealing", algorithm);
+            let error = testing::try_problem(&format!("{OBJECTIVE}{metaheuristic}")).err().unwrap();
+            assert!(error.message.contains("algorithm"), "{}", error.message);
+        }
+        for algorithm in ["SA", "GeneticAlgorithm", "HillClimbing"] {
+            let metaheuristic = METAHEURISTIC.replace("SimulatedAnnealing", algorithm);
+            assert!(testing::try_problem(&format!("{OBJECTIVE}{metaheuristic}")).is_ok());
+        }
+    }
+
+    #[test]
+    fn zero_finger_targets_are_rejected_on_construction() {
+        let objective = r#"
+  objective:
+    characters_full:
+      finger_load: 1.0
+    keyboard:
+      finger_load: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+"#;
+        let error = testing::try_problem(&format!("{objective}{METAHEURISTIC}")).err().unwrap();
+        assert!(error.message.contains("finger_load"), "{}", error.message);
+        let unused = objective.replace("finger_load: 1.0", "duplication: 1.0");
+        assert!(testing::try_problem(&format!("{unused}{METAHEURISTIC}")).is_ok());
+    }
+
+    #[test]
+    fn custom_scripts_see_the_current_codes() {
+        let custom = r#"
+    custom:
+      - name: 首码为 a 的字频
+        weight: 1.0
+        script: |
+          fn score(entries) {
+            let total = 0.0;
+            for e in entries { if e.code.starts_with("a") { total += e.frequency; } }
+            total
+          }
+"#;
+        let optimization = format!("{OBJECTIVE}{custom}{METAHEURISTIC}");
+        let mut problem = testing::problem(&optimization);
+        let mut moved = problem.initial.clone();
+        moved[problem.representation.element_repr["口"]] = problem.representation.key_repr[&'f'];
+        let initial = problem.initial.clone();
+        let score = |problem: &mut ElementPlacementProblem, candidate: &Solution| {
+            problem.rank_candidate(candidate).0.custom.unwrap()[0].1
+        };
+        let before = score(&mut problem, &initial);
+        let mut other = problem.clone();
+        let after = score(&mut other, &moved);
+        assert!(after < before);
+        assert_eq!(score(&mut problem, &initial), before);
+        assert_eq!(score(&mut testing::problem(&optimization), &moved), after);
+        assert_eq!(score(&mut other, &initial), before);
+    }
+
+    #[test]
+    fn custom_scripts_cannot_corrupt_later_evaluations() {
+        let custom = r#"
+    custom:
+      - name: 修改字词数组
+        weight: 1.0
+        script: |
+          fn score(entries) {
+            let total = 0.0;
+            for e in entries { if e.code.starts_with("a") { total += e.frequency; } }
+            for i in 0..entries.len() { entries[i].frequency = 0.0; entries[i].item = ""; }
+            entries.clear();
+            total
+          }
+"#;
+        let mut problem = testing::problem(&format!("{OBJECTIVE}{custom}{METAHEURISTIC}"));
+        let initial = problem.initial.clone();
+        let first = problem.rank_candidate(&initial).0.custom.unwrap()[0].1;
+        let second = problem.rank_candidate(&initial).0.custom.unwrap()[0].1;
+        assert!(first > 0.0);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn ant_colony_returns_a_feasible_layout() {
+        let mut problem = testing::problem(&optimization(INDIFFERENT, CONSTRAINED));
+        assert!(problem.constraints.violations(&problem.initial) > 0);
+        let parameters = ant_colony::Parameters {
+            ants: 5,
+            iterations: 20,
+            ..Default::default()
+        };
+        let candidate = ant_colony::solve(&mut problem, parameters, None, &SilentInterface);
+        assert_eq!(problem.constraints.violations(&candidate), 0);
+    }
+
+    #[test]
+    fn exhaustive_search_returns_a_feasible_layout() {
+        let mut problem = testing::problem(&optimization(INDIFFERENT, CONSTRAINED));
+        let parameters = exhaustive::Parameters::default();
+        let candidate = exhaustive::solve(&mut problem, parameters, &SilentInterface);
+        assert_eq!(problem.constraints.violations(&candidate), 0);
+        // 方案数量超过上限时在构造问题时报错
+        let limit = "    exhaustive:\n      max_candidates: 100\n";
+        let limited = format!("{}{limit}", optimization(INDIFFERENT, CONSTRAINED));
+        let limited = limited.replace("SimulatedAnnealing", "Exhaustive");
+        let error = testing::try_problem(&limited).err().unwrap();
+        assert!(error.message.contains("max_candidates"), "{}", error.message);
+    }
+
+    #[test]
+    fn beam_search_keeps_the_layout_feasible() {
+        let mut problem = testing::problem(&optimization(INDIFFERENT, CONSTRAINED));
+        let parameters = exhaustive::Parameters::default();
+        problem.initial = exhaustive::solve(&mut problem, parameters, &SilentInterface);
+        assert_eq!(problem.constraints.violations(&problem.initial), 0);
+        let parameters = beam_search::Parameters {
+            elements: 10,
+            width: 3,
+        };
+        let candidate = beam_search::search(&mut problem, parameters, &SilentInterface);
+        assert_eq!(problem.constraints.violations(&candidate), 0);
+    }
+
+    #[test]
+    fn warm_start_rejects_infeasible_candidates() {
+        let mut problem = testing::problem(&optimization(INDIFFERENT, CONSTRAINED));
+        let infeasible = problem.initial.clone();
+        assert!(problem.constraints.violations(&infeasible) > 0);
+        let parameters = exhaustive::Parameters::default();
+        let feasible = exhaustive::solve(&mut problem, parameters, &SilentInterface);
+        let candidates = vec![("feasible".to_string(), feasible.clone())];
+        problem.warm_start(candidates, &SilentInterface).unwrap();
+        assert_eq!(problem.initial, feasible);
+        let candidates = vec![("infeasible".to_string(), infeasible)];
+        let error = problem.warm_start(candidates, &SilentInterface).err().unwrap();
+        assert!(error.message.contains("infeasible"), "{}", error.message);
+    }
+
+    #[test]
+    fn nsga2_front_has_no_dominated_members() {
+        let mut problem = testing::problem(&format!("{OBJECTIVE}{METAHEURISTIC}"));
+        let parameters = nsga2::Parameters {
+            population_size: 20,
+            generations: 5,
+            mutation_rate: 0.5,
+            objectives: vec![
+                "characters_full.duplication".to_string(),
+                "characters_full.pair_equivalence".to_string(),
+            ],
+        };
+        let recorder = FrontRecorder(Mutex::new(vec![]));
+        nsga2::solve(&mut problem, parameters, &recorder);
+        let front = recorder.0.into_inner().unwrap();
+        assert!(!front.is_empty());
+        let dominates = |a: &[f64], b: &[f64]| {
+            a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+        };
+        for a in &front {
+            for b in &front {
+                assert!(!dominates(a, b), "{:?} 支配 {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_chains_are_reproducible() {
+        let optimization = format!("{OBJECTIVE}{METAHEURISTIC}");
+        // 步数跨过几次迁移，这样链之间的交流也在检查的范围内
+        let solve = || {
+            let mut problem = testing::problem(&optimization);
+            let initial = problem.initial.clone();
+            let before = problem.rank_candidate(&initial).1;
+            let candidate = parallel_annealing::solve(
+                &mut problem,
+                annealing(25000),
+                3,
+                None,
+                None,
+                &SilentInterface,
+            );
+            assert!(problem.rank_candidate(&candidate).1 <= before);
+            candidate
+        };
+        assert_eq!(solve(), solve());
+    }
+
+    #[test]
+    fn parallel_tempering_improves_and_is_reproducible() {
+        let optimization = format!("{OBJECTIVE}{METAHEURISTIC}");
+        let solve = || {
+            let mut problem = testing::problem(&optimization);
+            let initial = problem.initial.clone();
+            let before = problem.rank_candidate(&initial).1;
+            let parameters = parallel_tempering::Parameters {
+                replicas: 3,
+                exchange_interval: 100,
+            };
+            let candidate = parallel_tempering::solve(
+                &mut problem,
+                annealing(3000),
+                parameters,
+                None,
+                &SilentInterface,
+            );
+            let after = problem.rank_candidate(&candidate).1;
+            assert!(after < before, "{} {}", after, before);
+            candidate
+        };
+        assert_eq!(solve(), solve());
+    }
+
+    #[test]
+    fn memetic_improves_and_is_reproducible() {
+        let optimization = format!("{OBJECTIVE}{METAHEURISTIC}");
+        let solve = || {
+            let mut problem = testing::problem(&optimization);
+            let initial = problem.initial.clone();
+            let before = problem.rank_candidate(&initial).1;
+            let parameters = memetic::Parameters {
+                population_size: 4,
+                generations: 5,
+                offspring: 2,
+                burst_steps: 200,
+            };
+            let candidate =
+                memetic::solve(&mut problem, annealing(0), parameters, None, &SilentInterface);
+            let after = problem.rank_candidate(&candidate).1;
+            assert!(after < before, "{} {}", after, before);
+            candidate
+        };
+        assert_eq!(solve(), solve());
+    }
+}
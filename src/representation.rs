@@ -1,25 +1,71 @@
 //! 内部数据结构的表示和定义
 
 use crate::{
-    config::{Config, Mapped, MappedKey},
+    config::{
+        Config, LongSequencePolicy, Mapped, MappedKey, PaddingConfig, PaddingRule,
+        PronunciationConfig, PronunciationPosition, ShiftConfig, SyllablePart,
+    },
     error::Error,
+    objectives::fingering::{FingeringCounts, Keyboard, KeyboardGeometry, FINGERING_TYPES},
 };
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// 编码空间的上限。编码空间是按键数的最大码长次方，各种指标都要按它预先计算成数组
+const MAX_SPACE: usize = 1 << 27;
+
+/// 拼音的声母，按长度从长到短排列，以便优先匹配 zh、ch、sh；y 和 w 也当作声母
+const INITIALS: [&str; 23] = [
+    "zh", "ch", "sh", "b", "p", "m", "f", "d", "t", "n", "l", "g", "k", "h", "j", "q", "x", "r",
+    "z", "c", "s", "y", "w",
+];
 
 pub type RawSequenceMap = HashMap<char, String>;
 pub type WordList = Vec<String>;
+/// 简繁对照表：每个简体字对应的所有繁体字
+pub type Variants = HashMap<char, Vec<char>>;
+/// 末笔字型表：每个字的末笔（1 横、2 竖、3 撇、4 点、5 折）和字型（1 左右、2 上下、3 杂合）
+pub type Identifiers = HashMap<char, (u8, u8)>;
+/// 读音表：单字对应按常用程度排列的所有读音，词语对应词中每个字的读音，读音都是带调拼音
+pub type Pronunciation = HashMap<String, Vec<String>>;
 pub type KeyDistribution = HashMap<char, f64>;
 pub type PairEquivalence = HashMap<String, f64>;
+pub type TripleEquivalence = HashMap<String, f64>;
 pub type Frequency<T> = HashMap<T, u64>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assets {
     pub character_frequency: Frequency<char>,
     pub word_frequency: Frequency<String>,
     pub key_distribution: KeyDistribution,
     pub pair_equivalence: PairEquivalence,
+    // 三键当量是可选的，没有提供时为空
+    #[serde(default)]
+    pub triple_equivalence: TripleEquivalence,
+    // 用于模拟动态调频的语料，是可选的，没有提供时为空
+    #[serde(default)]
+    pub corpus: String,
+    // 标点符号的频率是可选的，没有提供时为空
+    #[serde(default)]
+    pub punctuation_frequency: Frequency<char>,
+    // 键盘几何是可选的，没有提供时为空
+    #[serde(default)]
+    pub keyboard_geometry: KeyboardGeometry,
+    // 读音表只在音形方案中使用，没有提供时为空
+    #[serde(default)]
+    pub pronunciation: Pronunciation,
+    // 字集的名称和其中所有的字，只在限定字集时使用，没有提供时为空
+    #[serde(default)]
+    pub character_sets: HashMap<String, String>,
+    // 繁体字频和简繁对照表只在评测繁体时使用，没有提供时为空
+    #[serde(default)]
+    pub traditional_frequency: Frequency<char>,
+    #[serde(default)]
+    pub variants: Variants,
+    // 末笔字型表只在用识别码补码时使用，没有提供时为空
+    #[serde(default)]
+    pub identifiers: Identifiers,
 }
 
 /// 元素用一个无符号整数表示
@@ -46,6 +92,12 @@ pub type KeyMap = Vec<Key>;
 /// 每个编码上占据了几个候选
 pub type Occupation = Vec<bool>;
 
+/// 字母表的表示：进制、字母表进制、选择键、按键到整数的映射和整数到按键的映射
+type AlphabetRepr = (usize, usize, Vec<Key>, HashMap<char, Key>, HashMap<Key, char>);
+
+/// 元素映射的表示：初始布局、元素到整数的映射和整数到元素的映射
+type KeyMapRepr = (KeyMap, HashMap<String, Element>, HashMap<Element, String>);
+
 #[derive(Debug, Serialize)]
 pub struct Entry {
     pub item: String,
@@ -53,20 +105,82 @@ pub struct Entry {
     pub short: Option<String>,
 }
 
+/// 一个单字简码的分配结果：取得简码的字，以及全码前缀相同、却没有取得这个简码的字
+#[derive(Debug, Serialize)]
+pub struct ShortCodeAssignment {
+    pub code: String,
+    pub item: String,
+    pub contenders: Vec<String>,
+}
+
+/// 反查表中的一项：一个编码，以及按候选顺序排列的所有使用这个编码的字词
+#[derive(Debug, Serialize)]
+pub struct CodeEntry {
+    pub code: String,
+    pub items: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct EncodeExport {
     pub characters: Vec<Entry>,
     pub words: Option<Vec<Entry>>,
+    pub punctuation: Option<Vec<Entry>>,
+    pub short_codes: Option<Vec<ShortCodeAssignment>>,
+    pub codes: Vec<CodeEntry>,
 }
 
-#[derive(Debug)]
+/// 按照当前方案输入一段文本的结果
+#[derive(Debug, Serialize)]
+pub struct SimulationExport {
+    /// 击键序列，不在字词表中的字符原样保留
+    pub keystrokes: String,
+    /// 输入的汉字数
+    pub characters: usize,
+    /// 输入这些汉字所用的击键数，包括选择键
+    pub keys: usize,
+    /// 需要选重的次数
+    pub selections: usize,
+}
+
+/// 优化轨迹中的一次元素移动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveRecord {
+    pub element: String,
+    pub from: char,
+    pub to: char,
+}
+
+/// 优化轨迹中的一步：在第 step 步接受了一个新方案，它相对于旧方案有若干处元素移动，目标函数变化了 delta
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrajectoryEntry {
+    pub step: usize,
+    pub changes: Vec<MoveRecord>,
+    pub delta: f64,
+}
+
+/// 增量编码所需的状态：上一次编码所用的布局，以及每个编码上有哪些字词
+#[derive(Debug, Clone, Default)]
+pub struct CodeIndex {
+    pub keymap: Option<KeyMap>,
+    // 每个编码上的字词序号，按频率从高到低排列，第一个是首选
+    pub buckets: HashMap<Code, Vec<usize>>,
+    pub occupation: Occupation,
+}
+
+#[derive(Debug, Clone)]
 pub struct Buffer {
     pub characters_full: Codes,
     pub characters_short: Option<Codes>,
     pub words_full: Option<Codes>,
+    pub characters_index: CodeIndex,
+    pub words_index: CodeIndex,
+    // 繁体单字全码，只在评测繁体时由目标函数初始化
+    pub traditional_full: Option<Codes>,
+    pub traditional_index: CodeIndex,
 }
 
 /// 配置表示是对配置文件的进一步封装，除了保存一份配置文件本身之外，还根据配置文件的内容推导出用于各种转换的映射
+#[derive(Clone)]
 pub struct Representation {
     pub config: Config,
     pub initial: KeyMap,
@@ -87,10 +201,14 @@ impl Mapped {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn normalize(&self) -> Vec<MappedKey> {
         match self {
             Mapped::Advanced(vector) => vector.clone(),
-            Mapped::Basic(string) => string.chars().map(|x| MappedKey::Ascii(x)).collect(),
+            Mapped::Basic(string) => string.chars().map(MappedKey::Ascii).collect(),
         }
     }
 }
@@ -99,7 +217,29 @@ pub fn assemble(element: &String, index: usize) -> String {
     if index == 0 {
         element.to_string()
     } else {
-        format!("{}.{}", element.to_string(), index)
+        format!("{}.{}", element, index)
+    }
+}
+
+/// 键盘映射中名称为单个标点符号的项不是元素，而是这个标点的固定编码
+pub fn is_symbol(element: &str) -> bool {
+    let mut chars = element.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            c.is_ascii_punctuation()
+                || matches!(c,
+                    '\u{00B7}'
+                    | '\u{2010}'..='\u{206F}'
+                    | '\u{3001}'..='\u{3004}'
+                    | '\u{3008}'..='\u{3011}'
+                    | '\u{3014}'..='\u{301F}'
+                    | '\u{FF01}'..='\u{FF0F}'
+                    | '\u{FF1A}'..='\u{FF20}'
+                    | '\u{FF3B}'..='\u{FF40}'
+                    | '\u{FF5B}'..='\u{FF65}'
+                )
+        }
+        _ => false,
     }
 }
 
@@ -127,16 +267,7 @@ impl Representation {
     /// n + 1, ..., m = 所有选择键
     pub fn transform_alphabet(
         config: &Config,
-    ) -> Result<
-        (
-            usize,
-            usize,
-            Vec<Key>,
-            HashMap<char, Key>,
-            HashMap<Key, char>,
-        ),
-        Error,
-    > {
+    ) -> Result<AlphabetRepr, Error> {
         let mut key_repr: HashMap<char, Key> = HashMap::new();
         let mut repr_key: HashMap<Key, char> = HashMap::new();
         let mut index = 1_usize;
@@ -155,12 +286,12 @@ impl Representation {
             .select_keys
             .as_ref()
             .unwrap_or(&default_select_keys);
-        if select_keys.len() < 1 {
+        if select_keys.is_empty() {
             return Err("选择键不能为空！".into());
         }
         let mut parsed_select_keys: Vec<Key> = vec![];
         for key in select_keys {
-            if key_repr.contains_key(&key) {
+            if key_repr.contains_key(key) {
                 return Err("编码键有重复！".into());
             };
             key_repr.insert(*key, index);
@@ -182,15 +313,21 @@ impl Representation {
     pub fn transform_keymap(
         config: &Config,
         key_repr: &HashMap<char, Key>,
-    ) -> Result<(KeyMap, HashMap<String, Element>, HashMap<Element, String>), Error> {
+    ) -> Result<KeyMapRepr, Error> {
         let mut keymap: KeyMap = Vec::new();
         let mut forward_converter: HashMap<String, usize> = HashMap::new();
         let mut reverse_converter: HashMap<usize, String> = HashMap::new();
-        for (element, mapped) in &config.form.mapping {
+        // 按元素名称排序后再编号，保证每次运行得到的编号都相同
+        let mut mapping: Vec<_> = config.form.mapping.iter().collect();
+        mapping.sort_by(|a, b| a.0.cmp(b.0));
+        for (element, mapped) in mapping {
+            if is_symbol(element) {
+                continue;
+            }
             let normalized = mapped.normalize();
             for (index, mapped_key) in normalized.iter().enumerate() {
                 if let MappedKey::Ascii(x) = mapped_key {
-                    if let Some(key) = key_repr.get(&x) {
+                    if let Some(key) = key_repr.get(x) {
                         let name = assemble(element, index);
                         forward_converter.insert(name.clone(), keymap.len());
                         reverse_converter.insert(keymap.len(), name.clone());
@@ -206,6 +343,70 @@ impl Representation {
         Ok((keymap, forward_converter, reverse_converter))
     }
 
+    /// 读取另一个方案文件中的元素布局，按照当前方案的元素编号转换为键盘映射。两个方案的元素集合必须完全相同
+    pub fn read_keymap(&self, config: &Config) -> Result<KeyMap, Error> {
+        let (keymap, forward_converter, _) = Self::transform_keymap(config, &self.key_repr)?;
+        if keymap.len() != self.initial.len() {
+            return Err(format!(
+                "元素数量为 {}，与当前方案的 {} 不一致",
+                keymap.len(),
+                self.initial.len()
+            )
+            .into());
+        }
+        let mut result = self.initial.clone();
+        for (name, index) in &self.element_repr {
+            let other = forward_converter
+                .get(name)
+                .ok_or(format!("缺少元素 {name}"))?;
+            result[*index] = keymap[*other];
+        }
+        Ok(result)
+    }
+
+    /// 读取参考布局，按照当前方案的元素编号转换为每个元素在参考布局中的按键。参考布局中没有的元素为 None
+    pub fn read_reference(
+        &self,
+        mapping: &HashMap<String, Mapped>,
+    ) -> Result<Vec<Option<Key>>, Error> {
+        let mut config = self.config.clone();
+        config.form.mapping = mapping.clone();
+        let (keymap, forward_converter, _) = Self::transform_keymap(&config, &self.key_repr)?;
+        let mut result = vec![None; self.initial.len()];
+        for (name, index) in &self.element_repr {
+            result[*index] = forward_converter.get(name).map(|x| keymap[*x]);
+        }
+        Ok(result)
+    }
+
+    /// 把两个键盘映射之间的差异转换为便于阅读和保存的元素移动记录
+    pub fn describe_moves(&self, old: &KeyMap, new: &KeyMap) -> Vec<MoveRecord> {
+        (0..old.len())
+            .filter(|element| old[*element] != new[*element])
+            .map(|element| MoveRecord {
+                element: self.repr_element[&element].clone(),
+                from: self.repr_key[&old[element]],
+                to: self.repr_key[&new[element]],
+            })
+            .collect()
+    }
+
+    /// 在键盘映射上重新执行一组元素移动
+    pub fn apply_moves(&self, keymap: &mut KeyMap, moves: &[MoveRecord]) -> Result<(), Error> {
+        for MoveRecord { element, to, .. } in moves {
+            let element = self
+                .element_repr
+                .get(element)
+                .ok_or(format!("轨迹中的元素 {element} 不在当前方案中"))?;
+            let key = self
+                .key_repr
+                .get(to)
+                .ok_or(format!("轨迹中的键 {to} 不在当前方案的字母表中"))?;
+            keymap[*element] = *key;
+        }
+        Ok(())
+    }
+
     /// 读取拆分表，将拆分序列中的每一个元素按照先前确定的元素 -> 整数映射来转换为整数向量
     pub fn transform_elements(
         &self,
@@ -213,32 +414,214 @@ impl Representation {
     ) -> Result<SequenceMap, Error> {
         let mut sequence_map = SequenceMap::new();
         let max_length = self.config.encoder.max_length;
-        if max_length >= 6 {
-            return Err("目前暂不支持最大码长大于等于 6 的方案计算！".into());
+        // 各种指标都按编码空间预先计算成数组，编码空间不能太大
+        let space = self.radix.checked_pow(max_length as u32);
+        if max_length == 0 || space.is_none_or(|x| x > MAX_SPACE) {
+            return Err(format!(
+                "最大码长 {max_length} 不合法，或者字母表和选择键共 {} 个按键时编码空间过大",
+                self.radix - 1
+            )
+            .into());
         }
         for (char, sequence) in raw_sequence_map {
-            let mut converted_elems: Vec<usize> = Vec::new();
-            let sequence: Vec<_> = sequence.split(' ').map(|x| x.to_string()).collect();
-            let length = sequence.len();
-            if length > max_length {
-                return Err(format!(
-                    "汉字「{char}」包含的元素数量为 {length}，超过了最大码长 {max_length}"
-                )
-                .into());
+            let first = sequence.split('|').next().unwrap_or_default();
+            sequence_map.insert(*char, self.transform_sequence(*char, first)?);
+        }
+        Ok(sequence_map)
+    }
+
+    /// 给元素数量不足的字补码：重复最后一个元素直到补足长度，或者加上一个识别码。
+    /// 识别码元素的名称为「识:」加上末笔和字型，例如「识:12」是末笔为横的上下型字
+    pub fn pad_sequence(
+        &self,
+        config: &PaddingConfig,
+        char: char,
+        sequence: &mut Sequence,
+        identifiers: &Identifiers,
+    ) -> Result<(), Error> {
+        let length = config.length.unwrap_or(self.config.encoder.max_length);
+        if sequence.is_empty() || sequence.len() >= length {
+            return Ok(());
+        }
+        match config.rule.unwrap_or_default() {
+            PaddingRule::RepeatLast => {
+                let last = *sequence.last().unwrap(); // 上面已经判断过不为空
+                sequence.resize(length, last);
+            }
+            PaddingRule::Identifier => {
+                let (stroke, shape) = identifiers
+                    .get(&char)
+                    .ok_or(format!("字 {char} 在末笔字型表中没有记录，无法加识别码"))?;
+                let name = format!("识:{stroke}{shape}");
+                let element = self
+                    .element_repr
+                    .get(&name)
+                    .ok_or(format!("识别码元素 {name} 不在键盘映射中"))?;
+                sequence.push(*element);
+            }
+        }
+        Ok(())
+    }
+
+    /// 一字多拆时，拆分表中以「|」分隔的第二种及以后的拆分，只列出有多种拆分的字
+    pub fn transform_alternatives(
+        &self,
+        raw_sequence_map: &RawSequenceMap,
+    ) -> Result<HashMap<char, Vec<Sequence>>, Error> {
+        let mut alternatives = HashMap::new();
+        for (char, sequence) in raw_sequence_map {
+            let sequences: Result<Vec<_>, _> = sequence
+                .split('|')
+                .skip(1)
+                .map(|x| self.transform_sequence(*char, x))
+                .collect();
+            let sequences = sequences?;
+            if !sequences.is_empty() {
+                alternatives.insert(*char, sequences);
             }
-            for element in &sequence {
-                if let Some(number) = self.element_repr.get(element) {
-                    converted_elems.push(*number);
-                } else {
+        }
+        Ok(alternatives)
+    }
+
+    /// 把一个字的拆分序列（以空格分隔的元素名称）转换为整数向量，元素过多时按设置截取
+    pub fn transform_sequence(&self, char: char, sequence: &str) -> Result<Sequence, Error> {
+        let max_length = self.config.encoder.max_length;
+        let policy = self.config.encoder.long_sequence_policy.unwrap_or_default();
+        let mut converted_elems: Vec<usize> = Vec::new();
+        let mut sequence: Vec<_> = sequence.trim().split(' ').map(|x| x.to_string()).collect();
+        let length = sequence.len();
+        if length > max_length {
+            match policy {
+                LongSequencePolicy::Error => {
                     return Err(format!(
-                        "汉字「{char}」包含的元素「{element}」无法在键盘映射中找到"
+                        "汉字「{char}」包含的元素数量为 {length}，超过了最大码长 {max_length}"
                     )
                     .into());
                 }
+                LongSequencePolicy::Truncate => sequence.truncate(max_length),
+                LongSequencePolicy::FirstAndLast => {
+                    let last = sequence.pop().unwrap(); // 元素数量超过最大码长，不会为空
+                    sequence.truncate(max_length - 1);
+                    sequence.push(last);
+                }
             }
-            sequence_map.insert(*char, converted_elems);
         }
-        Ok(sequence_map)
+        for element in &sequence {
+            if let Some(number) = self.element_repr.get(element) {
+                converted_elems.push(*number);
+            } else {
+                return Err(format!(
+                    "汉字「{char}」包含的元素「{element}」无法在键盘映射中找到"
+                )
+                .into());
+            }
+        }
+        Ok(converted_elems)
+    }
+
+    /// 把一个带调拼音（如 zhong1）拆成声母、韵母和声调，转换为「声:zh」「韵:ong」「调:1」这样的元素名称。
+    /// 韵母按照拼写来取，例如 ju 的韵母是 u；没有标调的按轻声处理
+    pub fn syllable_elements(syllable: &str, parts: &[SyllablePart]) -> Result<Vec<String>, Error> {
+        let syllable = syllable.to_lowercase().replace('ü', "v");
+        let letters = syllable.trim_end_matches(|x: char| x.is_ascii_digit());
+        let tone = &syllable[letters.len()..];
+        let tone = if tone.is_empty() { "5" } else { tone };
+        if letters.is_empty() || !letters.chars().all(|x| x.is_ascii_lowercase()) {
+            return Err(format!("读音「{syllable}」不是合法的带调拼音").into());
+        }
+        // 整个音节只有辅音（如 m、ng）时当作零声母
+        let initial = INITIALS
+            .iter()
+            .find(|x| letters.starts_with(**x) && letters.len() > x.len())
+            .copied()
+            .unwrap_or("");
+        let last = &letters[initial.len()..];
+        let initial = if initial.is_empty() { "0" } else { initial };
+        let elements = parts
+            .iter()
+            .map(|part| match part {
+                SyllablePart::Initial => format!("声:{initial}"),
+                SyllablePart::Final => format!("韵:{last}"),
+                SyllablePart::Tone => format!("调:{tone}"),
+            })
+            .collect();
+        Ok(elements)
+    }
+
+    /// 把一个读音的元素和字形元素合成音形方案的拆分序列
+    fn merge_pronunciation(
+        config: &PronunciationConfig,
+        syllable: &str,
+        shape: &str,
+    ) -> Result<String, Error> {
+        let default_parts = [SyllablePart::Initial, SyllablePart::Final];
+        let parts = config.parts.as_deref().unwrap_or(&default_parts);
+        let sound = Self::syllable_elements(syllable, parts)?;
+        let shape = shape.split(' ').filter(|x| !x.is_empty()).map(|x| x.to_string());
+        let merged: Vec<String> = match config.position.unwrap_or_default() {
+            PronunciationPosition::Before => sound.into_iter().chain(shape).collect(),
+            PronunciationPosition::After => shape.chain(sound).collect(),
+        };
+        Ok(merged.join(" "))
+    }
+
+    /// 音形方案：给拆分表中的每个字加上它第一个（最常用的）读音的元素
+    pub fn transform_pronunciation(
+        config: &PronunciationConfig,
+        raw_sequence_map: &RawSequenceMap,
+        pronunciation: &Pronunciation,
+    ) -> Result<RawSequenceMap, Error> {
+        let mut result = RawSequenceMap::new();
+        for (char, shape) in raw_sequence_map {
+            let syllable = pronunciation
+                .get(&char.to_string())
+                .and_then(|x| x.first())
+                .ok_or(format!("汉字「{char}」在读音表中没有读音"))?;
+            // 一字多拆时每一种拆分都要加上读音元素
+            let merged: Result<Vec<_>, _> = shape
+                .split('|')
+                .map(|x| Self::merge_pronunciation(config, syllable, x))
+                .collect();
+            result.insert(*char, merged?.join("|"));
+        }
+        Ok(result)
+    }
+
+    /// 音形方案：读音表中单独列出读音的词语（一般是含有多音字的词），按照词中每个字的实际读音求出各个字的拆分序列。
+    /// 词中有字不在拆分表中时，这个词无法构词，不需要求出
+    pub fn transform_word_pronunciation(
+        &self,
+        config: &PronunciationConfig,
+        raw_sequence_map: &RawSequenceMap,
+        pronunciation: &Pronunciation,
+        words: &[String],
+    ) -> Result<HashMap<String, Vec<Sequence>>, Error> {
+        let mut result = HashMap::new();
+        for word in words {
+            let Some(syllables) = pronunciation.get(word) else {
+                continue;
+            };
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len() < 2 {
+                continue;
+            }
+            if syllables.len() != chars.len() {
+                return Err(format!("词语「{word}」的读音与字数不一致").into());
+            }
+            let mut sequences = vec![];
+            for (char, syllable) in chars.iter().zip(syllables) {
+                let Some(shape) = raw_sequence_map.get(char) else {
+                    break;
+                };
+                let shape = shape.split('|').next().unwrap_or_default();
+                let merged = Self::merge_pronunciation(config, syllable, shape)?;
+                sequences.push(self.transform_sequence(*char, &merged)?);
+            }
+            if sequences.len() == chars.len() {
+                result.insert(word.clone(), sequences);
+            }
+        }
+        Ok(result)
     }
 
     /// 根据一个计算中得到的元素布局来生成一份新的配置文件，其余内容不变直接复制过来
@@ -250,6 +633,10 @@ impl Representation {
             *self.repr_key.get(current_mapped).unwrap() // 同上
         };
         for (element, mapped) in &self.config.form.mapping {
+            // 标点的编码是固定的，原样保留
+            if is_symbol(element) {
+                continue;
+            }
             let new_element = element.clone();
             let new_mapped = match mapped {
                 Mapped::Basic(string) => {
@@ -284,8 +671,8 @@ impl Representation {
         let mut chars: Vec<char> = Vec::new();
         let mut remainder = code;
         while remainder > 0 {
-            let k = remainder % self.radix as usize;
-            remainder /= self.radix as usize;
+            let k = remainder % self.radix;
+            remainder /= self.radix;
             if k == 0 {
                 continue;
             }
@@ -295,6 +682,49 @@ impl Representation {
         chars
     }
 
+    /// 把一个按键序列转换为编码，按键序列不能超过最大码长
+    pub fn parse_code(&self, keys: &str) -> Result<Code, Error> {
+        let mut code = 0;
+        let mut weight = 1;
+        for key in keys.chars() {
+            let key = self
+                .key_repr
+                .get(&key)
+                .ok_or(format!("编码 {keys} 中的字符 {key} 并不在字母表中"))?;
+            code += key * weight;
+            weight *= self.radix;
+        }
+        let length = keys.chars().count();
+        if length == 0 || length > self.config.encoder.max_length {
+            return Err(format!("编码 {keys} 为空或者超过了最大码长").into());
+        }
+        Ok(code)
+    }
+
+    /// 读取所有标点符号的编码，包括 encoder.punctuation 中的和键盘映射中直接写出的，按标点排序
+    pub fn transform_punctuation(&self) -> Result<Vec<(char, Code)>, Error> {
+        let mut punctuation = self.config.encoder.punctuation.clone().unwrap_or_default();
+        for (element, mapped) in &self.config.form.mapping {
+            if !is_symbol(element) {
+                continue;
+            }
+            let symbol = element.chars().next().unwrap(); // is_symbol 保证了只有一个字符
+            let Mapped::Basic(keys) = mapped else {
+                return Err(format!("标点 {symbol} 的编码只能是按键组成的字符串").into());
+            };
+            if punctuation.insert(symbol, keys.clone()).is_some() {
+                return Err(
+                    format!("标点 {symbol} 同时在 form.mapping 和 encoder.punctuation 中定义").into(),
+                );
+            }
+        }
+        let mut result = vec![];
+        for (symbol, keys) in punctuation {
+            result.push((symbol, self.parse_code(&keys)?));
+        }
+        Ok(result)
+    }
+
     /// 根据编码字符和未归一化的键位分布，生成一个理想的键位分布
     pub fn generate_ideal_distribution(&self, key_distribution: &HashMap<char, f64>) -> Vec<f64> {
         let mut result: Vec<f64> = (0..self.alphabet_radix)
@@ -312,6 +742,33 @@ impl Representation {
         result
     }
 
+    /// 为速度当量表中完全没有数据的编码键补上数据：它与其他按键组成的组合都取表中所有组合的平均值。
+    /// 标点、数字等按键加入字母表时往往缺少速度当量，如果按零计算，优化时反而会偏向这些按键
+    pub fn complete_pair_equivalence(
+        &self,
+        pair_equivalence: &HashMap<String, f64>,
+    ) -> HashMap<String, f64> {
+        let mut result = pair_equivalence.clone();
+        if pair_equivalence.is_empty() {
+            return result;
+        }
+        let mean = pair_equivalence.values().sum::<f64>() / pair_equivalence.len() as f64;
+        let known: HashSet<char> = pair_equivalence.keys().flat_map(|x| x.chars()).collect();
+        let keys: Vec<char> =
+            (1..self.radix).filter_map(|x| self.repr_key.get(&x).copied()).collect();
+        for key in keys.iter().take(self.alphabet_radix - 1) {
+            if known.contains(key) {
+                continue;
+            }
+            for other in keys.iter() {
+                for pair in [[*key, *other], [*other, *key]] {
+                    result.entry(pair.iter().collect()).or_insert(mean);
+                }
+            }
+        }
+        result
+    }
+
     /// 将编码空间内所有的编码组合预先计算好速度当量
     /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
     pub fn transform_pair_equivalence(&self, pair_equivalence: &HashMap<String, f64>) -> Vec<f64> {
@@ -332,6 +789,74 @@ impl Representation {
         result
     }
 
+    /// 将编码空间内所有的编码组合预先计算好三键当量，即所有相邻三个键的当量之和
+    /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
+    pub fn transform_triple_equivalence(
+        &self,
+        triple_equivalence: &HashMap<String, f64>,
+    ) -> Vec<f64> {
+        let mut result: Vec<f64> = vec![];
+        for code in 0..self.get_space() {
+            let chars = self.repr_code(code);
+            let mut total = 0.0;
+            for triple in chars.windows(3) {
+                let triple: String = triple.iter().collect();
+                total += triple_equivalence.get(&triple).unwrap_or(&0.0);
+            }
+            result.push(total);
+        }
+        result
+    }
+
+    /// 将编码空间内所有的编码组合预先计算好各种指法类型出现的次数
+    /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
+    pub fn transform_fingering(&self, keyboard: &Keyboard) -> Vec<FingeringCounts> {
+        let mut result: Vec<FingeringCounts> = vec![];
+        for code in 0..self.get_space() {
+            let chars = self.repr_code(code);
+            let mut total = [0; FINGERING_TYPES];
+            for pair in chars.windows(2) {
+                for (count, value) in total.iter_mut().zip(keyboard.classify(pair[0], pair[1])) {
+                    *count += value;
+                }
+            }
+            result.push(total);
+        }
+        result
+    }
+
+    /// 将编码空间内所有的编码组合预先计算好手指移动距离
+    /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
+    pub fn transform_travel(&self, keyboard: &Keyboard, geometry: &KeyboardGeometry) -> Vec<f64> {
+        (0..self.get_space())
+            .map(|code| keyboard.travel(geometry, &self.repr_code(code)))
+            .collect()
+    }
+
+    /// 将编码空间内所有的编码组合预先计算好上档键的额外当量，即每个上档字符的代价与上档键和基础按键组合的当量之和
+    /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
+    pub fn transform_shift(
+        &self,
+        keyboard: &Keyboard,
+        shift: &ShiftConfig,
+        pair_equivalence: &HashMap<String, f64>,
+    ) -> Vec<f64> {
+        let cost = shift.cost.unwrap_or(0.0);
+        (0..self.get_space())
+            .map(|code| {
+                let mut total = 0.0;
+                for base in self.repr_code(code).iter().filter_map(|x| keyboard.unshift(*x)) {
+                    total += cost;
+                    if let Some(key) = shift.key {
+                        let pair: String = [key, base].iter().collect();
+                        total += pair_equivalence.get(&pair).unwrap_or(&0.0);
+                    }
+                }
+                total
+            })
+            .collect()
+    }
+
     /// 将编码空间内所有的编码组合预先计算好新速度当量（杏码算法）
     /// 按照这个字符串所对应的整数为下标，存储到一个大数组中
     pub fn transform_new_pair_equivalence(
@@ -347,9 +872,9 @@ impl Representation {
             }
             //遍历所有组合
             let mut combinations: Vec<String> = vec!["".to_string()];
-            for i in 1..chars.len() - 1 {
+            for char in &chars[1..chars.len() - 1] {
                 for j in 0..combinations.len() {
-                    combinations.push(format!("{}{}", combinations[j], chars[i]));
+                    combinations.push(format!("{}{}", combinations[j], char));
                 }
             }
             let mut total = 0.0;
@@ -385,8 +910,21 @@ impl Representation {
                 return Err(format!("正则表达式 {pattern} 无法解析").into());
             }
         }
+        let top_keys: Vec<char> = encoder.top_keys.as_deref().unwrap_or_default().chars().collect();
+        for key in &top_keys {
+            if !self.config.form.alphabet.contains(*key) {
+                return Err(format!("顶功键 {key} 不在字母表中").into());
+            }
+        }
         for code in 0..self.get_space() {
             let chars = self.repr_code(code);
+            // 下一个编码总是以顶功键开头，所以这样的编码不需要选择键
+            let is_topped = chars.first().is_some_and(|x| top_keys.contains(x))
+                && chars[1..].iter().all(|x| !top_keys.contains(x));
+            if is_topped {
+                result.push(true);
+                continue;
+            }
             let string: String = chars.iter().collect();
             let is_matched = if let Some(re) = &re {
                 re.is_match(&string)
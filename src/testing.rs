@@ -0,0 +1,147 @@
+//! 单元测试共用的小型方案：八个字根放在六个键上，再加上由它们组成的一些字和词
+
+use crate::{
+    config::Config,
+    constraints::Constraints,
+    encoder::DefaultEncoder,
+    error::Error,
+    objectives::Objective,
+    problem::ElementPlacementProblem,
+    representation::{Assets, RawSequenceMap, Representation},
+};
+use serde_json::json;
+
+/// 方案文件的公共部分，`optimization` 之下的 `constraints` 和 `metaheuristic` 由各个测试提供
+const CONFIG: &str = r#"
+version: "0.1"
+source: null
+form:
+  alphabet: abcdef
+  mapping:
+    口: a
+    日: a
+    木: b
+    禾: b
+    米: c
+    人: d
+    一: e
+    丨: f
+encoder:
+  max_length: 4
+  select_keys: ["_"]
+  rules:
+    - length_equal: 2
+      formula: AaAbBaBb
+    - length_in_range: [3, 10]
+      formula: AaBaCaZa
+"#;
+
+const ELEMENTS: [(char, &str); 16] = [
+    ('口', "口"),
+    ('日', "日"),
+    ('木', "木"),
+    ('禾', "禾"),
+    ('米', "米"),
+    ('人', "人"),
+    ('一', "一"),
+    ('丨', "丨"),
+    ('呆', "口 木"),
+    ('杏', "木 口"),
+    ('香', "禾 日"),
+    ('困', "口 木 一"),
+    ('旧', "丨 日"),
+    ('果', "日 木"),
+    ('来', "一 米 丨"),
+    ('从', "人 人"),
+];
+
+const WORDS: [&str; 4] = ["人口", "果木", "日来", "呆人"];
+
+type Frequencies = Vec<(String, u64)>;
+
+/// 按照给定的优化配置（缩进两格的 YAML，写在 `optimization` 之下）读取方案，编码器部分可以用 `encoder` 覆盖
+pub fn config(optimization: &str, encoder: Option<&str>) -> Config {
+    let content = format!("{CONFIG}optimization:{optimization}");
+    let mut config: Config = serde_yaml::from_str(&content).unwrap();
+    if let Some(encoder) = encoder {
+        config.encoder = serde_yaml::from_str(encoder).unwrap();
+    }
+    config
+}
+
+/// 字表和词表。combinations 为 true 时再加上八个字根两两组合成的 64 个虚构的字（用私用区的码位表示）以及由它们组成的词，
+/// 这样移动一个元素只影响一小部分字词，可以覆盖增量编码的路径
+fn entries(combinations: bool) -> (Vec<(char, String, u64)>, Frequencies) {
+    let mut characters: Vec<(char, String, u64)> = ELEMENTS
+        .iter()
+        .enumerate()
+        .map(|(i, (x, y))| (*x, y.to_string(), 100 - i as u64 * 5))
+        .collect();
+    let mut words: Frequencies = WORDS
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.to_string(), 40 - i as u64 * 5))
+        .collect();
+    if combinations {
+        let roots: Vec<char> = ELEMENTS[..8].iter().map(|(x, _)| *x).collect();
+        let mut generated = Vec::new();
+        for (i, first) in roots.iter().enumerate() {
+            for (j, second) in roots.iter().enumerate() {
+                let character = char::from_u32(0xE000 + (i * 8 + j) as u32).unwrap();
+                let frequency = 10 + ((i * 5 + j * 3) % 11) as u64;
+                characters.push((character, format!("{first} {second}"), frequency));
+                generated.push(character);
+            }
+        }
+        for (i, pair) in generated.chunks(2).enumerate() {
+            words.push((pair.iter().collect(), 3 + (i % 5) as u64));
+        }
+    }
+    (characters, words)
+}
+
+pub fn assets(combinations: bool) -> Assets {
+    let (characters, words) = entries(combinations);
+    let characters: serde_json::Map<String, serde_json::Value> = characters
+        .into_iter()
+        .map(|(x, _, frequency)| (x.to_string(), json!(frequency)))
+        .collect();
+    let words: serde_json::Map<String, serde_json::Value> =
+        words.into_iter().map(|(x, frequency)| (x, json!(frequency))).collect();
+    let keys: serde_json::Map<String, serde_json::Value> =
+        "abcdef".chars().map(|x| (x.to_string(), json!(1.0))).collect();
+    serde_json::from_value(json!({
+        "character_frequency": characters,
+        "word_frequency": words,
+        "key_distribution": keys,
+        "pair_equivalence": { "ab": 1.5, "ba": 1.2, "cd": 1.3 },
+    }))
+    .unwrap()
+}
+
+pub fn encoder(
+    representation: &Representation,
+    assets: &Assets,
+    combinations: bool,
+) -> DefaultEncoder {
+    let (characters, words) = entries(combinations);
+    let characters: RawSequenceMap = characters.into_iter().map(|(x, y, _)| (x, y)).collect();
+    let words = words.into_iter().map(|(x, _)| x).collect();
+    DefaultEncoder::new(representation, characters, words, assets).unwrap()
+}
+
+/// 按照给定的优化配置构造一个元素布局问题
+pub fn problem(optimization: &str) -> ElementPlacementProblem {
+    try_problem(optimization).unwrap()
+}
+
+/// 按照给定的优化配置构造一个元素布局问题，返回构造时的错误
+pub fn try_problem(optimization: &str) -> Result<ElementPlacementProblem, Error> {
+    let representation = Representation::new(config(optimization, None))?;
+    let assets = assets(false);
+    let encoder = encoder(&representation, &assets, false);
+    let buffer = encoder.init_buffer();
+    let objective = Objective::new(&representation, encoder, assets)?;
+    let constraints = Constraints::new(&representation)?;
+    ElementPlacementProblem::new(representation, constraints, objective, buffer)
+}